@@ -1,16 +1,30 @@
 #version 450
 
-layout(location = 0) in vec3 fragColor;
-layout(location = 1) in vec2 fragTexCoord;
+struct DrawData {
+    mat4 model;
+    vec4 baseColorFactor;
+    float opacity;
+    uint firstInstance;
+    uint firstJoint;
+    vec4 sphere;
+    vec4 aabbMin;
+    vec4 aabbMax;
+};
 
-layout(location = 0) out vec4 outColor;
-layout(binding = 1) uniform sampler2D texSampler;
+layout(std430, set = 2, binding = 0) readonly buffer Draws {
+    DrawData draws[];
+};
 
-layout(push_constant) uniform PushConstants {
-    layout(offset = 64) float opacity;
-} pcs;
+layout(location = 0) in vec4 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) flat in uint fragDrawIndex;
 
+layout(location = 0) out vec4 outColor;
+layout(set = 1, binding = 0) uniform texture2D baseColorTexture;
+layout(set = 1, binding = 1) uniform sampler baseColorSampler;
 
 void main() {
-    outColor = vec4(texture(texSampler, fragTexCoord).rgb, pcs.opacity);
+    DrawData draw = draws[fragDrawIndex];
+    vec4 baseColor = texture(sampler2D(baseColorTexture, baseColorSampler), fragTexCoord) * draw.baseColorFactor * fragColor;
+    outColor = vec4(baseColor.rgb, baseColor.a * draw.opacity);
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+struct DrawData {
+    mat4 model;
+    vec4 baseColorFactor;
+    float opacity;
+    uint firstInstance;
+    uint firstJoint;
+    vec4 sphere;
+    vec4 aabbMin;
+    vec4 aabbMax;
+};
+
+layout(std430, set = 2, binding = 0) readonly buffer Draws {
+    DrawData draws[];
+};
+
+layout(std430, set = 2, binding = 1) readonly buffer Joints {
+    mat4 joints[];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec4 inColor;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inNormal;
+layout(location = 4) in vec4 inTangent;
+layout(location = 5) in vec2 inTexCoord1;
+layout(location = 6) in vec4 in1InstanceModel;
+layout(location = 7) in vec4 in2InstanceModel;
+layout(location = 8) in vec4 in3InstanceModel;
+layout(location = 9) in vec4 in4InstanceModel;
+layout(location = 10) in uint inDrawIndex;
+layout(location = 11) in uvec4 inJoints;
+layout(location = 12) in vec4 inWeights;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) flat out uint fragDrawIndex;
+
+void main() {
+    mat4 inInstanceModel = mat4(in1InstanceModel, in2InstanceModel, in3InstanceModel, in4InstanceModel);
+    uint firstJoint = draws[inDrawIndex].firstJoint;
+    mat4 skin = inWeights.x * joints[firstJoint + inJoints.x]
+        + inWeights.y * joints[firstJoint + inJoints.y]
+        + inWeights.z * joints[firstJoint + inJoints.z]
+        + inWeights.w * joints[firstJoint + inJoints.w];
+    gl_Position = ubo.proj * ubo.view * inInstanceModel * draws[inDrawIndex].model * skin * vec4(inPosition, 1.0);
+    fragColor = inColor;
+    fragTexCoord = inTexCoord;
+    fragDrawIndex = inDrawIndex;
+}
\ No newline at end of file
@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform texture2D sceneTexture;
+layout(set = 0, binding = 1) uniform sampler sceneSampler;
+
+void main() {
+    outColor = texture(sampler2D(sceneTexture, sceneSampler), fragTexCoord);
+}
@@ -0,0 +1,44 @@
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+
+const SHADERS_PATH: &str = "assets/shaders";
+const GLSLC: &str = if cfg!(windows) { "glslc.exe" } else { "glslc" };
+
+/// With the `compile-shaders` feature, compiles every shader of `assets/shaders` with glslc to
+/// `OUT_DIR/<file name>.spv`, e.g. `shader.vert.spv`, which the default `Vfs` mounts over `lib`.
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    if std::env::var_os("CARGO_FEATURE_COMPILE_SHADERS").is_none() {
+        return Ok(());
+    }
+
+    println!("cargo:rerun-if-changed={}", SHADERS_PATH);
+
+    let out_dir = std::env::var("OUT_DIR")?;
+    let mut errors = String::new();
+    for file in std::fs::read_dir(SHADERS_PATH)? {
+        let path = file?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+
+        let output = Command::new(GLSLC)
+            .arg(&path)
+            .arg("-o")
+            .arg(Path::new(&out_dir).join(format!("{}.spv", name)))
+            .output()
+            .map_err(|e| format!("Failed to execute {}: {}", GLSLC, e))?;
+
+        if !output.status.success() {
+            errors.push_str(&String::from_utf8_lossy(&output.stderr));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(format!("Failed to compile shaders:\n{}", errors).into());
+    }
+
+    Ok(())
+}
@@ -1,12 +1,15 @@
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
-use cgmath::{Deg, Quaternion, Rotation3, Vector3};
+use cgmath::{point3, Deg, Quaternion, Rotation3, Vector3};
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, Event, VirtualKeyCode, WindowEvent},
 };
-use yuumi::{App, CameraController, CameraProjectionKind};
+use yuumi::{
+    App, CameraController, CameraProjectionKind, MaterialDesc, PresentMode, RendererSettings,
+    Transform,
+};
 
 fn main() -> Result<()> {
     // Window
@@ -36,7 +39,35 @@ fn main() -> Result<()> {
     window.set_cursor_visible(cursor_visible);
 
     // App
-    let app = Arc::new(Mutex::new(App::new_windowed(&window)?));
+    let settings = RendererSettings::load_or_default("settings.toml")?;
+    let mut app = App::new_windowed_with_settings(&window, settings)?;
+
+    // Scene
+    app.load_model("cube", "models/cube")?;
+    app.load_model("viking_room", "models/viking_room")?;
+    for name in ["cube", "viking_room"] {
+        app.clear_instances(name)?;
+        for (y, z) in [(-1.25, 1.0), (1.25, 1.0), (-1.25, -1.0), (1.25, -1.0)] {
+            app.add_instance(name, Transform::from_position(point3(0.0, y, z)))?;
+        }
+        app.spawn_model(name)?;
+    }
+    // The scene shows up once the textures are streamed.
+    app.set_loading_screen(true);
+    app.stream_texture("cube", "textures/cube")?;
+    app.stream_texture("viking_room", "textures/viking_room")?;
+    for name in ["cube", "viking_room"] {
+        app.create_material(
+            name,
+            MaterialDesc {
+                base_color_texture: Some(name.to_owned()),
+                ..Default::default()
+            },
+        )?;
+        app.set_model_material(name, name)?;
+    }
+
+    let app = Arc::new(Mutex::new(app));
 
     // Assets
     let mut camera_controller = CameraController {
@@ -138,6 +169,30 @@ fn main() -> Result<()> {
                                         _ => 4,
                                     };
                             }
+                            Some(
+                                key @ (VirtualKeyCode::F1
+                                | VirtualKeyCode::F2
+                                | VirtualKeyCode::F3
+                                | VirtualKeyCode::F4),
+                            ) => {
+                                let present_mode = match key {
+                                    VirtualKeyCode::F1 => PresentMode::Vsync,
+                                    VirtualKeyCode::F2 => PresentMode::Adaptive,
+                                    VirtualKeyCode::F3 => PresentMode::Mailbox,
+                                    _ => PresentMode::Immediate,
+                                };
+                                let app = &mut app_arc.lock().expect("Failed to lock app");
+                                app.set_present_mode(present_mode)
+                                    .expect("Failed to set present mode");
+                            }
+                            Some(VirtualKeyCode::F5) => {
+                                let app = &mut app_arc.lock().expect("Failed to lock app");
+                                let limit = match app.renderer_settings().frame_rate_limit {
+                                    Some(_) => None,
+                                    None => Some(60),
+                                };
+                                app.set_frame_rate_limit(limit);
+                            }
                             Some(VirtualKeyCode::Escape) => {
                                 let app = &mut app_arc.lock().expect("Failed to lock app");
                                 app.destroy();
@@ -158,6 +213,19 @@ fn main() -> Result<()> {
         *control_flow = winit::event_loop::ControlFlow::Poll;
         match event {
             Event::MainEventsCleared if app.rendering => {
+                // Spin the scene around Z
+                let rotation = Quaternion::from_angle_z(Deg(app.uptime().as_secs_f32() * 5.0));
+                for name in ["cube", "viking_room"] {
+                    let transform = Transform {
+                        rotation,
+                        ..app
+                            .model_transform(name)
+                            .expect("Failed to get model transform")
+                    };
+                    app.set_model_transform(name, transform)
+                        .expect("Failed to set model transform");
+                }
+
                 unsafe { app.render(&window) }.expect("Failed to render");
             }
             Event::WindowEvent {
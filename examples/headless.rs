@@ -0,0 +1,24 @@
+use anyhow::Result;
+use yuumi::App;
+
+fn main() -> Result<()> {
+    let (width, height) = (800, 600);
+
+    // App
+    let mut app = App::new_headless(width, height)?;
+
+    // Render a frame and read it back
+    app.render_headless()?;
+    let pixels = app.read_pixels()?;
+
+    // Save the frame
+    let file = std::fs::File::create("headless.png")?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+
+    app.destroy();
+
+    Ok(())
+}
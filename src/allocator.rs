@@ -0,0 +1,346 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Result;
+
+use vulkanalia::prelude::v1_0::*;
+
+/// Size of the device memory blocks resources are sub-allocated from.
+const BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// A range of a device memory block, bound to a single buffer or image.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Allocation {
+    pub(crate) memory: vk::DeviceMemory,
+    pub(crate) offset: vk::DeviceSize,
+    pub(crate) size: vk::DeviceSize,
+    /// Host address of the allocation when its memory is host visible, 0 otherwise.
+    pub(crate) mapped: usize,
+    block: usize,
+}
+
+impl Allocation {
+    pub(crate) fn mapped_ptr<T>(&self) -> *mut T {
+        debug_assert!(self.mapped != 0, "Allocation is not host visible");
+        self.mapped as *mut T
+    }
+}
+
+/// Memory of a heap used by the renderer, see [`App::memory_usage`](crate::App::memory_usage).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapUsage {
+    pub heap: u32,
+    pub device_local: bool,
+    pub size: u64,
+    /// Bytes of device memory allocated from the heap.
+    pub allocated: u64,
+    /// Bytes of the allocated memory bound to resources.
+    pub used: u64,
+    pub blocks: u32,
+}
+
+#[derive(Debug)]
+struct Block {
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    memory_type: u32,
+    /// Buffers and images live in separate blocks, so `bufferImageGranularity` never matters.
+    linear: bool,
+    /// Holds a single resource too large to share a block, freed with it.
+    dedicated: bool,
+    mapped: usize,
+    free: FreeList,
+}
+
+impl Block {
+    fn used(&self) -> vk::DeviceSize {
+        self.size - self.free.free_size()
+    }
+}
+
+/// Free ranges as `(offset, size)`, sorted by offset and never adjacent.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FreeList {
+    ranges: Vec<(u64, u64)>,
+}
+
+impl FreeList {
+    pub(crate) fn free_size(&self) -> u64 {
+        self.ranges.iter().map(|(_, size)| size).sum()
+    }
+
+    /// First fit, returns the aligned offset.
+    pub(crate) fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let (index, offset) = self
+            .ranges
+            .iter()
+            .enumerate()
+            .find_map(|(i, (start, len))| {
+                let offset = align_up(*start, alignment);
+                (offset + size <= start + len).then_some((i, offset))
+            })?;
+
+        let (start, len) = self.ranges.remove(index);
+        let end = start + len;
+        if offset + size < end {
+            self.ranges
+                .insert(index, (offset + size, end - offset - size));
+        }
+        // The padding left by the alignment stays free.
+        if offset > start {
+            self.ranges.insert(index, (start, offset - start));
+        }
+
+        Some(offset)
+    }
+
+    pub(crate) fn free(&mut self, offset: u64, size: u64) {
+        let index = self.ranges.partition_point(|(start, _)| *start < offset);
+        self.ranges.insert(index, (offset, size));
+
+        // Merge with the next range, then with the previous one.
+        if let Some(&(next_start, next_len)) = self.ranges.get(index + 1) {
+            if offset + size == next_start {
+                self.ranges[index].1 += next_len;
+                self.ranges.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let (previous_start, previous_len) = self.ranges[index - 1];
+            if previous_start + previous_len == offset {
+                self.ranges[index - 1].1 += self.ranges[index].1;
+                self.ranges.remove(index);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct AllocatorState {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    blocks: Vec<Option<Block>>,
+}
+
+/// Takes large device memory blocks per memory type and sub-allocates them, so the number of
+/// allocations stays far below `maxMemoryAllocationCount`. Cheap to clone, clones share the
+/// blocks.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Allocator {
+    state: Arc<Mutex<AllocatorState>>,
+}
+
+impl Allocator {
+    pub(crate) fn set_memory_properties(&self, properties: vk::PhysicalDeviceMemoryProperties) {
+        self.lock().memory_properties = properties;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AllocatorState> {
+        self.state.lock().expect("Failed to lock allocator")
+    }
+
+    /// Smaller blocks on small heaps, like the host visible part of the VRAM.
+    fn block_size(state: &AllocatorState, memory_type: u32) -> vk::DeviceSize {
+        let heap = state.memory_properties.memory_types[memory_type as usize].heap_index;
+        let heap_size = state.memory_properties.memory_heaps[heap as usize].size;
+        BLOCK_SIZE.min(heap_size / 8).max(1)
+    }
+
+    /// `linear` is true for buffers and linear images, false for optimal images.
+    pub(crate) unsafe fn allocate(
+        &self,
+        device: &Device,
+        requirements: vk::MemoryRequirements,
+        memory_type: u32,
+        linear: bool,
+    ) -> Result<Allocation> {
+        let mut state = self.lock();
+
+        let existing = state
+            .blocks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, b)| b.as_mut().map(|b| (i, b)))
+            .filter(|(_, b)| b.memory_type == memory_type && b.linear == linear && !b.dedicated)
+            .find_map(|(i, b)| {
+                b.free
+                    .allocate(requirements.size, requirements.alignment)
+                    .map(|offset| (i, offset, b.memory, b.mapped))
+            });
+
+        if let Some((block, offset, memory, mapped)) = existing {
+            return Ok(Allocation {
+                memory,
+                offset,
+                size: requirements.size,
+                mapped: if mapped == 0 {
+                    0
+                } else {
+                    mapped + offset as usize
+                },
+                block,
+            });
+        }
+
+        let block_size = Self::block_size(&state, memory_type);
+        let dedicated = requirements.size > block_size / 2;
+        let size = if dedicated {
+            requirements.size
+        } else {
+            block_size
+        };
+
+        let info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type);
+        let memory = device.allocate_memory(&info, None)?;
+
+        // Host visible blocks stay mapped, a memory object can only be mapped once.
+        let host_visible = state.memory_properties.memory_types[memory_type as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let mapped = if host_visible {
+            device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())? as usize
+        } else {
+            0
+        };
+
+        let mut block = Block {
+            memory,
+            size,
+            memory_type,
+            linear,
+            dedicated,
+            mapped,
+            free: FreeList::default(),
+        };
+        block.free.free(0, size);
+        let offset = block
+            .free
+            .allocate(requirements.size, requirements.alignment)
+            .expect("Fresh block fits the allocation");
+
+        let index = match state.blocks.iter().position(|b| b.is_none()) {
+            Some(index) => index,
+            None => {
+                state.blocks.push(None);
+                state.blocks.len() - 1
+            }
+        };
+        state.blocks[index] = Some(block);
+
+        Ok(Allocation {
+            memory,
+            offset,
+            size: requirements.size,
+            mapped: if mapped == 0 {
+                0
+            } else {
+                mapped + offset as usize
+            },
+            block: index,
+        })
+    }
+
+    /// Returns the range to its block, the resource bound to it must be destroyed.
+    pub(crate) unsafe fn free(&self, device: &Device, allocation: Allocation) {
+        if allocation.memory.is_null() {
+            return;
+        }
+
+        let mut state = self.lock();
+        let slot = &mut state.blocks[allocation.block];
+        let block = slot.as_mut().expect("Allocation block is alive");
+        block.free.free(allocation.offset, allocation.size);
+
+        if block.dedicated {
+            device.free_memory(block.memory, None);
+            *slot = None;
+        }
+    }
+
+    pub(crate) fn heap_usage(&self) -> Vec<HeapUsage> {
+        let state = self.lock();
+        let properties = &state.memory_properties;
+
+        let mut usage = (0..properties.memory_heap_count)
+            .map(|heap| {
+                let memory_heap = properties.memory_heaps[heap as usize];
+                HeapUsage {
+                    heap,
+                    device_local: memory_heap
+                        .flags
+                        .contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                    size: memory_heap.size,
+                    ..Default::default()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for block in state.blocks.iter().flatten() {
+            let heap = properties.memory_types[block.memory_type as usize].heap_index;
+            let heap_usage = &mut usage[heap as usize];
+            heap_usage.allocated += block.size;
+            heap_usage.used += block.used();
+            heap_usage.blocks += 1;
+        }
+
+        usage
+    }
+
+    /// Frees every block, all the resources must be destroyed.
+    pub(crate) unsafe fn destroy(&self, device: &Device) {
+        let mut state = self.lock();
+        for block in state.blocks.drain(..).flatten() {
+            device.free_memory(block.memory, None);
+        }
+    }
+}
+
+fn align_up(offset: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    offset.div_ceil(alignment.max(1)) * alignment.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free_list(ranges: &[(u64, u64)]) -> FreeList {
+        let mut free_list = FreeList::default();
+        for (offset, size) in ranges {
+            free_list.free(*offset, *size);
+        }
+        free_list
+    }
+
+    #[test]
+    fn allocates_from_the_first_range_that_fits() {
+        let mut free_list = free_list(&[(0, 16), (32, 64)]);
+
+        assert_eq!(free_list.allocate(32, 1), Some(32));
+        assert_eq!(free_list.allocate(16, 1), Some(0));
+        assert_eq!(free_list.ranges, vec![(64, 32)]);
+        assert_eq!(free_list.allocate(64, 1), None);
+    }
+
+    #[test]
+    fn keeps_the_alignment_padding_free() {
+        let mut free_list = free_list(&[(4, 60)]);
+
+        assert_eq!(free_list.allocate(16, 16), Some(16));
+        assert_eq!(free_list.ranges, vec![(4, 12), (32, 32)]);
+        assert_eq!(free_list.free_size(), 44);
+    }
+
+    #[test]
+    fn merges_freed_ranges_with_their_neighbours() {
+        let mut free_list = free_list(&[(0, 64)]);
+        let offsets = [0, 1, 2].map(|_| free_list.allocate(16, 1).unwrap());
+        assert_eq!(offsets, [0, 16, 32]);
+
+        free_list.free(offsets[0], 16);
+        free_list.free(offsets[2], 16);
+        assert_eq!(free_list.ranges, vec![(0, 16), (32, 32)]);
+
+        free_list.free(offsets[1], 16);
+        assert_eq!(free_list.ranges, vec![(0, 64)]);
+    }
+}
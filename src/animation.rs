@@ -0,0 +1,215 @@
+use std::ops::{Add, Mul};
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, Vector3, VectorSpace};
+use serde::{Deserialize, Serialize};
+
+/// Translation, rotation and scale of a joint relative to its parent.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct JointTransform {
+    pub(crate) translation: Vector3<f32>,
+    pub(crate) rotation: Quaternion<f32>,
+    pub(crate) scale: Vector3<f32>,
+}
+
+impl JointTransform {
+    fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Joint {
+    /// Always before the joint in the skeleton.
+    pub(crate) parent: Option<usize>,
+    /// Where a joint without a parent joint sits in the model, from the nodes above it.
+    pub(crate) root: Matrix4<f32>,
+    /// Brings the vertices of the model in the space of the joint at bind time.
+    pub(crate) inverse_bind: Matrix4<f32>,
+    pub(crate) rest: JointTransform,
+}
+
+/// The joints a skinned model is posed with, parents first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Skeleton {
+    pub(crate) joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    /// The matrices moving the vertices from the bind pose to `pose`, one per joint.
+    fn joint_matrices(&self, pose: &[JointTransform], matrices: &mut Vec<Matrix4<f32>>) {
+        let mut worlds = Vec::with_capacity(self.joints.len());
+        for (joint, transform) in self.joints.iter().zip(pose) {
+            let parent = joint.parent.map_or(joint.root, |parent| worlds[parent]);
+            worlds.push(parent * transform.matrix());
+        }
+
+        matrices.clear();
+        matrices.extend(
+            worlds
+                .iter()
+                .zip(&self.joints)
+                .map(|(world, joint)| world * joint.inverse_bind),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Interpolation {
+    Step,
+    Linear,
+    /// Each key has an in tangent, a value and an out tangent.
+    CubicSpline,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum ChannelValues {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+/// The keys of one property of one joint.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Channel {
+    pub(crate) joint: usize,
+    pub(crate) interpolation: Interpolation,
+    /// In seconds, increasing.
+    pub(crate) times: Vec<f32>,
+    pub(crate) values: ChannelValues,
+}
+
+impl Channel {
+    /// Holds the first and last values outside of the keys.
+    fn sample<T: Keyframe>(&self, values: &[T], time: f32) -> T {
+        let value = |key: usize| match self.interpolation {
+            Interpolation::CubicSpline => values[key * 3 + 1],
+            _ => values[key],
+        };
+
+        let next = self.times.partition_point(|t| *t <= time);
+        if next == 0 {
+            return value(0);
+        }
+        if next == self.times.len() {
+            return value(next - 1);
+        }
+
+        let previous = next - 1;
+        let duration = self.times[next] - self.times[previous];
+        let factor = (time - self.times[previous]) / duration;
+
+        match self.interpolation {
+            Interpolation::Step => value(previous),
+            Interpolation::Linear => T::interpolate(value(previous), value(next), factor),
+            Interpolation::CubicSpline => {
+                let (factor2, factor3) = (factor * factor, factor * factor * factor);
+                value(previous) * (2.0 * factor3 - 3.0 * factor2 + 1.0)
+                    + values[previous * 3 + 2] * ((factor3 - 2.0 * factor2 + factor) * duration)
+                    + value(next) * (-2.0 * factor3 + 3.0 * factor2)
+                    + values[next * 3] * ((factor3 - factor2) * duration)
+            }
+        }
+    }
+}
+
+trait Keyframe: Copy + Add<Output = Self> + Mul<f32, Output = Self> {
+    fn interpolate(from: Self, to: Self, factor: f32) -> Self;
+}
+
+impl Keyframe for Vector3<f32> {
+    fn interpolate(from: Self, to: Self, factor: f32) -> Self {
+        from.lerp(to, factor)
+    }
+}
+
+impl Keyframe for Quaternion<f32> {
+    fn interpolate(from: Self, to: Self, factor: f32) -> Self {
+        from.slerp(to, factor)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct AnimationClip {
+    pub(crate) name: String,
+    /// In seconds, the time of the last key.
+    pub(crate) duration: f32,
+    pub(crate) channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Overwrites the joints the clip animates with their transform at `time`.
+    fn sample(&self, time: f32, pose: &mut [JointTransform]) {
+        for channel in &self.channels {
+            let transform = &mut pose[channel.joint];
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    transform.translation = channel.sample(values, time)
+                }
+                ChannelValues::Rotation(values) => {
+                    transform.rotation = channel.sample(values, time).normalize()
+                }
+                ChannelValues::Scale(values) => transform.scale = channel.sample(values, time),
+            }
+        }
+    }
+}
+
+/// Plays a clip on a spawned skinned model, see [`App::play_animation`](crate::App::play_animation).
+#[derive(Debug)]
+pub(crate) struct AnimationPlayer {
+    clip: Option<usize>,
+    /// In seconds since the clip started, scaled by the speed.
+    time: f32,
+    looping: bool,
+    pub(crate) speed: f32,
+    /// The pose of the last update, the rest pose without a clip.
+    pub(crate) joint_matrices: Vec<Matrix4<f32>>,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            clip: None,
+            time: 0.0,
+            looping: false,
+            speed: 1.0,
+            joint_matrices: Vec::new(),
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub(crate) fn play(&mut self, clip: usize, looping: bool) {
+        self.clip = Some(clip);
+        self.time = 0.0;
+        self.looping = looping;
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.clip = None;
+    }
+
+    /// Advances the clip by `delta` seconds and poses the skeleton. A clip that does not loop
+    /// holds its last pose once over.
+    pub(crate) fn update(&mut self, delta: f32, skeleton: &Skeleton, clips: &[AnimationClip]) {
+        let mut pose = skeleton
+            .joints
+            .iter()
+            .map(|joint| joint.rest)
+            .collect::<Vec<_>>();
+
+        if let Some(clip) = self.clip.and_then(|clip| clips.get(clip)) {
+            self.time += delta * self.speed;
+            let time = if self.looping && clip.duration > 0.0 {
+                self.time.rem_euclid(clip.duration)
+            } else {
+                self.time.clamp(0.0, clip.duration)
+            };
+            clip.sample(time, &mut pose);
+        }
+
+        skeleton.joint_matrices(&pose, &mut self.joint_matrices);
+    }
+}
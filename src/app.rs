@@ -1,33 +1,68 @@
+use crate::allocator::{Allocation, Allocator, HeapUsage};
 use crate::assets::Assets;
 use crate::camera::Camera;
-use crate::command_buffer::{create_command_buffers, create_command_pools};
-use crate::depth_object::create_depth_objects;
-use crate::descriptor_layout::create_descriptor_set_layout;
-use crate::descriptor_pool::{create_descriptor_pool, create_descriptor_sets};
-use crate::framebuffer::create_framebuffers;
+use crate::command_buffer::{
+    create_command_buffers, create_command_pools, create_image_command_pools,
+};
+use crate::culling::{create_cull_pipeline, record_cull_pass, CullData};
+use crate::deletion_queue::{DeletionQueue, Retired};
+use crate::depth_pyramid::{create_depth_pyramid_pipelines, record_depth_pyramid, DepthPyramid};
+use crate::descriptor_layout::{
+    create_cull_set_layout, create_depth_pyramid_set_layout, create_descriptor_set_layout,
+    create_draw_set_layout, create_material_set_layout,
+};
+use crate::descriptor_pool::{
+    create_descriptor_pool, create_descriptor_sets, create_draw_descriptor_sets,
+    create_material_descriptor_pool,
+};
+use crate::geometry_arena::GeometryArena;
+use crate::handle::{Handle, LoadState};
 use crate::image_view::create_swapchain_image_views;
+use crate::indirect::{DrawBuffers, SceneDraws};
 use crate::instance::create_instance;
+use crate::instances::InstanceId;
+use crate::loading_screen::record_loading_screen;
 use crate::logical_device::create_logical_device;
-use crate::metrics::Metrics;
-use crate::msaa::create_color_objects;
-use crate::physical_device::pick_physical_device;
+use crate::material::MaterialDesc;
+use crate::metrics::{DrawCounts, Metrics};
+use crate::model::Model;
+use crate::offscreen::{
+    create_offscreen_targets, destroy_offscreen_targets, read_offscreen_image,
+};
+use crate::physical_device::{pick_physical_device, PhysicalDeviceCandidate};
 use crate::pipeline::create_pipeline;
-use crate::render_pass::create_render_pass;
+use crate::render_graph::RenderGraph;
+use crate::render_pass::{create_render_graph, create_render_targets, SCENE_PASS, UPSCALE_PASS};
+use crate::settings::{apply_settings, PresentMode, RendererSettings};
+use crate::streaming::{AssetHandle, Streamed, Streamer};
 use crate::swapchain::create_swapchain;
 use crate::sync_object::create_sync_objects;
-use crate::texture_sampler::create_texture_sampler;
+use crate::texture::Texture;
+use crate::transform::Transform;
 use crate::uniform_buffer::{create_uniform_buffers, UniformBufferObject};
+use crate::upload::{
+    acquire_released, create_upload_context, destroy_upload_context, flush_uploads, wait_uploads,
+    Release, UploadContext,
+};
+use crate::upscale::{create_upscale_descriptor_set, create_upscale_pipeline, record_upscale_pass};
+use crate::vertex::{DrawInstance, InstanceData};
+use crate::vfs::Vfs;
 use anyhow::{anyhow, Result};
-use cgmath::SquareMatrix;
+use cgmath::{Matrix4, SquareMatrix};
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 use vulkanalia::loader::{LibloadingLoader, LIBRARY};
 use vulkanalia::prelude::v1_0::*;
-use vulkanalia::vk::{ExtDebugUtilsExtension, KhrSurfaceExtension, KhrSwapchainExtension};
+// The prelude's `Handle` is shadowed by the asset one.
+use vulkanalia::vk::{
+    ExtDebugUtilsExtension, Handle as _, KhrSurfaceExtension, KhrSwapchainExtension,
+};
 use vulkanalia::window::create_surface;
 use vulkanalia::Device;
 use winit::window::Window;
 
-pub(crate) const MAX_FRAMES_IN_FLIGHT: usize = 2;
+/// Upper bound of the frames in flight setting.
+pub(crate) const MAX_FRAMES_IN_FLIGHT: usize = 3;
 pub(crate) const VALIDATION_ENABLED: bool = cfg!(debug_assertions);
 pub(crate) const VALIDATION_LAYER: vk::ExtensionName =
     vk::ExtensionName::from_bytes(b"VK_LAYER_KHRONOS_validation");
@@ -43,24 +78,114 @@ pub struct App {
     pub resized: bool,
     pub(crate) metrics: Metrics,
     pub assets: Arc<RwLock<Assets>>,
-    // TEMP
-    sampler: vk::Sampler,
+    last_rendered_image: Option<usize>,
+    physical_devices: Vec<PhysicalDeviceCandidate>,
+    settings: RendererSettings,
+    /// Recreate the swapchain before the next frame, for settings it was created with.
+    swapchain_outdated: bool,
+    streamer: Streamer,
+    scene_draws: SceneDraws,
+    draw_buffers: [DrawBuffers; MAX_FRAMES_IN_FLIGHT],
+    /// Draw the loading screen instead of the scene while assets stream.
+    loading_screen: bool,
+    /// Progress the loading screen shows this frame, `None` when the scene is drawn.
+    loading_progress: Option<f32>,
+}
+
+/// Where the frames of an [`App`] end up.
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Window(&'a Window),
+    Offscreen { width: u32, height: u32 },
 }
 
 impl App {
     pub fn new_windowed(window: &Window) -> Result<Self> {
-        pretty_env_logger::init();
+        Self::new(
+            Target::Window(window),
+            RendererSettings::default(),
+            Vfs::default(),
+        )
+    }
 
-        #[cfg(debug_assertions)]
-        crate::shader::compile_shaders()?;
+    pub fn new_windowed_with_settings(window: &Window, settings: RendererSettings) -> Result<Self> {
+        Self::new(Target::Window(window), settings, Vfs::default())
+    }
+
+    /// Loads the shaders and assets through `vfs` instead of the repository layout.
+    pub fn new_windowed_with_vfs(
+        window: &Window,
+        settings: RendererSettings,
+        vfs: Vfs,
+    ) -> Result<Self> {
+        Self::new(Target::Window(window), settings, vfs)
+    }
+
+    /// Creates an app without a window or surface, rendering into offscreen images that can be
+    /// read back with [`App::read_pixels`].
+    pub fn new_headless(width: u32, height: u32) -> Result<Self> {
+        Self::new(
+            Target::Offscreen { width, height },
+            RendererSettings::default(),
+            Vfs::default(),
+        )
+    }
+
+    pub fn new_headless_with_settings(
+        width: u32,
+        height: u32,
+        settings: RendererSettings,
+    ) -> Result<Self> {
+        Self::new(
+            Target::Offscreen { width, height },
+            settings,
+            Vfs::default(),
+        )
+    }
+
+    /// Loads the shaders and assets through `vfs` instead of the repository layout.
+    pub fn new_headless_with_vfs(
+        width: u32,
+        height: u32,
+        settings: RendererSettings,
+        vfs: Vfs,
+    ) -> Result<Self> {
+        Self::new(Target::Offscreen { width, height }, settings, vfs)
+    }
+
+    fn new(target: Target, settings: RendererSettings, vfs: Vfs) -> Result<Self> {
+        let _ = pretty_env_logger::try_init();
+
+        if let Target::Offscreen { width, height } = target {
+            if width == 0 || height == 0 {
+                return Err(anyhow!(
+                    "Headless extent must not be empty: {}x{}",
+                    width,
+                    height
+                ));
+            }
+        }
+
+        let window = match target {
+            Target::Window(window) => Some(window),
+            Target::Offscreen { .. } => None,
+        };
 
         unsafe {
             let loader = LibloadingLoader::new(LIBRARY)?;
             let _entry = Entry::new(loader).map_err(|b| anyhow!("{}", b))?;
-            let mut data: AppData = AppData::default();
-            let instance = create_instance(&window, &_entry, &mut data)?;
-            data.surface = create_surface(&instance, &window, &window)?;
-            pick_physical_device(&instance, &mut data)?;
+            let mut data = AppData {
+                headless: window.is_none(),
+                vfs: Arc::new(vfs),
+                ..Default::default()
+            };
+            let instance = create_instance(window, &_entry, &mut data)?;
+            if let Some(window) = window {
+                data.surface = create_surface(&instance, &window, &window)?;
+            }
+            let physical_devices =
+                pick_physical_device(&instance, &mut data, &settings.physical_device)?;
+            apply_settings(&mut data, &settings);
             let device = create_logical_device(&instance, &mut data)?;
             let mut app = Self {
                 _entry,
@@ -72,41 +197,61 @@ impl App {
                 resized: false,
                 metrics: Metrics::default(),
                 assets: Arc::new(RwLock::new(Assets::default())),
-                sampler: vk::Sampler::null(),
+                last_rendered_image: None,
+                physical_devices,
+                settings,
+                swapchain_outdated: false,
+                streamer: Streamer::default(),
+                scene_draws: SceneDraws::default(),
+                draw_buffers: Default::default(),
+                loading_screen: false,
+                loading_progress: None,
             };
 
-            create_swapchain(&window, &app.instance, &app.device, &mut app.data)?;
-            create_swapchain_image_views(&app.device, &mut app.data)?;
-            create_render_pass(&app.instance, &app.device, &mut app.data)?;
+            match target {
+                Target::Window(window) => {
+                    create_swapchain(window, &app.instance, &app.device, &mut app.data)?;
+                    create_swapchain_image_views(&app.device, &mut app.data)?;
+                }
+                Target::Offscreen { width, height } => {
+                    create_offscreen_targets(
+                        &app.instance,
+                        &app.device,
+                        &mut app.data,
+                        width,
+                        height,
+                    )?;
+                }
+            }
             create_descriptor_set_layout(&app.device, &mut app.data)?; // TODO ON INIT ONLY
+            create_material_set_layout(&app.device, &mut app.data)?;
+            create_material_descriptor_pool(&app.device, &mut app.data)?;
+            create_draw_set_layout(&app.device, &mut app.data)?;
+            create_draw_descriptor_sets(&app.device, &mut app.data)?;
+            create_upscale_descriptor_set(&app.device, &mut app.data)?;
+            create_cull_set_layout(&app.device, &mut app.data)?;
+            create_depth_pyramid_set_layout(&app.device, &mut app.data)?;
+            create_depth_pyramid_pipelines(&app.device, &mut app.data)?;
+            create_cull_pipeline(&app.device, &mut app.data)?;
+            create_render_graph(&app.instance, &app.device, &mut app.data)?;
             create_pipeline(&app.device, &mut app.data)?;
+            create_upscale_pipeline(&app.device, &mut app.data)?;
             create_command_pools(&app.instance, &app.device, &mut app.data)?; // TODO ON INIT ONLY
-            create_color_objects(&app.instance, &app.device, &mut app.data)?;
-            create_depth_objects(&app.instance, &app.device, &mut app.data)?;
-            create_framebuffers(&app.device, &mut app.data)?;
+            create_upload_context(&app.instance, &app.device, &mut app.data)?;
+            app.streamer = Streamer::start(&app.instance, &app.device, &app.data)?;
 
             app.init_assets()?;
 
-            {
-                let assets = app.assets.read().expect("Failed to lock assets");
-
-                // Load textures
-                let viking_room_texture = assets
-                    .textures
-                    .get("viking_room")
-                    .expect("Texture not found");
-                let cube_texture = assets.textures.get("cube").expect("Texture not found");
-
-                let textures = &[cube_texture, viking_room_texture];
-
-                create_uniform_buffers(&app.instance, &app.device, &mut app.data)?;
-                create_descriptor_pool(&app.device, &mut app.data)?;
-                create_descriptor_sets(&app.device, &mut app.data, textures, &app.sampler)?;
-            }
+            create_uniform_buffers(&app.instance, &app.device, &mut app.data)?;
+            create_descriptor_pool(&app.device, &mut app.data)?;
+            create_descriptor_sets(&app.device, &mut app.data)?;
             create_command_buffers(&app.device, &mut app.data)?;
 
             create_sync_objects(&app.device, &mut app.data)?; // TODO ON INIT ONLY
 
+            app.metrics
+                .cycle
+                .set_frame_rate_limit(app.settings.frame_rate_limit);
             app.metrics.cycle.start();
 
             app.rendering = true;
@@ -115,40 +260,394 @@ impl App {
         }
     }
 
-    pub unsafe fn init_assets(&mut self) -> Result<()> {
-        {
-            let mut assets = self.assets.write().expect("Failed to lock assets");
+    unsafe fn init_assets(&mut self) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
 
-            assets.cameras.insert("main".to_owned(), Camera::default());
-            assets.active_camera = "main".to_owned();
+        assets.cameras.insert("main".to_owned(), Camera::default());
+        assets.active_camera = "main".to_owned();
 
-            // Prepare models
-            assets.load_model("cube", &mut self.instance, &mut self.device, &mut self.data)?;
-            assets.load_model(
-                "viking_room",
-                &mut self.instance,
-                &mut self.device,
-                &mut self.data,
-            )?;
-            assets.active_models.push("cube".to_string());
-            assets.active_models.push("viking_room".to_string());
-
-            // Prepare textures
-            assets.load_texture(
-                "viking_room",
-                &mut self.instance,
-                &mut self.device,
-                &mut self.data,
+        assets.init_defaults(&self.instance, &self.device, &mut self.data)
+    }
+
+    /// Loads a model from a `.gltf`, `.glb`, `.obj` or `.bin` file of the [`Vfs`], e.g.
+    /// `models/cube`. Without an extension, every supported one is tried. Loading does not spawn
+    /// the model.
+    ///
+    /// The `.bin` cache written to the cache mount is rebuilt once the source is edited or the
+    /// cache format changes.
+    ///
+    /// The materials of the file are created as `<name>/material<i>` and drawn with right away,
+    /// the textures they sample as `<name>/texture<i>`. They are unloaded along with the model.
+    pub fn load_model(&mut self, name: &str, path: impl AsRef<Path>) -> Result<Handle<Model>> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.load_model(
+            name,
+            path.as_ref(),
+            &mut self.instance,
+            &mut self.device,
+            &mut self.data,
+        )
+    }
+
+    /// Despawns the model, its GPU buffers and what was imported with it are freed once the frames
+    /// in flight are done with them.
+    pub fn unload_model(&mut self, name: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.unload_model(name, &mut self.data)
+    }
+
+    /// Adds a loaded model to the scene at the origin.
+    pub fn spawn_model(&mut self, name: &str) -> Result<()> {
+        self.spawn_model_at(name, Transform::default())
+    }
+
+    /// Adds a loaded model to the scene with the given transform.
+    pub fn spawn_model_at(&mut self, name: &str, transform: Transform) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.spawn_model(name, transform)
+    }
+
+    /// Transform of a spawned model.
+    pub fn model_transform(&self, name: &str) -> Result<Transform> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        Ok(assets.active_model(name)?.transform)
+    }
+
+    /// Moves, rotates and scales a spawned model.
+    pub fn set_model_transform(&mut self, name: &str, transform: Transform) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.active_model_mut(name)?.transform = transform;
+        Ok(())
+    }
+
+    /// Sets the opacity of a spawned model, from 0.0 (invisible) to 1.0 (opaque).
+    pub fn set_model_opacity(&mut self, name: &str, opacity: f32) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.active_model_mut(name)?.opacity = opacity.clamp(0.0, 1.0);
+        Ok(())
+    }
+
+    /// Names of the animation clips of a loaded model, in file order.
+    pub fn animation_names(&self, name: &str) -> Result<Vec<String>> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        let model = assets.model(name)?;
+        Ok(model
+            .animations
+            .iter()
+            .map(|clip| clip.name.clone())
+            .collect())
+    }
+
+    /// Plays a clip of a spawned model from its start, a clip that does not loop holds its last
+    /// pose.
+    pub fn play_animation(&mut self, name: &str, clip: &str, looping: bool) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        let clip = assets
+            .model(name)?
+            .animations
+            .iter()
+            .position(|animation| animation.name == clip)
+            .ok_or_else(|| anyhow!("Animation not found: {} in {}", clip, name))?;
+        assets.active_model_mut(name)?.animation.play(clip, looping);
+        Ok(())
+    }
+
+    /// Puts a spawned model back in its rest pose.
+    pub fn stop_animation(&mut self, name: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.active_model_mut(name)?.animation.stop();
+        Ok(())
+    }
+
+    /// Scales how fast the clips of a spawned model play, 1.0 by default.
+    pub fn set_animation_speed(&mut self, name: &str, speed: f32) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.active_model_mut(name)?.animation.speed = speed;
+        Ok(())
+    }
+
+    /// Removes a model from the scene, it stays loaded.
+    pub fn despawn_model(&mut self, name: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.despawn_model(name)
+    }
+
+    /// Places another copy of a loaded model. The model's own transform is applied first, then the
+    /// instance transform. A freshly loaded model has a single instance at the origin.
+    pub fn add_instance(&mut self, name: &str, transform: Transform) -> Result<InstanceId> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        let model = assets.model_mut(name)?;
+        Ok(model.instances.add(InstanceData {
+            model_matrix: transform.matrix(),
+        }))
+    }
+
+    pub fn remove_instance(&mut self, name: &str, id: InstanceId) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.model_mut(name)?.instances.remove(id)
+    }
+
+    pub fn clear_instances(&mut self, name: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.model_mut(name)?.instances.clear();
+        Ok(())
+    }
+
+    pub fn set_instance_transform(
+        &mut self,
+        name: &str,
+        id: InstanceId,
+        transform: Transform,
+    ) -> Result<()> {
+        self.set_instance_transforms(name, [(id, transform)])
+    }
+
+    /// Moves many instances of a model at once, taking the assets lock a single time.
+    pub fn set_instance_transforms(
+        &mut self,
+        name: &str,
+        transforms: impl IntoIterator<Item = (InstanceId, Transform)>,
+    ) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        let model = assets.model_mut(name)?;
+        for (id, transform) in transforms {
+            model.instances.update(
+                id,
+                InstanceData {
+                    model_matrix: transform.matrix(),
+                },
             )?;
-            assets.load_texture("cube", &mut self.instance, &mut self.device, &mut self.data)?;
+        }
+        Ok(())
+    }
+
+    /// Instances of a loaded model, in drawing order.
+    pub fn instance_ids(&self, name: &str) -> Result<Vec<InstanceId>> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        Ok(assets.model(name)?.instances.ids().to_vec())
+    }
+
+    /// Loads a texture from a `.png` or `.bin` file of the [`Vfs`]. Without an extension, every
+    /// supported one is tried, the `.bin` cache of a `.png` is rebuilt once the `.png` is edited.
+    pub fn load_texture(&mut self, name: &str, path: impl AsRef<Path>) -> Result<Handle<Texture>> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        let handle = assets.load_texture(
+            name,
+            path.as_ref(),
+            &mut self.instance,
+            &mut self.device,
+            &mut self.data,
+        )?;
 
-            let mip_levels = 1; // TEMP
-            self.sampler = create_texture_sampler(&self.device, &mut self.data, &mip_levels)?;
+        unsafe { assets.refresh_materials(name, &self.device, &mut self.data)? };
+        Ok(handle)
+    }
+
+    /// Loads a model on the streaming thread and returns right away. It can be spawned before it
+    /// is loaded, a checkered cube is drawn in its place until it is, see [`App::model_state`].
+    pub fn stream_model(&mut self, name: &str, path: impl AsRef<Path>) -> Result<Handle<Model>> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        let handle = assets.reserve_model(name)?;
+        let asset = AssetHandle::Model(handle);
+        self.streamer
+            .request(asset, path.as_ref())
+            .inspect_err(|_| assets.cancel_streaming(asset))?;
+        Ok(handle)
+    }
+
+    /// Loads a texture on the streaming thread and returns right away. The materials using it
+    /// sample a checkerboard until it is loaded, see [`App::texture_state`].
+    pub fn stream_texture(
+        &mut self,
+        name: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<Handle<Texture>> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        let handle = assets.reserve_texture(name)?;
+        let asset = AssetHandle::Texture(handle);
+        self.streamer
+            .request(asset, path.as_ref())
+            .inspect_err(|_| assets.cancel_streaming(asset))?;
+
+        unsafe { assets.refresh_materials(name, &self.device, &mut self.data)? };
+        Ok(handle)
+    }
+
+    /// Whether a model or texture with this name is still queued or loading.
+    pub fn is_streaming(&self, name: &str) -> bool {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        let streaming = [assets.models.state_of(name), assets.textures.state_of(name)]
+            .into_iter()
+            .flatten()
+            .any(LoadState::is_pending);
+        streaming
+    }
+
+    /// The model loaded or streaming under this name.
+    pub fn model_handle(&self, name: &str) -> Option<Handle<Model>> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        assets.models.handle(name)
+    }
+
+    /// The texture loaded or streaming under this name.
+    pub fn texture_handle(&self, name: &str) -> Option<Handle<Texture>> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        assets.textures.handle(name)
+    }
+
+    /// Where a model is at, `None` once unloaded.
+    pub fn model_state(&self, model: Handle<Model>) -> Option<LoadState> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        assets.models.state(model).cloned()
+    }
+
+    /// Where a texture is at, `None` once unloaded.
+    pub fn texture_state(&self, texture: Handle<Texture>) -> Option<LoadState> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        assets.textures.state(texture).cloned()
+    }
+
+    /// Draws a progress bar instead of the scene while models or textures stream. The scene
+    /// shows up again once none is queued or loading.
+    pub fn set_loading_screen(&mut self, enabled: bool) {
+        self.loading_screen = enabled;
+    }
+
+    /// How much of what was streamed since nothing was pending is loaded or failed, from 0.0
+    /// to 1.0.
+    pub fn loading_progress(&self) -> f32 {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        assets.loading_progress()
+    }
+
+    /// Inserts the assets finished by the streaming thread. Their remaining GPU work joins the
+    /// upload batch submitted with the frame.
+    unsafe fn receive_streamed_assets(&mut self) -> Result<()> {
+        let assets = self.assets.clone();
+        while let Some(streamed) = self.streamer.try_recv() {
+            let mut assets = assets.write().expect("Failed to lock assets");
+
+            let (handle, texture) = match streamed {
+                Streamed::Started(asset) => {
+                    assets.set_streaming_state(asset, LoadState::Loading);
+                    continue;
+                }
+                Streamed::DecodedModel(handle, serialized) => {
+                    // Unloaded while streaming.
+                    if assets.models.name(handle).is_none() {
+                        continue;
+                    }
+                    if let Err(e) = assets.insert_model(
+                        handle,
+                        serialized,
+                        &self.instance,
+                        &self.device,
+                        &mut self.data,
+                    ) {
+                        assets.fail_streaming(AssetHandle::Model(handle), e);
+                    }
+                    continue;
+                }
+                Streamed::Texture(handle, texture, releases) => {
+                    if let Err(e) = self.acquire_streamed(&releases) {
+                        texture.retire(&mut self.data.deletion_queue);
+                        assets.fail_streaming(AssetHandle::Texture(handle), e);
+                        continue;
+                    }
+                    (handle, texture)
+                }
+                Streamed::DecodedTexture(handle, pixels, width, height) => {
+                    match Texture::from_pixels(
+                        &pixels,
+                        width,
+                        height,
+                        &self.instance,
+                        &self.device,
+                        &mut self.data,
+                    ) {
+                        Ok(texture) => (handle, texture),
+                        Err(e) => {
+                            assets.fail_streaming(AssetHandle::Texture(handle), e);
+                            continue;
+                        }
+                    }
+                }
+                Streamed::Failed(asset, error) => {
+                    assets.fail_streaming(asset, error);
+                    continue;
+                }
+            };
+
+            if let Err(texture) = assets.textures.fill(handle, texture) {
+                texture.retire(&mut self.data.deletion_queue);
+                continue;
+            }
+            if let Some(name) = assets.textures.name(handle).map(str::to_owned) {
+                assets.refresh_materials(&name, &self.device, &mut self.data)?;
+            }
         }
 
         Ok(())
     }
 
+    unsafe fn acquire_streamed(&mut self, releases: &[Release]) -> Result<()> {
+        let transfer_family = self
+            .streamer
+            .transfer_family
+            .ok_or_else(|| anyhow!("Missing transfer queue."))?;
+        acquire_released(
+            &self.instance,
+            &self.device,
+            &mut self.data,
+            transfer_family,
+            releases,
+        )
+    }
+
+    /// Unloads the texture, the materials using it fall back to white. Its image is freed once
+    /// the frames in flight are done with it. A texture still streaming is dropped once received.
+    pub fn unload_texture(&mut self, name: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.unload_texture(name, &mut self.data.deletion_queue)?;
+        unsafe { assets.refresh_materials(name, &self.device, &mut self.data) }
+    }
+
+    /// Creates a material that meshes can be drawn with, see [`App::set_model_material`].
+    pub fn create_material(&mut self, name: &str, desc: MaterialDesc) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        unsafe { assets.create_material(name, desc, &self.device, &mut self.data) }
+    }
+
+    /// Frees a material, the meshes using it are drawn with the default material.
+    pub fn destroy_material(&mut self, name: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.destroy_material(name, &mut self.data)
+    }
+
+    /// Draws every mesh of a loaded model with the material.
+    pub fn set_model_material(&mut self, name: &str, material: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets
+            .model_mut(name)?
+            .meshes
+            .iter_mut()
+            .for_each(|mesh| mesh.material = Some(material.to_owned()));
+        Ok(())
+    }
+
+    /// Draws a single mesh of a loaded model with the material, meshes are in file order.
+    pub fn set_mesh_material(&mut self, name: &str, mesh: usize, material: &str) -> Result<()> {
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        let model = assets.model_mut(name)?;
+        let mesh_count = model.meshes.len();
+        model
+            .meshes
+            .get_mut(mesh)
+            .ok_or_else(|| anyhow!("Mesh {} out of range, {} has {}", mesh, name, mesh_count))?
+            .material = Some(material.to_owned());
+        Ok(())
+    }
+
     pub unsafe fn render(&mut self, window: &Window) -> Result<()> {
         self.metrics.cycle.start_frame();
 
@@ -159,6 +658,14 @@ impl App {
             u64::max_value(),
         )?;
 
+        // We destroy what the frame's fence proves is no longer in use.
+        self.data.deletion_queue.collect(
+            &self.device,
+            &self.data.allocator,
+            &mut self.data.geometry,
+            self.frame,
+        );
+
         // We acquire the next image from the swapchain.
         let result = self.device.acquire_next_image_khr(
             self.data.swapchain,
@@ -186,6 +693,15 @@ impl App {
         // We mark the image as in use.
         self.data.images_in_flight[image_index as usize] = self.data.in_flight_fences[self.frame];
 
+        // We insert the assets streamed since the last frame.
+        self.receive_streamed_assets()?;
+
+        // We pose the skeletons of the spawned models.
+        self.update_animations();
+
+        // We build the draws of this frame.
+        self.update_draw_buffers()?;
+
         // We update the command buffer.
         self.update_command_buffer(image_index)?;
 
@@ -204,6 +720,9 @@ impl App {
             .signal_semaphores(signal_semaphores)
             .build();
 
+        // We submit the uploads recorded since the last frame, the frame sees their results.
+        flush_uploads(&self.device, &mut self.data)?;
+
         // We reset the fence of the current frame. This is because we're going to re-use this frame's resources.
         self.device
             .reset_fences(&[self.data.in_flight_fences[self.frame]])?;
@@ -215,6 +734,9 @@ impl App {
             self.data.in_flight_fences[self.frame],
         )?;
 
+        // What was retired until now is freed once this frame's fence signals.
+        self.data.deletion_queue.submitted(self.frame);
+
         // We build the present info that we're going to use to present.
         let swapchains = &[self.data.swapchain];
         let image_indices = &[image_index as u32];
@@ -234,15 +756,74 @@ impl App {
             || result == Err(vk::ErrorCode::OUT_OF_DATE_KHR);
 
         // We check if the window was resized. If it was, we recreate the swapchain.
-        if self.resized || changed {
+        if self.resized || self.swapchain_outdated || changed {
             self.recreate_swapchain(window)?;
             self.resized = false;
+            self.swapchain_outdated = false;
         } else if let Err(e) = result {
             return Err(anyhow!(e));
         }
 
         // We increment the frame index.
-        self.frame = (self.frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.frame = (self.frame + 1) % self.data.setting_frames_in_flight;
+
+        self.metrics.cycle.end_frame();
+        self.metrics.total_frames += 1;
+
+        Ok(())
+    }
+
+    /// Renders a frame into the offscreen images of a headless app.
+    pub fn render_headless(&mut self) -> Result<()> {
+        if !self.data.headless {
+            return Err(anyhow!("render_headless requires an app created with new_headless"));
+        }
+
+        self.metrics.cycle.start_frame();
+
+        unsafe {
+            // Each frame in flight owns one offscreen image, so waiting for the frame's fence
+            // also guarantees its image is no longer in use.
+            self.device
+                .wait_for_fences(&[self.data.in_flight_fences[self.frame]], true, u64::MAX)?;
+
+            self.data.deletion_queue.collect(
+                &self.device,
+                &self.data.allocator,
+                &mut self.data.geometry,
+                self.frame,
+            );
+
+            let image_index = self.frame;
+
+            self.receive_streamed_assets()?;
+            self.update_animations();
+            self.update_draw_buffers()?;
+            self.update_command_buffer(image_index)?;
+            self.update_uniform_buffer(image_index)?;
+
+            let command_buffers = &[self.data.command_buffers[image_index]];
+            let submit_info = vk::SubmitInfo::builder()
+                .command_buffers(command_buffers)
+                .build();
+
+            flush_uploads(&self.device, &mut self.data)?;
+
+            self.device
+                .reset_fences(&[self.data.in_flight_fences[self.frame]])?;
+
+            self.device.queue_submit(
+                self.data.graphics_queue,
+                &[submit_info],
+                self.data.in_flight_fences[self.frame],
+            )?;
+
+            self.data.deletion_queue.submitted(self.frame);
+
+            self.last_rendered_image = Some(image_index);
+        }
+
+        self.frame = (self.frame + 1) % self.data.setting_frames_in_flight;
 
         self.metrics.cycle.end_frame();
         self.metrics.total_frames += 1;
@@ -250,6 +831,185 @@ impl App {
         Ok(())
     }
 
+    /// Returns the last frame rendered by [`App::render_headless`] as tightly packed RGBA8 rows,
+    /// top row first.
+    pub fn read_pixels(&self) -> Result<Vec<u8>> {
+        let image_index = self
+            .last_rendered_image
+            .ok_or_else(|| anyhow!("No frame has been rendered yet"))?;
+
+        unsafe {
+            self.device
+                .wait_for_fences(&[self.data.in_flight_fences[image_index]], true, u64::MAX)?;
+
+            read_offscreen_image(&self.instance, &self.device, &self.data, image_index)
+        }
+    }
+
+    pub fn renderer_settings(&self) -> &RendererSettings {
+        &self.settings
+    }
+
+    /// Applies new settings, rebuilding only the objects depending on what changed. A new
+    /// present mode takes effect from the next windowed frame, the physical device stays the
+    /// one picked at creation.
+    pub fn set_renderer_settings(&mut self, settings: RendererSettings) -> Result<()> {
+        let previous = (
+            self.data.msaa_samples,
+            self.data.setting_sample_shading,
+            self.data.setting_resolution_scale,
+            self.data.setting_anisotropy,
+            self.data.setting_max_sampler_anisotropy,
+            self.data.setting_present_mode,
+        );
+
+        self.settings = settings;
+
+        unsafe {
+            self.device.device_wait_idle()?;
+
+            // The number of frames in flight may change, nothing retired can be in use anyway.
+            self.data.deletion_queue.collect_all(
+                &self.device,
+                &self.data.allocator,
+                &mut self.data.geometry,
+            );
+
+            apply_settings(&mut self.data, &self.settings);
+
+            if (
+                self.data.msaa_samples,
+                self.data.setting_sample_shading,
+                self.data.setting_resolution_scale,
+            ) != (previous.0, previous.1, previous.2)
+            {
+                self.rebuild_pipeline()?;
+            }
+
+            if (
+                self.data.setting_anisotropy,
+                self.data.setting_max_sampler_anisotropy,
+            ) != (previous.3, previous.4)
+            {
+                let mut assets = self.assets.write().expect("Failed to lock assets");
+                assets.recreate_samplers(&self.device, &mut self.data)?;
+            }
+        }
+
+        if self.data.setting_present_mode != previous.5 && !self.data.headless {
+            self.swapchain_outdated = true;
+        }
+
+        self.metrics
+            .cycle
+            .set_frame_rate_limit(self.settings.frame_rate_limit);
+
+        // Every frame is idle, start over from the first one.
+        self.frame = 0;
+
+        Ok(())
+    }
+
+    /// Switches the present mode, the swapchain is recreated before the next frame.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<()> {
+        if present_mode == self.settings.present_mode {
+            return Ok(());
+        }
+
+        self.set_renderer_settings(RendererSettings {
+            present_mode,
+            ..self.settings.clone()
+        })
+    }
+
+    /// Caps the frame rate by sleeping on the CPU before each frame, `None` removes the cap.
+    pub fn set_frame_rate_limit(&mut self, frames_per_second: Option<u32>) {
+        self.settings.frame_rate_limit = frames_per_second;
+        self.metrics.cycle.set_frame_rate_limit(frames_per_second);
+    }
+
+    /// Every physical device considered at startup, best first, including the unsuitable ones.
+    pub fn physical_devices(&self) -> &[PhysicalDeviceCandidate] {
+        &self.physical_devices
+    }
+
+    /// Device memory allocated by the renderer, per memory heap.
+    pub fn memory_usage(&self) -> Vec<HeapUsage> {
+        self.data.allocator.heap_usage()
+    }
+
+    /// Time elapsed since the app was created.
+    pub fn uptime(&self) -> std::time::Duration {
+        self.metrics.engine_start.elapsed()
+    }
+
+    /// Meshes drawn and culled in the last frame the GPU finished, a few frames behind.
+    pub fn draw_counts(&self) -> DrawCounts {
+        self.metrics.draw_counts
+    }
+
+    /// Size of the rendered frames in pixels.
+    pub fn extent(&self) -> (u32, u32) {
+        (
+            self.data.swapchain_extent.width,
+            self.data.swapchain_extent.height,
+        )
+    }
+
+    /// Advances the animations of the spawned models by the time the last frame took.
+    fn update_animations(&mut self) {
+        let delta = self.metrics.cycle.frame_delta().as_secs_f32();
+        let mut assets = self.assets.write().expect("Failed to lock assets");
+        assets.update_animations(delta);
+    }
+
+    /// Builds the draws of the scene, none behind the loading screen, and copies them to the
+    /// buffers of this frame.
+    unsafe fn update_draw_buffers(&mut self) -> Result<()> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        let camera = assets.active_camera()?;
+
+        // Counted by the culling pass when the frame was last drawn.
+        let draw_buffers = &mut self.draw_buffers[self.frame];
+        self.metrics.draw_counts = draw_buffers.counts();
+
+        self.loading_progress =
+            (self.loading_screen && assets.pending() > 0).then(|| assets.loading_progress());
+        if self.loading_progress.is_some() {
+            self.scene_draws.clear();
+        } else {
+            self.scene_draws.build(&assets);
+        }
+
+        let pyramid = &self.data.depth_pyramid;
+        let cull_data = CullData {
+            previous_view_proj: pyramid.view_proj.unwrap_or(Matrix4::identity()),
+            planes: camera.frustum_planes(),
+            pyramid_size: [pyramid.extent.width as f32, pyramid.extent.height as f32],
+            pyramid_levels: pyramid.levels,
+            instance_count: self.scene_draws.instances.len() as u32,
+            occlusion: (self.data.occlusion_culling && pyramid.view_proj.is_some()) as u32,
+            _padding: [0; 3],
+        };
+
+        draw_buffers.update(
+            &self.scene_draws,
+            &cull_data,
+            self.frame,
+            &self.instance,
+            &self.device,
+            &self.data,
+        )
+    }
+
+    /// What the active camera projects the world with this frame.
+    fn camera_view_proj(&self) -> Result<Matrix4<f32>> {
+        let assets = self.assets.read().expect("Failed to lock assets");
+        let camera = assets.active_camera()?;
+
+        Ok(camera.projection * camera.model_view)
+    }
+
     unsafe fn update_command_buffer(&mut self, image_index: usize) -> Result<()> {
         let command_pool = self.data.command_pools[image_index];
         self.device
@@ -262,45 +1022,50 @@ impl App {
 
         self.device.begin_command_buffer(command_buffer, &info)?;
 
-        let render_area = vk::Rect2D::builder()
-            .offset(vk::Offset2D::default())
-            .extent(self.data.swapchain_extent);
-
-        let color_clear_value = vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.0, 0.0, 0.0, 1.0],
-            },
-        };
-
-        let depth_clear_value = vk::ClearValue {
-            depth_stencil: vk::ClearDepthStencilValue {
-                depth: 1.0,
-                stencil: 0,
-            },
-        };
-
-        let clear_values = &[color_clear_value, depth_clear_value];
-        let info = vk::RenderPassBeginInfo::builder()
-            .render_pass(self.data.render_pass)
-            .framebuffer(self.data.framebuffers[image_index])
-            .render_area(render_area)
-            .clear_values(clear_values);
-
-        self.device.cmd_begin_render_pass(
+        record_cull_pass(
+            &self.device,
             command_buffer,
-            &info,
-            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+            &self.data,
+            self.frame,
+            self.scene_draws.instances.len() as u32,
         );
 
-        let mut secondary_command_buffers = Vec::new();
+        // The graph handles the layouts and dependencies between the passes.
+        for pass in 0..self.data.render_graph.pass_count() {
+            let name = self.data.render_graph.pass_name(pass);
+            let contents = match name {
+                SCENE_PASS => vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+                _ => vk::SubpassContents::INLINE,
+            };
 
-        let secondary_command_buffer = self.update_secondary_command_buffer(image_index)?;
-        secondary_command_buffers.push(secondary_command_buffer);
+            self.data.render_graph.begin_pass(
+                &self.device,
+                command_buffer,
+                pass,
+                image_index,
+                self.data.swapchain_extent,
+                contents,
+            );
+
+            match name {
+                SCENE_PASS => {
+                    let secondary_command_buffer =
+                        self.update_secondary_command_buffer(pass, image_index)?;
+                    self.device
+                        .cmd_execute_commands(command_buffer, &[secondary_command_buffer]);
+                }
+                UPSCALE_PASS => record_upscale_pass(&self.device, command_buffer, &self.data),
+                name => return Err(anyhow!("Render pass without recording: {}", name)),
+            }
 
-        self.device
-            .cmd_execute_commands(command_buffer, &secondary_command_buffers);
+            self.device.cmd_end_render_pass(command_buffer);
 
-        self.device.cmd_end_render_pass(command_buffer);
+            // Built from the depth the scene was just drawn with, for the next frame.
+            if name == SCENE_PASS && self.data.occlusion_culling {
+                let view_proj = self.camera_view_proj()?;
+                record_depth_pyramid(&self.device, command_buffer, &mut self.data, view_proj);
+            }
+        }
 
         self.device.end_command_buffer(command_buffer)?;
 
@@ -309,6 +1074,7 @@ impl App {
 
     unsafe fn update_secondary_command_buffer(
         &mut self,
+        pass: usize,
         image_index: usize,
     ) -> Result<vk::CommandBuffer> {
         // Allocate
@@ -330,9 +1096,9 @@ impl App {
         // Commands
 
         let inheritance_info = vk::CommandBufferInheritanceInfo::builder()
-            .render_pass(self.data.render_pass)
+            .render_pass(self.data.render_graph.render_pass(SCENE_PASS)?)
             .subpass(0)
-            .framebuffer(self.data.framebuffers[image_index]);
+            .framebuffer(self.data.render_graph.framebuffer(pass, image_index));
 
         let info = vk::CommandBufferBeginInfo::builder()
             .flags(vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
@@ -347,83 +1113,147 @@ impl App {
             self.data.pipeline,
         );
 
-        // Iterate through the meshes
-        let assets = self.assets.read().expect("Failed to lock assets");
+        let extent = self
+            .data
+            .render_graph
+            .pass_extent(pass, self.data.swapchain_extent);
+
+        let viewport = vk::Viewport::builder()
+            .x(0.0)
+            .y(0.0)
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+        self.device
+            .cmd_set_viewport(secondary_command_buffer, 0, &[viewport]);
 
-        assets.active_models.iter().for_each(|name| {
-            let model = assets.models.get(name).expect("Mesh not found");
-            for mesh in &model.meshes {
-                self.device.cmd_bind_vertex_buffers(
-                    secondary_command_buffer,
-                    0,
-                    &[mesh.vertex_buffer],
-                    &[0],
-                );
-                self.device.cmd_bind_vertex_buffers(
-                    secondary_command_buffer,
-                    1,
-                    &[mesh.instance_buffer],
-                    &[0],
-                );
-                self.device.cmd_bind_index_buffer(
-                    secondary_command_buffer,
-                    mesh.index_buffer,
-                    0,
-                    vk::IndexType::UINT32,
-                );
-                self.device.cmd_bind_descriptor_sets(
-                    secondary_command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.data.pipeline_layout,
-                    0,
-                    &[self.data.descriptor_sets[image_index]],
-                    &[],
-                );
+        let scissor = vk::Rect2D::builder()
+            .offset(vk::Offset2D { x: 0, y: 0 })
+            .extent(extent);
+        self.device
+            .cmd_set_scissor(secondary_command_buffer, 0, &[scissor]);
 
-                // Push constants
-
-                let time = self.metrics.engine_start.elapsed().as_secs_f32();
-                let rotation = cgmath::Quaternion::from(cgmath::Euler {
-                    x: cgmath::Deg(0.0),
-                    y: cgmath::Deg(0.0),
-                    z: cgmath::Deg(time * 5.0),
-                });
-
-                let model = cgmath::Matrix4::identity() * cgmath::Matrix4::from(rotation);
-                let model_bytes = unsafe {
-                    std::slice::from_raw_parts(
-                        &model as *const cgmath::Matrix4<f32> as *const u8,
-                        std::mem::size_of::<cgmath::Matrix4<f32>>(),
-                    )
-                };
-                self.device.cmd_push_constants(
-                    secondary_command_buffer,
-                    self.data.pipeline_layout,
-                    vk::ShaderStageFlags::VERTEX,
-                    0,
-                    model_bytes,
-                );
+        self.device.cmd_bind_descriptor_sets(
+            secondary_command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.data.pipeline_layout,
+            0,
+            &[self.data.descriptor_sets[image_index]],
+            &[],
+        );
 
-                let opacity = 1.0 - (3 as f32 * 0.3);
-                let opacity_bytes = opacity.to_ne_bytes();
-                self.device.cmd_push_constants(
-                    secondary_command_buffer,
-                    self.data.pipeline_layout,
-                    vk::ShaderStageFlags::FRAGMENT,
-                    64,
-                    &opacity_bytes,
-                );
+        // Every mesh lives in the geometry arena, bound once for the whole scene.
+        let geometry = &self.data.geometry;
+        if !geometry.vertices.buffer.is_null() {
+            self.device.cmd_bind_vertex_buffers(
+                secondary_command_buffer,
+                0,
+                &[geometry.vertices.buffer],
+                &[0],
+            );
+        }
+        if !geometry.indices.buffer.is_null() {
+            self.device.cmd_bind_index_buffer(
+                secondary_command_buffer,
+                geometry.indices.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
 
-                self.device.cmd_draw_indexed(
+        // The draws of the frame, the instances of each draw follow the previous ones.
+        let draw_buffers = &self.draw_buffers[self.frame];
+        self.device.cmd_bind_descriptor_sets(
+            secondary_command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.data.pipeline_layout,
+            2,
+            &[self.data.draw_descriptor_sets[self.frame]],
+            &[],
+        );
+        self.device.cmd_bind_vertex_buffers(
+            secondary_command_buffer,
+            1,
+            &[draw_buffers.visible_instances.buffer],
+            &[0],
+        );
+
+        if let Some(progress) = self.loading_progress {
+            record_loading_screen(&self.device, secondary_command_buffer, extent, progress);
+        }
+
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        let mut skinned = false;
+        for batch in &self.scene_draws.batches {
+            // Skinned meshes have their own pipeline and vertices.
+            if batch.skinned != skinned {
+                skinned = batch.skinned;
+                let (pipeline, vertices) = if skinned {
+                    (self.data.skinned_pipeline, geometry.skinned_vertices.buffer)
+                } else {
+                    (self.data.pipeline, geometry.vertices.buffer)
+                };
+                self.device.cmd_bind_pipeline(
                     secondary_command_buffer,
-                    mesh.index_count,
-                    mesh.instance_count,
-                    0,
-                    0,
-                    0,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
                 );
+                if !vertices.is_null() {
+                    self.device.cmd_bind_vertex_buffers(
+                        secondary_command_buffer,
+                        0,
+                        &[vertices],
+                        &[0],
+                    );
+                }
+            }
+
+            self.device.cmd_bind_descriptor_sets(
+                secondary_command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.data.pipeline_layout,
+                1,
+                &[batch.material_set],
+                &[],
+            );
+
+            let commands = batch.first_command..batch.first_command + batch.command_count;
+            if self.data.feature_draw_indirect_first_instance {
+                // As few calls as `maxDrawIndirectCount` allows, one per command without multi
+                // draw support.
+                let mut first = commands.start;
+                while first < commands.end {
+                    let count = (commands.end - first).min(self.data.limit_max_draw_indirect_count);
+                    self.device.cmd_draw_indexed_indirect(
+                        secondary_command_buffer,
+                        draw_buffers.commands.buffer,
+                        first as u64 * stride as u64,
+                        count,
+                        stride,
+                    );
+                    first += count;
+                }
+            } else {
+                // The commands start at instance 0, the instances are bound at their offset.
+                for command in commands {
+                    let first_instance = self.scene_draws.commands[command as usize].first_instance;
+                    self.device.cmd_bind_vertex_buffers(
+                        secondary_command_buffer,
+                        1,
+                        &[draw_buffers.visible_instances.buffer],
+                        &[first_instance as u64 * std::mem::size_of::<DrawInstance>() as u64],
+                    );
+                    self.device.cmd_draw_indexed_indirect(
+                        secondary_command_buffer,
+                        draw_buffers.commands.buffer,
+                        command as u64 * stride as u64,
+                        1,
+                        stride,
+                    );
+                }
             }
-        });
+        }
 
         self.device.end_command_buffer(secondary_command_buffer)?;
 
@@ -433,10 +1263,7 @@ impl App {
     unsafe fn update_uniform_buffer(&self, image_index: usize) -> Result<()> {
         let assets = self.assets.read().expect("Failed to lock assets");
 
-        let camera = assets
-            .cameras
-            .get(&assets.active_camera)
-            .expect("Camera not found");
+        let camera = assets.active_camera()?;
 
         let ubo = UniformBufferObject {
             view: camera.model_view,
@@ -444,62 +1271,58 @@ impl App {
         };
 
         // OPTIMIZE use push constants
-        let memory = self.device.map_memory(
-            self.data.uniform_buffers_memory[image_index],
-            0,
-            std::mem::size_of::<UniformBufferObject>() as u64,
-            vk::MemoryMapFlags::empty(),
-        )?;
-
-        std::ptr::copy_nonoverlapping(&ubo, memory.cast(), 1);
-
-        self.device
-            .unmap_memory(self.data.uniform_buffers_memory[image_index]);
+        std::ptr::copy_nonoverlapping(
+            &ubo,
+            self.data.uniform_buffers_memory[image_index].mapped_ptr(),
+            1,
+        );
 
         Ok(())
     }
 
     pub(crate) unsafe fn recreate_swapchain(&mut self, window: &Window) -> Result<()> {
         self.device.device_wait_idle()?;
-        self.destroy_swapchain();
+
+        let format = self.data.swapchain_format;
+        let image_count = self.data.swapchain_images.len();
+
+        // Only the extent dependent objects are rebuilt, the old swapchain is handed over to the
+        // new one so presentation carries on while resizing.
+        self.data
+            .render_graph
+            .destroy_targets(&self.device, &self.data.allocator);
+        self.destroy_swapchain_image_views();
         create_swapchain(window, &self.instance, &self.device, &mut self.data)?;
         create_swapchain_image_views(&self.device, &mut self.data)?;
-        create_render_pass(&self.instance, &self.device, &mut self.data)?;
-        create_pipeline(&self.device, &mut self.data)?;
-        create_color_objects(&self.instance, &self.device, &mut self.data)?;
-        create_depth_objects(&self.instance, &self.device, &mut self.data)?;
-        create_framebuffers(&self.device, &mut self.data)?;
-
-        create_uniform_buffers(&self.instance, &self.device, &mut self.data)?;
-        create_descriptor_pool(&self.device, &mut self.data)?;
 
-        // Load textures
-        let assets = self.assets.read().expect("Failed to lock assets");
+        if self.data.swapchain_format != format {
+            // The render passes, and the pipeline built against them, depend on the format.
+            self.rebuild_pipeline()?;
+        } else {
+            create_render_targets(&self.instance, &self.device, &mut self.data)?;
+        }
 
-        let viking_room_texture = assets
-            .textures
-            .get("viking_room")
-            .expect("Texture not found");
-        let cube_texture = assets.textures.get("cube").expect("Texture not found");
-        let textures = &[cube_texture, viking_room_texture];
+        if self.data.swapchain_images.len() != image_count {
+            self.destroy_image_resources();
+            self.create_image_resources()?;
+        }
 
-        create_descriptor_sets(&self.device, &mut self.data, textures, &self.sampler)?;
-        create_command_buffers(&self.device, &mut self.data)?;
         self.data
             .images_in_flight
             .resize(self.data.swapchain_images.len(), vk::Fence::null());
         Ok(())
     }
 
-    unsafe fn destroy_swapchain(&mut self) {
-        self.device
-            .destroy_image_view(self.data.color_image_view, None);
-        self.device.free_memory(self.data.color_image_memory, None);
-        self.device.destroy_image(self.data.color_image, None);
-        self.device
-            .destroy_image_view(self.data.depth_image_view, None);
-        self.device.free_memory(self.data.depth_image_memory, None);
-        self.device.destroy_image(self.data.depth_image, None);
+    /// Creates the objects kept per swapchain image.
+    unsafe fn create_image_resources(&mut self) -> Result<()> {
+        create_image_command_pools(&self.instance, &self.device, &mut self.data)?;
+        create_uniform_buffers(&self.instance, &self.device, &mut self.data)?;
+        create_descriptor_pool(&self.device, &mut self.data)?;
+        create_descriptor_sets(&self.device, &mut self.data)?;
+        create_command_buffers(&self.device, &mut self.data)
+    }
+
+    unsafe fn destroy_image_resources(&mut self) {
         self.device
             .destroy_descriptor_pool(self.data.descriptor_pool, None);
         self.data
@@ -509,20 +1332,53 @@ impl App {
         self.data
             .uniform_buffers_memory
             .iter()
-            .for_each(|m| self.device.free_memory(*m, None));
+            .for_each(|m| self.data.allocator.free(&self.device, *m));
         self.data
-            .framebuffers
+            .command_pools
             .iter()
-            .for_each(|f| self.device.destroy_framebuffer(*f, None));
-        self.device.destroy_pipeline(self.data.pipeline, None);
-        self.device
-            .destroy_pipeline_layout(self.data.pipeline_layout, None);
-        self.device.destroy_render_pass(self.data.render_pass, None);
+            .for_each(|p| self.device.destroy_command_pool(*p, None));
+    }
+
+    /// Rebuilds the render graph and the pipelines built against its render passes.
+    unsafe fn rebuild_pipeline(&mut self) -> Result<()> {
+        self.destroy_pipeline();
+        create_render_graph(&self.instance, &self.device, &mut self.data)?;
+        create_pipeline(&self.device, &mut self.data)?;
+        create_upscale_pipeline(&self.device, &mut self.data)
+    }
+
+    unsafe fn destroy_pipeline(&mut self) {
+        let deletion_queue = &mut self.data.deletion_queue;
+        deletion_queue.push(Retired::Pipeline(self.data.pipeline));
+        deletion_queue.push(Retired::Pipeline(self.data.skinned_pipeline));
+        deletion_queue.push(Retired::PipelineLayout(self.data.pipeline_layout));
+        if !self.data.upscale_pipeline.is_null() {
+            deletion_queue.push(Retired::Pipeline(self.data.upscale_pipeline));
+            deletion_queue.push(Retired::PipelineLayout(self.data.upscale_pipeline_layout));
+        }
+        self.data.upscale_pipeline = vk::Pipeline::null();
+        self.data.upscale_pipeline_layout = vk::PipelineLayout::null();
+        self.data
+            .render_graph
+            .destroy(&self.device, &self.data.allocator);
+    }
+
+    unsafe fn destroy_swapchain_image_views(&mut self) {
         self.data
             .swapchain_image_views
             .iter()
             .for_each(|v| self.device.destroy_image_view(*v, None));
-        self.device.destroy_swapchain_khr(self.data.swapchain, None);
+    }
+
+    unsafe fn destroy_swapchain(&mut self) {
+        self.destroy_image_resources();
+        self.destroy_pipeline();
+        self.destroy_swapchain_image_views();
+        if self.data.headless {
+            destroy_offscreen_targets(&self.device, &mut self.data);
+        } else {
+            self.device.destroy_swapchain_khr(self.data.swapchain, None);
+        }
     }
 
     pub fn destroy(&mut self) {
@@ -538,37 +1394,60 @@ impl App {
 impl Drop for App {
     fn drop(&mut self) {
         unsafe {
+            self.streamer.stop(&mut self.data.deletion_queue);
+            wait_uploads(&self.device, &mut self.data).expect("Failed to wait for uploads");
+            destroy_upload_context(&self.device, &mut self.data);
             self.destroy_swapchain();
-            self.data
-                .command_pools
-                .iter()
-                .for_each(|p| self.device.destroy_command_pool(*p, None));
 
-            let assets = self.assets.read().expect("Failed to lock assets");
-
-            assets.textures.iter().for_each(|(_, texture)| {
-                self.device.destroy_image(texture.image, None);
-                self.device.free_memory(texture.image_memory, None);
-
-                self.device.destroy_image_view(texture.image_view, None);
-            });
+            self.assets
+                .write()
+                .expect("Failed to lock assets")
+                .destroy(&self.device, &mut self.data.deletion_queue);
+            self.data
+                .depth_pyramid
+                .retire(&mut self.data.deletion_queue);
+            self.data.deletion_queue.collect_all(
+                &self.device,
+                &self.data.allocator,
+                &mut self.data.geometry,
+            );
+            self.data
+                .geometry
+                .destroy(&self.device, &self.data.allocator);
 
-            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_pipeline(self.data.cull_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.data.cull_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_pool(self.data.cull_descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.data.cull_set_layout, None);
+            self.device
+                .destroy_pipeline(self.data.depth_pyramid_pipeline, None);
+            self.device
+                .destroy_pipeline(self.data.depth_pyramid_ms_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.data.depth_pyramid_pipeline_layout, None);
+            self.device
+                .destroy_sampler(self.data.depth_pyramid_sampler, None);
+            self.device
+                .destroy_descriptor_set_layout(self.data.depth_pyramid_set_layout, None);
 
+            self.device.destroy_sampler(self.data.upscale_sampler, None);
+            self.device
+                .destroy_descriptor_pool(self.data.material_descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.data.material_set_layout, None);
+            self.draw_buffers
+                .iter_mut()
+                .for_each(|b| b.destroy(&self.device, &self.data.allocator));
+            self.device
+                .destroy_descriptor_pool(self.data.draw_descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.data.draw_set_layout, None);
             self.device
                 .destroy_descriptor_set_layout(self.data.descriptor_set_layout, None);
 
-            assets.models.iter().for_each(|(_, model)| {
-                for mesh in &model.meshes {
-                    self.device.destroy_buffer(mesh.vertex_buffer, None);
-                    self.device.free_memory(mesh.vertex_buffer_memory, None);
-                    self.device.destroy_buffer(mesh.index_buffer, None);
-                    self.device.free_memory(mesh.index_buffer_memory, None);
-                    self.device.destroy_buffer(mesh.instance_buffer, None);
-                    self.device.free_memory(mesh.instance_buffer_memory, None);
-                }
-            });
-
             self.data
                 .in_flight_fences
                 .iter()
@@ -583,8 +1462,11 @@ impl Drop for App {
                 .for_each(|s| self.device.destroy_semaphore(*s, None));
             self.device
                 .destroy_command_pool(self.data.command_pool, None);
+            self.data.allocator.destroy(&self.device);
             self.device.destroy_device(None);
-            self.instance.destroy_surface_khr(self.data.surface, None);
+            if !self.data.headless {
+                self.instance.destroy_surface_khr(self.data.surface, None);
+            }
 
             if VALIDATION_ENABLED {
                 self.instance
@@ -598,22 +1480,51 @@ impl Drop for App {
 
 #[derive(Clone, Debug, Default)]
 pub(crate) struct AppData {
+    pub(crate) headless: bool,
+    /// Where the shaders and assets are read from, shared with the streaming thread.
+    pub(crate) vfs: Arc<Vfs>,
     pub(crate) surface: vk::SurfaceKHR,
     pub(crate) messenger: vk::DebugUtilsMessengerEXT,
     pub(crate) physical_device: vk::PhysicalDevice,
+    pub(crate) allocator: Allocator,
+    pub(crate) deletion_queue: DeletionQueue,
+    pub(crate) geometry: GeometryArena,
     pub(crate) msaa_samples: vk::SampleCountFlags,
     pub(crate) graphics_queue: vk::Queue,
     pub(crate) present_queue: vk::Queue,
+    /// Null without a queue to spare for streaming.
+    pub(crate) transfer_queue: vk::Queue,
     pub(crate) swapchain_format: vk::Format,
     pub(crate) swapchain_extent: vk::Extent2D,
     pub(crate) swapchain: vk::SwapchainKHR,
     pub(crate) swapchain_images: Vec<vk::Image>,
     pub(crate) swapchain_image_views: Vec<vk::ImageView>,
-    pub(crate) render_pass: vk::RenderPass,
+    pub(crate) offscreen_images_memory: Vec<Allocation>,
+    pub(crate) render_graph: RenderGraph,
     pub(crate) descriptor_set_layout: vk::DescriptorSetLayout,
+    pub(crate) material_set_layout: vk::DescriptorSetLayout,
+    pub(crate) draw_set_layout: vk::DescriptorSetLayout,
     pub(crate) pipeline_layout: vk::PipelineLayout,
     pub(crate) pipeline: vk::Pipeline,
-    pub(crate) framebuffers: Vec<vk::Framebuffer>,
+    /// Draws the skinned meshes, with the layout of `pipeline`.
+    pub(crate) skinned_pipeline: vk::Pipeline,
+    pub(crate) upscale_pipeline_layout: vk::PipelineLayout,
+    pub(crate) upscale_pipeline: vk::Pipeline,
+    pub(crate) upscale_sampler: vk::Sampler,
+    pub(crate) upscale_descriptor_set: vk::DescriptorSet,
+    pub(crate) cull_set_layout: vk::DescriptorSetLayout,
+    pub(crate) cull_pipeline_layout: vk::PipelineLayout,
+    pub(crate) cull_pipeline: vk::Pipeline,
+    pub(crate) cull_descriptor_pool: vk::DescriptorPool,
+    /// One per frame in flight.
+    pub(crate) cull_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub(crate) depth_pyramid_set_layout: vk::DescriptorSetLayout,
+    pub(crate) depth_pyramid_pipeline_layout: vk::PipelineLayout,
+    pub(crate) depth_pyramid_pipeline: vk::Pipeline,
+    /// Reduces a multisampled depth attachment into the first level.
+    pub(crate) depth_pyramid_ms_pipeline: vk::Pipeline,
+    pub(crate) depth_pyramid_sampler: vk::Sampler,
+    pub(crate) depth_pyramid: DepthPyramid,
     pub(crate) command_pool: vk::CommandPool,
     pub(crate) command_pools: Vec<vk::CommandPool>,
     pub(crate) command_buffers: Vec<vk::CommandBuffer>,
@@ -622,22 +1533,36 @@ pub(crate) struct AppData {
     pub(crate) render_finished_semaphores: Vec<vk::Semaphore>,
     pub(crate) in_flight_fences: Vec<vk::Fence>,
     pub(crate) images_in_flight: Vec<vk::Fence>,
-    // OPTIMIZE Use a single buffer for multiple buffers. Requires custom allocator.
+    // OPTIMIZE Use a single buffer for multiple buffers.
     pub(crate) uniform_buffers: Vec<vk::Buffer>,
-    pub(crate) uniform_buffers_memory: Vec<vk::DeviceMemory>,
+    pub(crate) uniform_buffers_memory: Vec<Allocation>,
     pub(crate) descriptor_pool: vk::DescriptorPool,
     pub(crate) descriptor_sets: Vec<vk::DescriptorSet>,
-    pub(crate) depth_image: vk::Image,
-    pub(crate) depth_image_memory: vk::DeviceMemory,
-    pub(crate) depth_image_view: vk::ImageView,
-    pub(crate) color_image: vk::Image,
-    pub(crate) color_image_memory: vk::DeviceMemory,
-    pub(crate) color_image_view: vk::ImageView,
+    pub(crate) material_descriptor_pool: vk::DescriptorPool,
+    pub(crate) draw_descriptor_pool: vk::DescriptorPool,
+    /// One per frame in flight.
+    pub(crate) draw_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub(crate) upload: UploadContext,
 
+    pub(crate) limit_max_msaa_samples: vk::SampleCountFlags,
     pub(crate) limit_max_sampler_anisotropy: f32,
     pub(crate) limit_max_push_constants_size: u32,
-
+    /// 1 without `multiDrawIndirect`.
+    pub(crate) limit_max_draw_indirect_count: u32,
+
+    pub(crate) feature_sampler_anisotropy: bool,
+    pub(crate) feature_sample_rate_shading: bool,
+    pub(crate) feature_multi_draw_indirect: bool,
+    pub(crate) feature_draw_indirect_first_instance: bool,
+    /// Whether the depth attachment can be sampled, the culling pass only tests the frustum
+    /// otherwise.
+    pub(crate) occlusion_culling: bool,
+
+    /// Resolved from the `RendererSettings` by `apply_settings`.
     pub(crate) setting_anisotropy: bool,
     pub(crate) setting_max_sampler_anisotropy: f32,
     pub(crate) setting_sample_shading: bool,
+    pub(crate) setting_present_mode: PresentMode,
+    pub(crate) setting_frames_in_flight: usize,
+    pub(crate) setting_resolution_scale: f32,
 }
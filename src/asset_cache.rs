@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::vfs::Vfs;
+
+const MAGIC: [u8; 4] = *b"YUMI";
+
+/// Bumped whenever a serialized asset or the header changes layout, every cache is rebuilt.
+const FORMAT_VERSION: u32 = 1;
+
+/// What a `.bin` cache was imported from. Follows the magic and the format version.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CacheHeader {
+    /// Name of the source in the file system, whatever the mount it was read from.
+    pub(crate) source: String,
+    /// FNV-1a of the source file.
+    pub(crate) source_hash: u64,
+    /// The importer and how it was configured.
+    pub(crate) importer: String,
+}
+
+impl CacheHeader {
+    /// Hashes the source file at `path`.
+    pub(crate) fn for_source(name: &str, path: &Path, importer: String) -> Result<Self> {
+        Ok(Self {
+            source: name.to_owned(),
+            source_hash: fnv1a(&std::fs::read(path)?),
+            importer,
+        })
+    }
+}
+
+/// Reads a cache written by [`write_cache`]. A cache from another format version is
+/// incompatible, one written from another source, content or importer than `expected` is stale.
+/// Without `expected`, any cache of the current format is taken.
+pub(crate) fn read_cache<T: DeserializeOwned>(
+    vfs: &Vfs,
+    name: &str,
+    expected: Option<&CacheHeader>,
+) -> Result<T> {
+    let bytes = vfs.read(name)?;
+    if bytes.len() < 8 || bytes[..4] != MAGIC {
+        return Err(anyhow!("not an asset cache: {}", name));
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into()?);
+    if version != FORMAT_VERSION {
+        return Err(anyhow!(
+            "cache format {} instead of {}: {}",
+            version,
+            FORMAT_VERSION,
+            name
+        ));
+    }
+
+    let mut reader = &bytes[8..];
+    let header: CacheHeader = bincode::deserialize_from(&mut reader)?;
+    if let Some(expected) = expected {
+        if header != *expected {
+            return Err(anyhow!(
+                "stale cache of {} ({}): {}",
+                header.source,
+                header.importer,
+                name
+            ));
+        }
+    }
+
+    Ok(bincode::deserialize_from(&mut reader)?)
+}
+
+/// Writes to the cache mount serving `name`.
+pub(crate) fn write_cache<T: Serialize>(
+    vfs: &Vfs,
+    name: &str,
+    header: &CacheHeader,
+    value: &T,
+) -> Result<()> {
+    vfs.write(name, &encode_cache(header, value)?)
+}
+
+/// The bytes of a cache, as [`read_cache`] reads them.
+pub(crate) fn encode_cache<T: Serialize>(header: &CacheHeader, value: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, header)?;
+    bincode::serialize_into(&mut bytes, value)?;
+    Ok(bytes)
+}
+
+/// 64 bit FNV-1a, enough to tell an edited source apart.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::test_util::test_dir;
+
+    fn header(source_hash: u64) -> CacheHeader {
+        CacheHeader {
+            source: "models/cube.glb".to_owned(),
+            source_hash,
+            importer: "gltf".to_owned(),
+        }
+    }
+
+    fn cache_vfs(name: &str, bytes: &[u8]) -> (Vfs, PathBuf) {
+        let dir = test_dir(name);
+        let mut vfs = Vfs::empty();
+        vfs.mount_cache("", &dir);
+        vfs.write("cube.bin", bytes).unwrap();
+        (vfs, dir)
+    }
+
+    #[test]
+    fn round_trips_with_its_header() {
+        let value = vec![1u32, 2, 3];
+        let (vfs, dir) = cache_vfs(
+            "cache-round-trip",
+            &encode_cache(&header(7), &value).unwrap(),
+        );
+
+        assert_eq!(
+            read_cache::<Vec<u32>>(&vfs, "cube.bin", Some(&header(7))).unwrap(),
+            value
+        );
+        assert_eq!(
+            read_cache::<Vec<u32>>(&vfs, "cube.bin", None).unwrap(),
+            value
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_stale_caches() {
+        let bytes = encode_cache(&header(7), &0u32).unwrap();
+        let (vfs, dir) = cache_vfs("cache-stale", &bytes);
+
+        assert!(read_cache::<u32>(&vfs, "cube.bin", Some(&header(8))).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let mut bytes = encode_cache(&header(7), &0u32).unwrap();
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let (vfs, dir) = cache_vfs("cache-format", &bytes);
+        assert!(read_cache::<u32>(&vfs, "cube.bin", None).is_err());
+
+        vfs.write("cube.bin", b"glTF").unwrap();
+        assert!(read_cache::<u32>(&vfs, "cube.bin", None).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hashes_the_source_content() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_ne!(fnv1a(b"cube"), fnv1a(b"cubf"));
+    }
+}
@@ -1,29 +1,74 @@
 use crate::{
+    animation::AnimationPlayer,
     app::AppData,
     camera::Camera,
-    model::{self, Model},
+    deletion_queue::{DeletionQueue, Retired},
+    descriptor_pool::{allocate_material_descriptor_set, write_material_descriptor_set},
+    handle::{AssetStore, Handle, LoadState},
+    material::{Material, MaterialDesc},
+    model::{self, imported_material_name, imported_texture_name, Model, SerializedModel},
+    placeholder,
+    streaming::AssetHandle,
     texture::Texture,
+    texture_sampler::{create_texture_sampler, SamplerFilter, SamplerSettings},
+    transform::Transform,
 };
 use anyhow::{anyhow, Result};
+use log::error;
 use std::collections::HashMap;
+use std::path::Path;
 use vulkanalia::prelude::v1_0::*;
+// The prelude's `Handle` is shadowed by the asset one.
+use vulkanalia::vk::Handle as _;
 
 #[derive(Debug)]
 pub struct Assets {
     pub cameras: HashMap<String, Camera>,
     pub(crate) active_camera: String,
-    pub(crate) models: HashMap<String, Model>,
-    pub(crate) active_models: Vec<String>,
-    pub(crate) textures: HashMap<String, Texture>,
+    pub(crate) models: AssetStore<Model>,
+    pub(crate) active_models: Vec<ActiveModel>,
+    pub(crate) textures: AssetStore<Texture>,
+    pub(crate) materials: HashMap<String, Material>,
+    pub(crate) samplers: HashMap<SamplerSettings, vk::Sampler>,
+    /// White texel sampled by materials without a texture.
+    pub(crate) default_texture: Option<Texture>,
+    /// Drawn with meshes without a material.
+    pub(crate) default_material: vk::DescriptorSet,
+    /// Checkerboard sampled by materials whose texture is not loaded.
+    pub(crate) placeholder_texture: Option<Texture>,
+    /// Drawn in place of the spawned models that are not loaded.
+    pub(crate) placeholder_model: Option<Model>,
+    /// Samples the checkerboard, the placeholder model is drawn with it.
+    pub(crate) placeholder_material: vk::DescriptorSet,
+    /// Assets streamed since none was pending, the loading screen shows how many are done.
+    pub(crate) streamed: usize,
 }
+/// A loaded model placed in the scene.
+#[derive(Debug)]
+pub(crate) struct ActiveModel {
+    pub(crate) name: String,
+    pub(crate) transform: Transform,
+    pub(crate) opacity: f32,
+    /// Poses the skeleton of the model, if it has one.
+    pub(crate) animation: AnimationPlayer,
+}
+
 impl Default for Assets {
     fn default() -> Self {
         Self {
             cameras: HashMap::new(),
             active_camera: String::new(),
-            models: HashMap::new(),
+            models: AssetStore::default(),
             active_models: Vec::new(),
-            textures: HashMap::new(),
+            textures: AssetStore::default(),
+            materials: HashMap::new(),
+            samplers: HashMap::new(),
+            default_texture: None,
+            default_material: vk::DescriptorSet::null(),
+            placeholder_texture: None,
+            placeholder_model: None,
+            placeholder_material: vk::DescriptorSet::null(),
+            streamed: 0,
         }
     }
 }
@@ -31,56 +76,577 @@ impl Assets {
     pub(crate) fn load_model(
         &mut self,
         name: &str,
+        path: &Path,
         instance: &mut Instance,
         device: &mut Device,
         data: &mut AppData,
-    ) -> Result<()> {
-        if self.models.contains_key(name) {
+    ) -> Result<Handle<Model>> {
+        if self.models.contains(name) {
             return Err(anyhow!("Mesh name already in use: {}", name));
         }
 
-        self.models.insert(
-            name.to_string(),
-            model::load_model(name, instance, device, data)?,
-        );
+        let serialized = model::read_model(&data.vfs, path)?;
+        let handle = self
+            .models
+            .reserve(name)
+            .ok_or_else(|| anyhow!("Mesh name already in use: {}", name))?;
+        if let Err(e) = unsafe { self.insert_model(handle, serialized, instance, device, data) } {
+            self.models.remove(name);
+            return Err(e);
+        }
+        Ok(handle)
+    }
+
+    /// Creates a decoded model for a reserved handle, along with the textures and materials it
+    /// was imported with.
+    pub(crate) unsafe fn insert_model(
+        &mut self,
+        handle: Handle<Model>,
+        mut serialized: SerializedModel,
+        instance: &Instance,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        let name = self
+            .models
+            .name(handle)
+            .ok_or_else(|| anyhow!("Model unloaded: {:?}", handle))?
+            .to_owned();
+        let name = name.as_str();
+
+        // Checked up front so an import never replaces existing textures or materials.
+        if let Some(texture) = (0..serialized.textures.len())
+            .map(|index| imported_texture_name(name, index))
+            .find(|texture| self.textures.contains(texture))
+        {
+            return Err(anyhow!("Texture name already in use: {}", texture));
+        }
+        if let Some(material) = (0..serialized.materials.len())
+            .map(|index| imported_material_name(name, index))
+            .find(|material| self.materials.contains_key(material))
+        {
+            return Err(anyhow!("Material name already in use: {}", material));
+        }
+
+        // The materials sample the textures by name, so both are inserted as they are created.
+        let mut textures = Vec::new();
+        let mut materials = Vec::new();
+        let model = (|| {
+            for (index, texture) in std::mem::take(&mut serialized.textures)
+                .into_iter()
+                .enumerate()
+            {
+                let texture = Texture::from_pixels(
+                    &texture.pixels,
+                    texture.width,
+                    texture.height,
+                    instance,
+                    device,
+                    data,
+                )?;
+                let texture_name = imported_texture_name(name, index);
+                self.textures.insert(&texture_name, texture);
+                textures.push(texture_name);
+            }
+
+            for (index, material) in std::mem::take(&mut serialized.materials)
+                .into_iter()
+                .enumerate()
+            {
+                let desc = MaterialDesc {
+                    base_color_texture: material
+                        .base_color_texture
+                        .map(|texture| imported_texture_name(name, texture)),
+                    sampler: material.sampler,
+                    base_color_factor: material.base_color_factor,
+                };
+                let material_name = imported_material_name(name, index);
+                self.create_material(&material_name, desc, device, data)?;
+                materials.push(material_name);
+            }
+
+            model::create_model(name, serialized, instance, device, data)
+        })();
+
+        // Unloads what was imported before the failure so the name can be loaded again.
+        let model = match model {
+            Ok(model) => model,
+            Err(e) => {
+                for material in &materials {
+                    self.destroy_material(material, data)?;
+                }
+                for texture in &textures {
+                    self.unload_texture(texture, &mut data.deletion_queue)?;
+                }
+                return Err(e);
+            }
+        };
+
+        if let Err(model) = self.models.fill(handle, model) {
+            model.retire(&mut data.deletion_queue);
+        }
         Ok(())
     }
 
-    // TODO
-    pub(crate) fn _unload_model(&mut self, name: &str) -> Result<()> {
-        if !self.models.contains_key(name) {
+    /// Removes the model from the scene, retires its buffers and what was imported with it. A
+    /// model still streaming is dropped once received.
+    pub(crate) fn unload_model(&mut self, name: &str, data: &mut AppData) -> Result<()> {
+        let model = self
+            .models
+            .remove(name)
+            .ok_or_else(|| anyhow!("Mesh name not found: {}", name))?;
+
+        self.active_models.retain(|m| m.name != name);
+
+        let Some(model) = model else {
+            return Ok(());
+        };
+
+        // The user may have destroyed some already.
+        for material in &model.materials {
+            if self.materials.contains_key(material) {
+                self.destroy_material(material, data)?;
+            }
+        }
+        for texture in &model.textures {
+            if self.textures.contains(texture) {
+                self.unload_texture(texture, &mut data.deletion_queue)?;
+            }
+        }
+
+        model.retire(&mut data.deletion_queue);
+        Ok(())
+    }
+
+    pub(crate) fn model(&self, name: &str) -> Result<&Model> {
+        match self.models.state_of(name) {
+            None => Err(anyhow!("Mesh name not found: {}", name)),
+            Some(LoadState::Ready) => self
+                .models
+                .get(name)
+                .ok_or_else(|| anyhow!("Mesh name not found: {}", name)),
+            Some(state) => Err(anyhow!("Mesh not loaded: {} ({:?})", name, state)),
+        }
+    }
+
+    pub(crate) fn model_mut(&mut self, name: &str) -> Result<&mut Model> {
+        match self.models.state_of(name) {
+            None => Err(anyhow!("Mesh name not found: {}", name)),
+            Some(LoadState::Ready) => self
+                .models
+                .get_mut(name)
+                .ok_or_else(|| anyhow!("Mesh name not found: {}", name)),
+            Some(state) => Err(anyhow!("Mesh not loaded: {} ({:?})", name, state)),
+        }
+    }
+
+    /// A model still streaming can be spawned, the placeholder is drawn until it is loaded.
+    pub(crate) fn spawn_model(&mut self, name: &str, transform: Transform) -> Result<()> {
+        if !self.models.contains(name) {
             return Err(anyhow!("Mesh name not found: {}", name));
         }
+        if self.active_models.iter().any(|m| m.name == name) {
+            return Err(anyhow!("Mesh already spawned: {}", name));
+        }
 
-        self.models.remove(name);
+        self.active_models.push(ActiveModel {
+            name: name.to_string(),
+            transform,
+            opacity: 1.0,
+            animation: AnimationPlayer::default(),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn active_model(&self, name: &str) -> Result<&ActiveModel> {
+        self.active_models
+            .iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| anyhow!("Mesh not spawned: {}", name))
+    }
+
+    pub(crate) fn active_model_mut(&mut self, name: &str) -> Result<&mut ActiveModel> {
+        self.active_models
+            .iter_mut()
+            .find(|m| m.name == name)
+            .ok_or_else(|| anyhow!("Mesh not spawned: {}", name))
+    }
+
+    /// Advances the clips of the spawned models and poses their skeletons.
+    pub(crate) fn update_animations(&mut self, delta: f32) {
+        for active_model in &mut self.active_models {
+            let Some(model) = self.models.get(&active_model.name) else {
+                continue;
+            };
+            if let Some(skeleton) = &model.skeleton {
+                active_model
+                    .animation
+                    .update(delta, skeleton, &model.animations);
+            }
+        }
+    }
+
+    pub(crate) fn despawn_model(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .active_models
+            .iter()
+            .position(|m| m.name == name)
+            .ok_or_else(|| anyhow!("Mesh not spawned: {}", name))?;
+
+        self.active_models.remove(index);
         Ok(())
     }
 
     pub(crate) fn load_texture(
         &mut self,
         name: &str,
+        path: &Path,
         instance: &mut Instance,
         device: &mut Device,
         data: &mut AppData,
-    ) -> Result<()> {
-        if self.textures.contains_key(name) {
+    ) -> Result<Handle<Texture>> {
+        if self.textures.contains(name) {
             return Err(anyhow!("Texture name already in use: {}", name));
         }
 
-        self.textures.insert(
-            name.to_string(),
-            Texture::load(name, instance, device, data)?,
+        let texture = Texture::load(path, instance, device, data)?;
+        self.textures
+            .insert(name, texture)
+            .ok_or_else(|| anyhow!("Texture name already in use: {}", name))
+    }
+
+    /// Reserves the name of a model requested from the streaming thread.
+    pub(crate) fn reserve_model(&mut self, name: &str) -> Result<Handle<Model>> {
+        if self.models.contains(name) {
+            return Err(anyhow!("Mesh name already in use: {}", name));
+        }
+
+        self.count_streamed();
+        self.models
+            .reserve(name)
+            .ok_or_else(|| anyhow!("Mesh name already in use: {}", name))
+    }
+
+    /// Reserves the name of a texture requested from the streaming thread.
+    pub(crate) fn reserve_texture(&mut self, name: &str) -> Result<Handle<Texture>> {
+        if self.textures.contains(name) {
+            return Err(anyhow!("Texture name already in use: {}", name));
+        }
+
+        self.count_streamed();
+        self.textures
+            .reserve(name)
+            .ok_or_else(|| anyhow!("Texture name already in use: {}", name))
+    }
+
+    /// The progress of the loading screen starts over once nothing is pending.
+    fn count_streamed(&mut self) {
+        if self.pending() == 0 {
+            self.streamed = 0;
+        }
+        self.streamed += 1;
+    }
+
+    /// Frees the name of an asset the streaming thread was never asked for.
+    pub(crate) fn cancel_streaming(&mut self, asset: AssetHandle) {
+        let name = match asset {
+            AssetHandle::Model(handle) => self.models.name(handle),
+            AssetHandle::Texture(handle) => self.textures.name(handle),
+        };
+        let Some(name) = name.map(str::to_owned) else {
+            return;
+        };
+
+        match asset {
+            AssetHandle::Model(_) => self.models.remove(&name).map(|_| ()),
+            AssetHandle::Texture(_) => self.textures.remove(&name).map(|_| ()),
+        };
+        self.streamed = self.streamed.saturating_sub(1);
+    }
+
+    /// The placeholder stays drawn in place of the asset until it is unloaded.
+    pub(crate) fn fail_streaming(&mut self, asset: AssetHandle, error: anyhow::Error) {
+        let name = match asset {
+            AssetHandle::Model(handle) => self.models.name(handle),
+            AssetHandle::Texture(handle) => self.textures.name(handle),
+        };
+        // Unloaded while streaming.
+        let Some(name) = name else {
+            return;
+        };
+
+        error!("Failed to stream {}: {:?}", name, error);
+        self.set_streaming_state(asset, LoadState::Failed(format!("{:#}", error)));
+    }
+
+    pub(crate) fn set_streaming_state(&mut self, asset: AssetHandle, state: LoadState) {
+        match asset {
+            AssetHandle::Model(handle) => self.models.set_state(handle, state),
+            AssetHandle::Texture(handle) => self.textures.set_state(handle, state),
+        }
+    }
+
+    /// Queued or loading models and textures.
+    pub(crate) fn pending(&self) -> usize {
+        self.models.pending() + self.textures.pending()
+    }
+
+    /// How much of what was streamed since none was pending is loaded, from 0.0 to 1.0. Failed
+    /// assets count as done.
+    pub(crate) fn loading_progress(&self) -> f32 {
+        if self.streamed == 0 {
+            return 1.0;
+        }
+        1.0 - self.pending() as f32 / self.streamed as f32
+    }
+
+    /// The camera the scene is viewed through.
+    pub(crate) fn active_camera(&self) -> Result<&Camera> {
+        self.cameras
+            .get(&self.active_camera)
+            .ok_or_else(|| anyhow!("Camera not found: {}", self.active_camera))
+    }
+
+    /// Retires the texture image. A texture still streaming is dropped once received.
+    pub(crate) fn unload_texture(
+        &mut self,
+        name: &str,
+        deletion_queue: &mut DeletionQueue,
+    ) -> Result<()> {
+        let texture = self
+            .textures
+            .remove(name)
+            .ok_or_else(|| anyhow!("Texture name not found: {}", name))?;
+
+        if let Some(texture) = texture {
+            texture.retire(deletion_queue);
+        }
+        Ok(())
+    }
+
+    /// Creates the default texture and material, drawn until the user provides their own, and
+    /// the placeholders drawn until an asset is loaded.
+    pub(crate) unsafe fn init_defaults(
+        &mut self,
+        instance: &Instance,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        self.default_texture = Some(Texture::from_pixels(
+            &[255, 255, 255, 255],
+            1,
+            1,
+            instance,
+            device,
+            data,
+        )?);
+
+        self.default_material = allocate_material_descriptor_set(device, data)?;
+        self.write_material(
+            self.default_material,
+            &MaterialDesc::default(),
+            device,
+            data,
+        )?;
+
+        let (pixels, width, height) = placeholder::checkerboard();
+        self.placeholder_texture = Some(Texture::from_pixels(
+            &pixels, width, height, instance, device, data,
+        )?);
+        self.placeholder_model = Some(model::create_model(
+            "placeholder",
+            placeholder::cube(),
+            instance,
+            device,
+            data,
+        )?);
+
+        self.placeholder_material = allocate_material_descriptor_set(device, data)?;
+        self.write_placeholder_material(device, data)
+    }
+
+    /// Shares samplers between the materials with the same settings.
+    unsafe fn sampler(
+        &mut self,
+        settings: &SamplerSettings,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<vk::Sampler> {
+        if let Some(sampler) = self.samplers.get(settings) {
+            return Ok(*sampler);
+        }
+
+        let sampler = create_texture_sampler(device, data, settings)?;
+        self.samplers.insert(*settings, sampler);
+        Ok(sampler)
+    }
+
+    /// Writes the texture and sampler of a material, the set must not be in use.
+    unsafe fn write_material(
+        &mut self,
+        descriptor_set: vk::DescriptorSet,
+        desc: &MaterialDesc,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        let sampler = self.sampler(&desc.sampler, device, data)?;
+
+        let texture = self
+            .material_texture(desc)
+            .ok_or_else(|| anyhow!("Default texture not created"))?;
+
+        write_material_descriptor_set(device, descriptor_set, texture.image_view, sampler);
+        Ok(())
+    }
+
+    /// White without a texture or one that was never requested, the checkerboard while it
+    /// streams or after it failed.
+    fn material_texture(&self, desc: &MaterialDesc) -> Option<&Texture> {
+        let Some(name) = &desc.base_color_texture else {
+            return self.default_texture.as_ref();
+        };
+
+        match self.textures.state_of(name) {
+            Some(LoadState::Ready) => self.textures.get(name),
+            Some(_) => self.placeholder_texture.as_ref(),
+            None => self.default_texture.as_ref(),
+        }
+    }
+
+    /// Samples the checkerboard texel by texel, the material set must not be in use.
+    unsafe fn write_placeholder_material(
+        &mut self,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        let settings = SamplerSettings {
+            filter: SamplerFilter::Nearest,
+            ..Default::default()
+        };
+        let sampler = self.sampler(&settings, device, data)?;
+
+        let texture = self
+            .placeholder_texture
+            .as_ref()
+            .ok_or_else(|| anyhow!("Placeholder texture not created"))?;
+
+        write_material_descriptor_set(
+            device,
+            self.placeholder_material,
+            texture.image_view,
+            sampler,
         );
         Ok(())
     }
 
-    // TODO
-    pub(crate) fn _unload_texture(&mut self, name: &str) -> Result<()> {
-        if !self.textures.contains_key(name) {
-            return Err(anyhow!("Texture name not found: {}", name));
+    /// Recreates the samplers after the anisotropy settings changed and rewrites every
+    /// material, none of them may be in use.
+    pub(crate) unsafe fn recreate_samplers(
+        &mut self,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        self.samplers
+            .drain()
+            .for_each(|(_, sampler)| device.destroy_sampler(sampler, None));
+
+        let materials = self
+            .materials
+            .values()
+            .map(|m| (m.descriptor_set, m.desc.clone()))
+            .chain([(self.default_material, MaterialDesc::default())])
+            .collect::<Vec<_>>();
+
+        for (descriptor_set, desc) in materials {
+            self.write_material(descriptor_set, &desc, device, data)?;
+        }
+        self.write_placeholder_material(device, data)
+    }
+
+    pub(crate) unsafe fn create_material(
+        &mut self,
+        name: &str,
+        desc: MaterialDesc,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        if self.materials.contains_key(name) {
+            return Err(anyhow!("Material name already in use: {}", name));
         }
 
-        self.textures.remove(name);
+        let descriptor_set = allocate_material_descriptor_set(device, data)?;
+        self.write_material(descriptor_set, &desc, device, data)?;
+
+        self.materials.insert(
+            name.to_string(),
+            Material {
+                desc,
+                descriptor_set,
+            },
+        );
+        Ok(())
+    }
+
+    /// Retires the material set. Meshes using the material are drawn with the default one.
+    pub(crate) fn destroy_material(&mut self, name: &str, data: &mut AppData) -> Result<()> {
+        let material = self
+            .materials
+            .remove(name)
+            .ok_or_else(|| anyhow!("Material name not found: {}", name))?;
+
+        data.deletion_queue.push(Retired::DescriptorSet(
+            data.material_descriptor_pool,
+            material.descriptor_set,
+        ));
+        Ok(())
+    }
+
+    /// Points the materials sampling a texture that was just requested, loaded or unloaded at it.
+    /// The frames in flight may still bind their sets, so each gets a new set and the old one is
+    /// retired.
+    pub(crate) unsafe fn refresh_materials(
+        &mut self,
+        texture_name: &str,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        let materials = self
+            .materials
+            .iter()
+            .filter(|(_, m)| m.desc.base_color_texture.as_deref() == Some(texture_name))
+            .map(|(name, m)| (name.clone(), m.desc.clone()))
+            .collect::<Vec<_>>();
+
+        for (name, desc) in materials {
+            let descriptor_set = allocate_material_descriptor_set(device, data)?;
+            self.write_material(descriptor_set, &desc, device, data)?;
+
+            let material = self
+                .materials
+                .get_mut(&name)
+                .ok_or_else(|| anyhow!("Material name not found: {}", name))?;
+            let retired = std::mem::replace(&mut material.descriptor_set, descriptor_set);
+            data.deletion_queue.push(Retired::DescriptorSet(
+                data.material_descriptor_pool,
+                retired,
+            ));
+        }
         Ok(())
     }
+
+    /// Retires every model and texture, destroys the samplers right away.
+    pub(crate) unsafe fn destroy(&mut self, device: &Device, deletion_queue: &mut DeletionQueue) {
+        self.models
+            .drain()
+            .chain(self.placeholder_model.take())
+            .for_each(|model| model.retire(deletion_queue));
+        self.textures
+            .drain()
+            .chain(self.default_texture.take())
+            .chain(self.placeholder_texture.take())
+            .for_each(|texture| texture.retire(deletion_queue));
+        self.samplers
+            .values()
+            .for_each(|sampler| device.destroy_sampler(*sampler, None));
+    }
 }
@@ -0,0 +1,14 @@
+use anyhow::{anyhow, Result};
+
+fn main() -> Result<()> {
+    let _ = pretty_env_logger::try_init();
+
+    let mut args = std::env::args().skip(1);
+    let (Some(source_dir), Some(pack), None) = (args.next(), args.next(), args.next()) else {
+        return Err(anyhow!("usage: yuumi-cook <asset directory> <pack file>"));
+    };
+
+    let count = yuumi::cook(&source_dir, &pack)?;
+    println!("Cooked {} files from {} into {}", count, source_dir, pack);
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use cgmath::{point3, vec3, EuclideanSpace, InnerSpace, Matrix4, Point3, Transform};
+use serde::{Deserialize, Serialize};
+
+use crate::vertex::Vertex;
+
+/// Axis aligned box around the vertices of a mesh, in model space.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct Aabb {
+    pub(crate) min: Point3<f32>,
+    pub(crate) max: Point3<f32>,
+}
+
+/// Sphere around the vertices of a mesh, in model space.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct BoundingSphere {
+    pub(crate) center: Point3<f32>,
+    pub(crate) radius: f32,
+}
+
+/// The volumes the culling pass tests a mesh with, computed when the `.bin` cache is written.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct Bounds {
+    pub(crate) aabb: Aabb,
+    pub(crate) sphere: BoundingSphere,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        let origin = point3(0.0, 0.0, 0.0);
+        Self {
+            aabb: Aabb {
+                min: origin,
+                max: origin,
+            },
+            sphere: BoundingSphere {
+                center: origin,
+                radius: 0.0,
+            },
+        }
+    }
+}
+
+impl Bounds {
+    pub(crate) fn from_vertices(vertices: &[Vertex]) -> Self {
+        let Some(first) = vertices.first() else {
+            return Self::default();
+        };
+
+        let first = Point3::from_vec(first.pos);
+        let aabb = vertices.iter().fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |aabb, vertex| Aabb {
+                min: point3(
+                    aabb.min.x.min(vertex.pos.x),
+                    aabb.min.y.min(vertex.pos.y),
+                    aabb.min.z.min(vertex.pos.z),
+                ),
+                max: point3(
+                    aabb.max.x.max(vertex.pos.x),
+                    aabb.max.y.max(vertex.pos.y),
+                    aabb.max.z.max(vertex.pos.z),
+                ),
+            },
+        );
+
+        // Centered on the box, tighter than its circumscribed sphere for most meshes.
+        let center = aabb.min.midpoint(aabb.max);
+        let radius = vertices
+            .iter()
+            .map(|vertex| (Point3::from_vec(vertex.pos) - center).magnitude())
+            .fold(0.0, f32::max);
+
+        Self {
+            aabb,
+            sphere: BoundingSphere { center, radius },
+        }
+    }
+
+    /// Bounds around the mesh once posed by `joints`, as long as the weights of each vertex sum
+    /// to one. A vertex moves at most as far as its farthest joint moves it.
+    pub(crate) fn posed(&self, joints: &[Matrix4<f32>]) -> Self {
+        let center = self.sphere.center;
+        let radius = joints
+            .iter()
+            .map(|joint| {
+                let scale = [joint.x, joint.y, joint.z]
+                    .iter()
+                    .map(|axis| axis.truncate().magnitude())
+                    .fold(0.0, f32::max);
+                (joint.transform_point(center) - center).magnitude() + scale * self.sphere.radius
+            })
+            .fold(0.0, f32::max);
+
+        let extent = vec3(radius, radius, radius);
+        Self {
+            aabb: Aabb {
+                min: center - extent,
+                max: center + extent,
+            },
+            sphere: BoundingSphere { center, radius },
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use cgmath::{
-    ortho, point3, vec3, Deg, EuclideanSpace, Matrix4, Quaternion, Rotation3, SquareMatrix,
+    ortho, point3, vec3, Deg, EuclideanSpace, InnerSpace, Matrix, Matrix4, Quaternion, Rotation3,
+    SquareMatrix, Vector4,
 };
 
 use crate::types::PositionXYZ;
@@ -96,6 +97,17 @@ impl Camera {
         }
         self.update();
     }
+
+    /// The left, right, bottom, top, near and far planes of the view volume in world space, as
+    /// of the last [`Camera::update`]. Each is a normal pointing inside in `xyz` and the distance
+    /// from the origin in `w`, a point is inside when `dot(xyz, point) + w >= 0` for all of them.
+    pub fn frustum_planes(&self) -> [Vector4<f32>; 6] {
+        let clip = self.projection * self.model_view;
+        let (x, y, z, w) = (clip.row(0), clip.row(1), clip.row(2), clip.row(3));
+
+        // The projection flips y, so the bottom of the screen is at y = w in clip space.
+        [w + x, w - x, w - y, w + y, w + z, w - z].map(|plane| plane / plane.truncate().magnitude())
+    }
 }
 
 impl Default for Camera {
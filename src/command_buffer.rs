@@ -11,6 +11,17 @@ pub(crate) unsafe fn create_command_pools(
 ) -> Result<()> {
     data.command_pool = create_command_pool(instance, device, data)?;
 
+    create_image_command_pools(instance, device, data)
+}
+
+/// One pool per swapchain image, reset before recording the image's commands.
+pub(crate) unsafe fn create_image_command_pools(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    data.command_pools.clear();
+
     let num_images = data.swapchain_images.len();
     for _ in 0..num_images {
         let command_pool = create_command_pool(instance, device, data)?;
@@ -35,6 +46,8 @@ unsafe fn create_command_pool(
 }
 
 pub(crate) unsafe fn create_command_buffers(device: &Device, data: &mut AppData) -> Result<()> {
+    data.command_buffers.clear();
+
     let num_images = data.swapchain_images.len();
     for image_index in 0..num_images {
         let allocate_info = vk::CommandBufferAllocateInfo::builder()
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::info;
+
+use crate::asset_cache::{encode_cache, CacheHeader};
+use crate::model::{self, MODEL_EXTENSIONS};
+use crate::pack::write_pack;
+use crate::texture::{self, TEXTURE_EXTENSIONS};
+use crate::vfs::{cache_name, normalize};
+
+/// Converts every model and texture under `source_dir` and writes their caches to a pack, to be
+/// mounted with [`Vfs::mount_pack`](crate::Vfs::mount_pack). Compiled shaders (`.spv`) are packed
+/// as they are, other files are skipped. Touches no GPU, the same sources always give the same
+/// pack. Returns how many files were packed.
+pub fn cook(source_dir: impl AsRef<Path>, pack: impl AsRef<Path>) -> Result<usize> {
+    let source_dir = source_dir.as_ref();
+
+    let mut sources = Vec::new();
+    walk(source_dir, &mut sources)?;
+    sources.sort();
+
+    let mut files = BTreeMap::new();
+    for path in sources {
+        let name = normalize(path.strip_prefix(source_dir)?);
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+
+        let (packed_name, bytes) = if MODEL_EXTENSIONS.contains(&extension) {
+            let header =
+                CacheHeader::for_source(&name, &path, model::importer_settings(extension))?;
+            let model = model::import_model(&path)?;
+            (cache_name(&name), encode_cache(&header, &model)?)
+        } else if TEXTURE_EXTENSIONS.contains(&extension) {
+            let header =
+                CacheHeader::for_source(&name, &path, texture::importer_settings(extension))?;
+            let texture = texture::import_texture(&path)?;
+            (cache_name(&name), encode_cache(&header, &texture)?)
+        } else if extension == "spv" {
+            (name.clone(), std::fs::read(&path)?)
+        } else {
+            continue;
+        };
+
+        info!("Cooked {} as {}", name, packed_name);
+        if files.insert(packed_name.clone(), bytes).is_some() {
+            return Err(anyhow!("two sources are cooked as {}", packed_name));
+        }
+    }
+
+    write_pack(pack.as_ref(), &files)?;
+    Ok(files.len())
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use anyhow::Result;
+use cgmath::{Matrix4, Vector4};
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    app::{AppData, MAX_FRAMES_IN_FLIGHT},
+    depth_pyramid::{compute_barrier, prepare_depth_pyramid},
+    indirect::DrawBuffers,
+    shader::create_shader_module,
+};
+
+/// Matches the workgroup size of the culling shader.
+const GROUP_SIZE: u32 = 64;
+
+/// What the culling pass tests the instances against, laid out as std140.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CullData {
+    /// The camera the depth pyramid was built with.
+    pub(crate) previous_view_proj: Matrix4<f32>,
+    pub(crate) planes: [Vector4<f32>; 6],
+    pub(crate) pyramid_size: [f32; 2],
+    pub(crate) pyramid_levels: u32,
+    pub(crate) instance_count: u32,
+    /// Whether to test against the depth pyramid, only once it is built.
+    pub(crate) occlusion: u32,
+    pub(crate) _padding: [u32; 3],
+}
+
+/// Creates the culling pipeline and a set per frame in flight, kept for the app's lifetime.
+pub(crate) unsafe fn create_cull_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let set_layouts = &[data.cull_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.cull_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let shader_module = create_shader_module(device, &data.vfs, "cull.comp".to_owned())?;
+
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .name(b"main\0");
+
+    let info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage)
+        .layout(data.cull_pipeline_layout);
+
+    data.cull_pipeline = device
+        .create_compute_pipelines(vk::PipelineCache::null(), &[info], None)?
+        .0;
+
+    device.destroy_shader_module(shader_module, None);
+
+    let uniform_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32);
+    let storage_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(5 * MAX_FRAMES_IN_FLIGHT as u32);
+    let image_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::SAMPLED_IMAGE)
+        .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32);
+    let sampler_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::SAMPLER)
+        .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32);
+
+    let pool_sizes = &[uniform_size, storage_size, image_size, sampler_size];
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(pool_sizes)
+        .max_sets(MAX_FRAMES_IN_FLIGHT as u32);
+    data.cull_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+
+    let layouts = vec![data.cull_set_layout; MAX_FRAMES_IN_FLIGHT];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.cull_descriptor_pool)
+        .set_layouts(&layouts);
+    data.cull_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+
+    Ok(())
+}
+
+/// Points a cull set at the buffers of its frame in flight and at the depth pyramid, the set
+/// must not be in use.
+pub(crate) unsafe fn write_cull_descriptor_set(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    buffers: &DrawBuffers,
+    data: &AppData,
+) {
+    let buffer_infos = [
+        buffers.cull_data.buffer,
+        buffers.draws.buffer,
+        buffers.instances.buffer,
+        buffers.commands.buffer,
+        buffers.visible_instances.buffer,
+        buffers.counts.buffer,
+    ]
+    .map(|buffer| {
+        [vk::DescriptorBufferInfo::builder()
+            .buffer(buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE as u64)
+            .build()]
+    });
+
+    let mut writes = buffer_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer_info)| {
+            let descriptor_type = match binding {
+                0 => vk::DescriptorType::UNIFORM_BUFFER,
+                _ => vk::DescriptorType::STORAGE_BUFFER,
+            };
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .descriptor_type(descriptor_type)
+                .buffer_info(buffer_info)
+                .build()
+        })
+        .collect::<Vec<_>>();
+
+    let pyramid_info = &[vk::DescriptorImageInfo::builder()
+        .image_layout(vk::ImageLayout::GENERAL)
+        .image_view(data.depth_pyramid.view)
+        .build()];
+    writes.push(
+        vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(6)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(pyramid_info)
+            .build(),
+    );
+
+    let sampler_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.depth_pyramid_sampler)
+        .build()];
+    writes.push(
+        vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(7)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(sampler_info)
+            .build(),
+    );
+
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
+/// Tests every instance of the frame against the frustum and the depth pyramid, then packs the
+/// visible ones per draw and counts them into the draw commands. Recorded before the passes.
+pub(crate) unsafe fn record_cull_pass(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    data: &AppData,
+    frame: usize,
+    instance_count: u32,
+) {
+    prepare_depth_pyramid(device, command_buffer, data);
+
+    // The pyramid built at the end of the previous frame.
+    compute_barrier(device, command_buffer, vk::AccessFlags::SHADER_WRITE);
+
+    if instance_count > 0 {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            data.cull_pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            data.cull_pipeline_layout,
+            0,
+            &[data.cull_descriptor_sets[frame]],
+            &[],
+        );
+        device.cmd_dispatch(command_buffer, instance_count.div_ceil(GROUP_SIZE), 1, 1);
+    }
+
+    // The draws read the commands and instances, and the scene pass may only overwrite the
+    // depth attachment once the previous pyramid reduction is done with it.
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(
+            vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        );
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::DRAW_INDIRECT
+            | vk::PipelineStageFlags::VERTEX_INPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        vk::DependencyFlags::empty(),
+        &[barrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+}
@@ -0,0 +1,93 @@
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    allocator::{Allocation, Allocator},
+    app::MAX_FRAMES_IN_FLIGHT,
+    geometry_arena::{GeometryArena, GeometryRange},
+};
+
+/// A Vulkan object the frames in flight may still use.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Retired {
+    Buffer(vk::Buffer, Allocation),
+    Image(vk::Image, Allocation),
+    ImageView(vk::ImageView),
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorSet(vk::DescriptorPool, vk::DescriptorSet),
+    DescriptorPool(vk::DescriptorPool),
+    Geometry(GeometryRange),
+}
+
+impl Retired {
+    unsafe fn destroy(self, device: &Device, allocator: &Allocator, geometry: &mut GeometryArena) {
+        match self {
+            Retired::Buffer(buffer, memory) => {
+                device.destroy_buffer(buffer, None);
+                allocator.free(device, memory);
+            }
+            Retired::Image(image, memory) => {
+                device.destroy_image(image, None);
+                allocator.free(device, memory);
+            }
+            Retired::ImageView(image_view) => device.destroy_image_view(image_view, None),
+            Retired::Pipeline(pipeline) => device.destroy_pipeline(pipeline, None),
+            Retired::PipelineLayout(layout) => device.destroy_pipeline_layout(layout, None),
+            Retired::DescriptorSet(pool, set) => {
+                // Only fails for pools created without FREE_DESCRIPTOR_SET.
+                let _ = device.free_descriptor_sets(pool, &[set]);
+            }
+            Retired::DescriptorPool(pool) => device.destroy_descriptor_pool(pool, None),
+            Retired::Geometry(range) => geometry.free(range),
+        }
+    }
+}
+
+/// Destroys retired objects once the fence of a frame submitted after their retirement has
+/// signaled. Submissions to a queue complete in order, so that frame's fence covers every
+/// earlier frame and upload batch that could use them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DeletionQueue {
+    /// Retired since the last frame was submitted.
+    pending: Vec<Retired>,
+    /// Retired before the last submission of each frame in flight.
+    frames: [Vec<Retired>; MAX_FRAMES_IN_FLIGHT],
+}
+
+impl DeletionQueue {
+    pub(crate) fn push(&mut self, retired: Retired) {
+        self.pending.push(retired);
+    }
+
+    /// Ties the objects retired so far to the frame just submitted.
+    pub(crate) fn submitted(&mut self, frame: usize) {
+        self.frames[frame].append(&mut self.pending);
+    }
+
+    /// Destroys what the frame's fence covers, the fence must have signaled.
+    pub(crate) unsafe fn collect(
+        &mut self,
+        device: &Device,
+        allocator: &Allocator,
+        geometry: &mut GeometryArena,
+        frame: usize,
+    ) {
+        self.frames[frame]
+            .drain(..)
+            .for_each(|retired| retired.destroy(device, allocator, geometry));
+    }
+
+    /// Destroys every retired object, the device must be idle.
+    pub(crate) unsafe fn collect_all(
+        &mut self,
+        device: &Device,
+        allocator: &Allocator,
+        geometry: &mut GeometryArena,
+    ) {
+        self.frames
+            .iter_mut()
+            .flat_map(|frame| frame.drain(..))
+            .chain(self.pending.drain(..))
+            .for_each(|retired| retired.destroy(device, allocator, geometry));
+    }
+}
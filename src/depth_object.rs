@@ -2,58 +2,7 @@ use anyhow::{anyhow, Result};
 
 use vulkanalia::prelude::v1_0::*;
 
-use crate::{
-    app::AppData,
-    image_view::create_image_view,
-    texture_image::{create_image, transition_image_layout},
-};
-
-pub(crate) unsafe fn create_depth_objects(
-    instance: &Instance,
-    device: &Device,
-    data: &mut AppData,
-) -> Result<()> {
-    let format = get_depth_format(instance, data)?;
-
-    let (depth_image, depth_image_memory) = create_image(
-        instance,
-        device,
-        data,
-        data.swapchain_extent.width,
-        data.swapchain_extent.height,
-        1,
-        data.msaa_samples,
-        format,
-        vk::ImageTiling::OPTIMAL,
-        vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-        vk::MemoryPropertyFlags::DEVICE_LOCAL,
-    )?;
-
-    data.depth_image = depth_image;
-    data.depth_image_memory = depth_image_memory;
-
-    // Image View
-
-    let aspects = vk::ImageAspectFlags::DEPTH;
-    let mip_levels = 1;
-
-    data.depth_image_view =
-        create_image_view(device, &data.depth_image, &format, &aspects, &mip_levels)?;
-
-    // Explicitly transitioning the depth image
-
-    transition_image_layout(
-        device,
-        data,
-        data.depth_image,
-        format,
-        vk::ImageLayout::UNDEFINED,
-        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-        1,
-    )?;
-
-    Ok(())
-}
+use crate::app::AppData;
 
 pub(crate) unsafe fn get_depth_format(instance: &Instance, data: &AppData) -> Result<vk::Format> {
     let candidates = &[
@@ -0,0 +1,401 @@
+use anyhow::Result;
+use cgmath::Matrix4;
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    allocator::Allocation,
+    app::AppData,
+    deletion_queue::{DeletionQueue, Retired},
+    render_pass::DEPTH_TARGET,
+    shader::create_shader_module,
+    texture_image::create_image,
+};
+
+const FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+/// Matches the workgroup size of the reduction shaders.
+const GROUP_SIZE: u32 = 8;
+
+/// The farthest depth of the last frame over tiles doubling in size with each mip level, the
+/// culling pass tests the instances against it. Level 0 is the largest power of two fitting the
+/// depth attachment.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DepthPyramid {
+    image: vk::Image,
+    memory: Allocation,
+    /// Every level, sampled by the culling pass.
+    pub(crate) view: vk::ImageView,
+    /// Written by the reduction into the level, then sampled by the one into the next.
+    level_views: Vec<vk::ImageView>,
+    descriptor_pool: vk::DescriptorPool,
+    /// One per level, reducing the previous level or the depth attachment into it.
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    pub(crate) extent: vk::Extent2D,
+    pub(crate) levels: u32,
+    /// Bumped whenever recreated, the sets pointing at the previous pyramid must be rewritten.
+    pub(crate) generation: u64,
+    /// View projection of the frame the pyramid was last built from, `None` until it is.
+    pub(crate) view_proj: Option<Matrix4<f32>>,
+}
+
+impl DepthPyramid {
+    pub(crate) fn retire(&mut self, deletion_queue: &mut DeletionQueue) {
+        if self.image.is_null() {
+            return;
+        }
+
+        deletion_queue.push(Retired::DescriptorPool(self.descriptor_pool));
+        self.level_views
+            .drain(..)
+            .chain([self.view])
+            .for_each(|view| deletion_queue.push(Retired::ImageView(view)));
+        deletion_queue.push(Retired::Image(self.image, self.memory));
+
+        *self = Self {
+            generation: self.generation,
+            ..Self::default()
+        };
+    }
+}
+
+/// Whether the depth attachment can be sampled to build the pyramid.
+pub(crate) unsafe fn supports_depth_pyramid(
+    instance: &Instance,
+    data: &AppData,
+    depth_format: vk::Format,
+) -> bool {
+    let properties =
+        instance.get_physical_device_format_properties(data.physical_device, depth_format);
+    let limits = instance
+        .get_physical_device_properties(data.physical_device)
+        .limits;
+
+    properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        && limits
+            .sampled_image_depth_sample_counts
+            .contains(data.msaa_samples)
+}
+
+/// Creates the reduction pipelines and the sampler they and the culling pass read with, kept for
+/// the app's lifetime.
+pub(crate) unsafe fn create_depth_pyramid_pipelines(
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let set_layouts = &[data.depth_pyramid_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.depth_pyramid_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    data.depth_pyramid_pipeline = create_reduction_pipeline(device, data, "depth_pyramid.comp")?;
+    data.depth_pyramid_ms_pipeline =
+        create_reduction_pipeline(device, data, "depth_pyramid_ms.comp")?;
+
+    // Only fetched from, the filtering never applies.
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .max_lod(vk::LOD_CLAMP_NONE);
+    data.depth_pyramid_sampler = device.create_sampler(&info, None)?;
+
+    Ok(())
+}
+
+unsafe fn create_reduction_pipeline(
+    device: &Device,
+    data: &AppData,
+    shader: &str,
+) -> Result<vk::Pipeline> {
+    let shader_module = create_shader_module(device, &data.vfs, shader.to_owned())?;
+
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .name(b"main\0");
+
+    let info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage)
+        .layout(data.depth_pyramid_pipeline_layout);
+
+    let pipeline = device
+        .create_compute_pipelines(vk::PipelineCache::null(), &[info], None)?
+        .0;
+
+    device.destroy_shader_module(shader_module, None);
+
+    Ok(pipeline)
+}
+
+/// Recreates the pyramid for the current depth attachment and retires the previous one. Without
+/// occlusion culling a single texel stands in, so the culling pass always has one to bind.
+pub(crate) unsafe fn create_depth_pyramid(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let mut pyramid = std::mem::take(&mut data.depth_pyramid);
+    pyramid.retire(&mut data.deletion_queue);
+    pyramid.generation += 1;
+
+    pyramid.extent = if data.occlusion_culling {
+        let depth = data
+            .render_graph
+            .attachment_extent(DEPTH_TARGET, data.swapchain_extent)?;
+        vk::Extent2D {
+            width: previous_power_of_two(depth.width),
+            height: previous_power_of_two(depth.height),
+        }
+    } else {
+        vk::Extent2D {
+            width: 1,
+            height: 1,
+        }
+    };
+    pyramid.levels = u32::BITS
+        - pyramid
+            .extent
+            .width
+            .max(pyramid.extent.height)
+            .leading_zeros();
+
+    let (image, memory) = create_image(
+        instance,
+        device,
+        data,
+        pyramid.extent.width,
+        pyramid.extent.height,
+        pyramid.levels,
+        vk::SampleCountFlags::_1,
+        FORMAT,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+    pyramid.image = image;
+    pyramid.memory = memory;
+
+    pyramid.view = create_level_view(device, image, 0, pyramid.levels)?;
+    pyramid.level_views = (0..pyramid.levels)
+        .map(|level| create_level_view(device, image, level, 1))
+        .collect::<Result<Vec<_>>>()?;
+
+    let image_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::SAMPLED_IMAGE)
+        .descriptor_count(pyramid.levels);
+    let sampler_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::SAMPLER)
+        .descriptor_count(pyramid.levels);
+    let storage_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::STORAGE_IMAGE)
+        .descriptor_count(pyramid.levels);
+
+    let pool_sizes = &[image_size, sampler_size, storage_size];
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(pool_sizes)
+        .max_sets(pyramid.levels);
+    pyramid.descriptor_pool = device.create_descriptor_pool(&info, None)?;
+
+    let layouts = vec![data.depth_pyramid_set_layout; pyramid.levels as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(pyramid.descriptor_pool)
+        .set_layouts(&layouts);
+    pyramid.descriptor_sets = device.allocate_descriptor_sets(&info)?;
+
+    if data.occlusion_culling {
+        let depth_view = data.render_graph.image_view(DEPTH_TARGET)?;
+        for level in 0..pyramid.levels as usize {
+            let source = match level {
+                0 => (depth_view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                _ => (pyramid.level_views[level - 1], vk::ImageLayout::GENERAL),
+            };
+            write_reduction_descriptor_set(
+                device,
+                pyramid.descriptor_sets[level],
+                source,
+                data.depth_pyramid_sampler,
+                pyramid.level_views[level],
+            );
+        }
+    }
+
+    data.depth_pyramid = pyramid;
+    Ok(())
+}
+
+unsafe fn create_level_view(
+    device: &Device,
+    image: vk::Image,
+    base_level: u32,
+    levels: u32,
+) -> Result<vk::ImageView> {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(base_level)
+        .level_count(levels)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(vk::ImageViewType::_2D)
+        .format(FORMAT)
+        .subresource_range(subresource_range);
+
+    Ok(device.create_image_view(&info, None)?)
+}
+
+unsafe fn write_reduction_descriptor_set(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    (source, source_layout): (vk::ImageView, vk::ImageLayout),
+    sampler: vk::Sampler,
+    destination: vk::ImageView,
+) {
+    let info = vk::DescriptorImageInfo::builder()
+        .image_layout(source_layout)
+        .image_view(source);
+    let source_info = &[info];
+    let source_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(0)
+        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+        .image_info(source_info);
+
+    let info = vk::DescriptorImageInfo::builder().sampler(sampler);
+    let sampler_info = &[info];
+    let sampler_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(1)
+        .descriptor_type(vk::DescriptorType::SAMPLER)
+        .image_info(sampler_info);
+
+    let info = vk::DescriptorImageInfo::builder()
+        .image_layout(vk::ImageLayout::GENERAL)
+        .image_view(destination);
+    let destination_info = &[info];
+    let destination_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(2)
+        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+        .image_info(destination_info);
+
+    device.update_descriptor_sets(
+        &[source_write, sampler_write, destination_write],
+        &[] as &[vk::CopyDescriptorSet],
+    );
+}
+
+/// Moves a pyramid that was never built to the layout the culling pass samples it in, its
+/// contents are discarded. Recorded before the culling pass.
+pub(crate) unsafe fn prepare_depth_pyramid(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    data: &AppData,
+) {
+    let pyramid = &data.depth_pyramid;
+    if pyramid.view_proj.is_some() {
+        return;
+    }
+
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(pyramid.levels)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::GENERAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(pyramid.image)
+        .subresource_range(subresource_range)
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+}
+
+/// Reduces the depth attachment the scene pass just wrote into every level of the pyramid, for
+/// the culling pass of the next frame. `view_proj` is the camera the scene was drawn with.
+pub(crate) unsafe fn record_depth_pyramid(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    data: &mut AppData,
+    view_proj: Matrix4<f32>,
+) {
+    // The culling pass of this frame is done sampling the previous pyramid.
+    compute_barrier(device, command_buffer, vk::AccessFlags::empty());
+
+    let pyramid = &data.depth_pyramid;
+    for level in 0..pyramid.levels {
+        let pipeline = if level == 0 && data.msaa_samples != vk::SampleCountFlags::_1 {
+            data.depth_pyramid_ms_pipeline
+        } else {
+            data.depth_pyramid_pipeline
+        };
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            data.depth_pyramid_pipeline_layout,
+            0,
+            &[pyramid.descriptor_sets[level as usize]],
+            &[],
+        );
+
+        let width = (pyramid.extent.width >> level).max(1);
+        let height = (pyramid.extent.height >> level).max(1);
+        device.cmd_dispatch(
+            command_buffer,
+            width.div_ceil(GROUP_SIZE),
+            height.div_ceil(GROUP_SIZE),
+            1,
+        );
+
+        compute_barrier(device, command_buffer, vk::AccessFlags::SHADER_WRITE);
+    }
+
+    data.depth_pyramid.view_proj = Some(view_proj);
+}
+
+/// Makes the compute shaders recorded next wait for the previous ones, and see `src_access`.
+pub(crate) unsafe fn compute_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    src_access: vk::AccessFlags,
+) {
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(src_access)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[barrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+}
+
+fn previous_power_of_two(value: u32) -> u32 {
+    match value {
+        0 => 1,
+        value => 1 << (u32::BITS - 1 - value.leading_zeros()),
+    }
+}
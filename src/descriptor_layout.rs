@@ -4,6 +4,7 @@ use vulkanalia::prelude::v1_0::*;
 
 use crate::app::AppData;
 
+/// Set 0, bound once per frame.
 pub(crate) unsafe fn create_descriptor_set_layout(
     device: &Device,
     data: &mut AppData,
@@ -14,16 +15,112 @@ pub(crate) unsafe fn create_descriptor_set_layout(
         .descriptor_count(1)
         .stage_flags(vk::ShaderStageFlags::VERTEX);
 
+    let bindings = &[ubo_binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+
+    data.descriptor_set_layout = device.create_descriptor_set_layout(&info, None)?;
+
+    Ok(())
+}
+
+/// Set 1, bound once per batch of draws.
+pub(crate) unsafe fn create_material_set_layout(device: &Device, data: &mut AppData) -> Result<()> {
+    let texture_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+
     let sampler_binding = vk::DescriptorSetLayoutBinding::builder()
         .binding(1)
-        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_type(vk::DescriptorType::SAMPLER)
         .descriptor_count(1)
         .stage_flags(vk::ShaderStageFlags::FRAGMENT);
 
-    let bindings = &[ubo_binding, sampler_binding];
+    let bindings = &[texture_binding, sampler_binding];
     let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
 
-    data.descriptor_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    data.material_set_layout = device.create_descriptor_set_layout(&info, None)?;
+
+    Ok(())
+}
+
+/// Set 2, the draw data and joint matrices of a frame in flight.
+pub(crate) unsafe fn create_draw_set_layout(device: &Device, data: &mut AppData) -> Result<()> {
+    let draws_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+
+    let joints_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(1)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::VERTEX);
+
+    let bindings = &[draws_binding, joints_binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+
+    data.draw_set_layout = device.create_descriptor_set_layout(&info, None)?;
+
+    Ok(())
+}
+
+/// The culling pass: its parameters, the draw buffers of a frame in flight and the depth
+/// pyramid.
+pub(crate) unsafe fn create_cull_set_layout(device: &Device, data: &mut AppData) -> Result<()> {
+    let bindings = (0..8)
+        .map(|binding| {
+            let descriptor_type = match binding {
+                0 => vk::DescriptorType::UNIFORM_BUFFER,
+                1..=5 => vk::DescriptorType::STORAGE_BUFFER,
+                6 => vk::DescriptorType::SAMPLED_IMAGE,
+                _ => vk::DescriptorType::SAMPLER,
+            };
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        })
+        .collect::<Vec<_>>();
+
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+
+    data.cull_set_layout = device.create_descriptor_set_layout(&info, None)?;
+
+    Ok(())
+}
+
+/// A level of the depth pyramid: the level or depth attachment it is reduced from, and itself.
+pub(crate) unsafe fn create_depth_pyramid_set_layout(
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let source_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::COMPUTE);
+
+    let sampler_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(1)
+        .descriptor_type(vk::DescriptorType::SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::COMPUTE);
+
+    let destination_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(2)
+        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::COMPUTE);
+
+    let bindings = &[source_binding, sampler_binding, destination_binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+
+    data.depth_pyramid_set_layout = device.create_descriptor_set_layout(&info, None)?;
 
     Ok(())
 }
@@ -2,18 +2,20 @@ use anyhow::Result;
 
 use vulkanalia::prelude::v1_0::*;
 
-use crate::{app::AppData, texture::Texture, uniform_buffer::UniformBufferObject};
+use crate::{
+    app::{AppData, MAX_FRAMES_IN_FLIGHT},
+    uniform_buffer::UniformBufferObject,
+};
+
+/// Upper bound of materials alive at once.
+pub(crate) const MAX_MATERIALS: u32 = 1024;
 
 pub(crate) unsafe fn create_descriptor_pool(device: &Device, data: &mut AppData) -> Result<()> {
     let ubo_size = vk::DescriptorPoolSize::builder()
         .type_(vk::DescriptorType::UNIFORM_BUFFER)
         .descriptor_count(data.swapchain_images.len() as u32);
 
-    let sampler_size = vk::DescriptorPoolSize::builder()
-        .type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-        .descriptor_count(data.swapchain_images.len() as u32);
-
-    let pool_sizes = &[ubo_size, sampler_size];
+    let pool_sizes = &[ubo_size];
     let info = vk::DescriptorPoolCreateInfo::builder()
         .pool_sizes(pool_sizes)
         .max_sets(data.swapchain_images.len() as u32);
@@ -23,12 +25,7 @@ pub(crate) unsafe fn create_descriptor_pool(device: &Device, data: &mut AppData)
     Ok(())
 }
 
-pub(crate) unsafe fn create_descriptor_sets(
-    device: &Device,
-    data: &mut AppData,
-    textures: &[&Texture],
-    sampler: &vk::Sampler,
-) -> Result<()> {
+pub(crate) unsafe fn create_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
     let layouts = vec![data.descriptor_set_layout; data.swapchain_images.len()];
     let info = vk::DescriptorSetAllocateInfo::builder()
         .descriptor_pool(data.descriptor_pool)
@@ -50,26 +47,145 @@ pub(crate) unsafe fn create_descriptor_sets(
             .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
             .buffer_info(buffer_info);
 
-        for texture in textures {
-            let info = vk::DescriptorImageInfo::builder()
-                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .image_view(texture.image_view)
-                .sampler(*sampler);
-
-            let image_info = &[info];
-            let sampler_write = vk::WriteDescriptorSet::builder()
-                .dst_set(data.descriptor_sets[i])
-                .dst_binding(1)
-                .dst_array_element(0)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .image_info(image_info);
-
-            device.update_descriptor_sets(
-                &[ubo_write, sampler_write],
-                &[] as &[vk::CopyDescriptorSet],
-            );
-        }
+        device.update_descriptor_sets(&[ubo_write], &[] as &[vk::CopyDescriptorSet]);
     }
 
     Ok(())
 }
+
+/// Material sets outlive the swapchain, so they come from their own pool.
+pub(crate) unsafe fn create_material_descriptor_pool(
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let texture_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::SAMPLED_IMAGE)
+        .descriptor_count(MAX_MATERIALS * 2);
+
+    let sampler_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::SAMPLER)
+        .descriptor_count(MAX_MATERIALS * 2);
+
+    // Twice as many sets, for the sets replaced while the frames in flight still bind them.
+    let pool_sizes = &[texture_size, sampler_size];
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        .pool_sizes(pool_sizes)
+        .max_sets(MAX_MATERIALS * 2);
+
+    data.material_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+
+    Ok(())
+}
+
+pub(crate) unsafe fn allocate_material_descriptor_set(
+    device: &Device,
+    data: &AppData,
+) -> Result<vk::DescriptorSet> {
+    let layouts = &[data.material_set_layout];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.material_descriptor_pool)
+        .set_layouts(layouts);
+
+    Ok(device.allocate_descriptor_sets(&info)?[0])
+}
+
+/// Points a material set at its texture and sampler, the set must not be in use.
+pub(crate) unsafe fn write_material_descriptor_set(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    image_view: vk::ImageView,
+    sampler: vk::Sampler,
+) {
+    let info = vk::DescriptorImageInfo::builder()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(image_view);
+
+    let image_info = &[info];
+    let texture_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(0)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+        .image_info(image_info);
+
+    let info = vk::DescriptorImageInfo::builder().sampler(sampler);
+
+    let sampler_info = &[info];
+    let sampler_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(1)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::SAMPLER)
+        .image_info(sampler_info);
+
+    device.update_descriptor_sets(
+        &[texture_write, sampler_write],
+        &[] as &[vk::CopyDescriptorSet],
+    );
+}
+
+/// One draw set per frame in flight, pointed at its buffers once created.
+pub(crate) unsafe fn create_draw_descriptor_sets(
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let storage_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(2 * MAX_FRAMES_IN_FLIGHT as u32);
+
+    let pool_sizes = &[storage_size];
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(pool_sizes)
+        .max_sets(MAX_FRAMES_IN_FLIGHT as u32);
+
+    data.draw_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+
+    let layouts = vec![data.draw_set_layout; MAX_FRAMES_IN_FLIGHT];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.draw_descriptor_pool)
+        .set_layouts(&layouts);
+
+    data.draw_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+
+    Ok(())
+}
+
+/// Points a draw set at its draws and joints storage buffers, the set must not be in use.
+pub(crate) unsafe fn write_draw_descriptor_set(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    draws: vk::Buffer,
+    joints: vk::Buffer,
+) {
+    let info = vk::DescriptorBufferInfo::builder()
+        .buffer(draws)
+        .offset(0)
+        .range(vk::WHOLE_SIZE as u64);
+
+    let draws_info = &[info];
+    let draws_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(0)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .buffer_info(draws_info);
+
+    let info = vk::DescriptorBufferInfo::builder()
+        .buffer(joints)
+        .offset(0)
+        .range(vk::WHOLE_SIZE as u64);
+
+    let joints_info = &[info];
+    let joints_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(1)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .buffer_info(joints_info);
+
+    device.update_descriptor_sets(
+        &[draws_write, joints_write],
+        &[] as &[vk::CopyDescriptorSet],
+    );
+}
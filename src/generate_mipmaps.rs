@@ -2,22 +2,25 @@ use anyhow::{anyhow, Result};
 
 use vulkanalia::prelude::v1_0::*;
 
-use crate::{
-    app::AppData,
-    single_time_cmd::{begin_single_time_commands, end_single_time_commands},
-};
+use crate::app::AppData;
+use crate::upload::Release;
 
 // OPTIMIZE pregenerate and store texture file mipmaps alongside the base level to improve loading speed
 pub(crate) unsafe fn generate_mipmaps(
     instance: &Instance,
     device: &Device,
     data: &AppData,
-    image: vk::Image,
-    format: vk::Format,
-    width: u32,
-    height: u32,
-    mip_levels: u32,
+    command_buffer: vk::CommandBuffer,
+    release: Release,
 ) -> Result<()> {
+    let Release {
+        image,
+        format,
+        width,
+        height,
+        mip_levels,
+    } = release;
+
     if !instance
         .get_physical_device_format_properties(data.physical_device, format)
         .optimal_tiling_features
@@ -28,8 +31,6 @@ pub(crate) unsafe fn generate_mipmaps(
         ));
     }
 
-    let command_buffer = begin_single_time_commands(device, data)?;
-
     let subresource = vk::ImageSubresourceRange::builder()
         .aspect_mask(vk::ImageAspectFlags::COLOR)
         .base_array_layer(0)
@@ -146,7 +147,5 @@ pub(crate) unsafe fn generate_mipmaps(
         &[barrier],
     );
 
-    end_single_time_commands(device, data, command_buffer)?;
-
     Ok(())
 }
@@ -0,0 +1,329 @@
+use anyhow::Result;
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    allocator::{Allocation, Allocator, FreeList},
+    app::AppData,
+    deletion_queue::Retired,
+    upload::{stage, upload_command_buffer},
+    vertex::{SkinnedVertex, Vertex},
+    vertex_buffer::create_buffer,
+};
+
+const INITIAL_VERTEX_CAPACITY: u64 = 256 * 1024;
+const INITIAL_SKINNED_VERTEX_CAPACITY: u64 = 64 * 1024;
+const INITIAL_INDEX_CAPACITY: u64 = 1024 * 1024;
+
+/// Where the vertices and indices of a mesh live in the geometry arena.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct GeometryRange {
+    pub(crate) first_index: u32,
+    pub(crate) index_count: u32,
+    pub(crate) vertex_offset: i32,
+    pub(crate) vertex_count: u32,
+    /// Whether the vertices live in the skinned vertex buffer.
+    pub(crate) skinned: bool,
+}
+
+/// The vertices and indices of every mesh, sub-allocated from one vertex and one index buffer
+/// so a single bind serves the whole scene. Skinned meshes have their own vertex buffer and
+/// share the index buffer.
+#[derive(Clone, Debug)]
+pub(crate) struct GeometryArena {
+    pub(crate) vertices: ArenaBuffer,
+    pub(crate) skinned_vertices: ArenaBuffer,
+    pub(crate) indices: ArenaBuffer,
+}
+
+impl Default for GeometryArena {
+    fn default() -> Self {
+        Self {
+            vertices: ArenaBuffer::new(
+                std::mem::size_of::<Vertex>() as u64,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                INITIAL_VERTEX_CAPACITY,
+            ),
+            skinned_vertices: ArenaBuffer::new(
+                std::mem::size_of::<SkinnedVertex>() as u64,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                INITIAL_SKINNED_VERTEX_CAPACITY,
+            ),
+            indices: ArenaBuffer::new(
+                std::mem::size_of::<u32>() as u64,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                INITIAL_INDEX_CAPACITY,
+            ),
+        }
+    }
+}
+
+impl GeometryArena {
+    /// Returns the ranges of a retired mesh, the GPU must be done with them.
+    pub(crate) fn free(&mut self, range: GeometryRange) {
+        self.vertex_buffer(range.skinned)
+            .free(range.vertex_offset as u64, range.vertex_count as u64);
+        self.indices
+            .free(range.first_index as u64, range.index_count as u64);
+    }
+
+    pub(crate) unsafe fn destroy(&mut self, device: &Device, allocator: &Allocator) {
+        self.vertices.destroy(device, allocator);
+        self.skinned_vertices.destroy(device, allocator);
+        self.indices.destroy(device, allocator);
+    }
+
+    fn vertex_buffer(&mut self, skinned: bool) -> &mut ArenaBuffer {
+        if skinned {
+            &mut self.skinned_vertices
+        } else {
+            &mut self.vertices
+        }
+    }
+}
+
+/// A device local buffer that doubles when full. Ranges are counted in elements.
+#[derive(Clone, Debug)]
+pub(crate) struct ArenaBuffer {
+    pub(crate) buffer: vk::Buffer,
+    memory: Allocation,
+    stride: u64,
+    usage: vk::BufferUsageFlags,
+    initial_capacity: u64,
+    capacity: u64,
+    free: FreeList,
+}
+
+impl ArenaBuffer {
+    fn new(stride: u64, usage: vk::BufferUsageFlags, initial_capacity: u64) -> Self {
+        Self {
+            buffer: vk::Buffer::null(),
+            memory: Allocation::default(),
+            stride,
+            usage,
+            initial_capacity,
+            capacity: 0,
+            free: FreeList::default(),
+        }
+    }
+
+    /// Sub-allocates `len` elements, growing the buffer if needed. Returns the first one.
+    unsafe fn allocate(
+        &mut self,
+        len: u64,
+        instance: &Instance,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<u64> {
+        if len == 0 {
+            return Ok(0);
+        }
+
+        if let Some(first) = self.free.allocate(len, 1) {
+            return Ok(first);
+        }
+
+        self.grow(len, instance, device, data)?;
+        Ok(self
+            .free
+            .allocate(len, 1)
+            .expect("Grown arena fits the range"))
+    }
+
+    fn free(&mut self, first: u64, len: u64) {
+        if len > 0 {
+            self.free.free(first, len);
+        }
+    }
+
+    /// Moves the elements to a buffer with room for `len` more, the old buffer is retired. The
+    /// ranges keep their offsets.
+    unsafe fn grow(
+        &mut self,
+        len: u64,
+        instance: &Instance,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        let mut capacity = self.initial_capacity.max(self.capacity * 2);
+        while capacity < self.capacity + len {
+            capacity *= 2;
+        }
+
+        let (buffer, memory) = create_buffer(
+            instance,
+            device,
+            data,
+            capacity * self.stride,
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST | self.usage,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        if self.capacity > 0 {
+            let command_buffer = upload_command_buffer(device, data)?;
+
+            // The writes recorded before the move land in the old buffer first, the ones after
+            // it are not overwritten by it.
+            transfer_barrier(device, command_buffer);
+            let region = vk::BufferCopy::builder().size(self.capacity * self.stride);
+            device.cmd_copy_buffer(command_buffer, self.buffer, buffer, &[region]);
+            transfer_barrier(device, command_buffer);
+
+            data.deletion_queue
+                .push(Retired::Buffer(self.buffer, self.memory));
+        }
+
+        self.free.free(self.capacity, capacity - self.capacity);
+        self.buffer = buffer;
+        self.memory = memory;
+        self.capacity = capacity;
+
+        Ok(())
+    }
+
+    /// Copies `values` to the range starting at `first` with the current upload batch.
+    unsafe fn write<T: Copy>(
+        &self,
+        first: u64,
+        values: &[T],
+        instance: &Instance,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        let (staging_buffer, staging_offset) = stage(instance, device, data, values)?;
+
+        let command_buffer = upload_command_buffer(device, data)?;
+        let region = vk::BufferCopy::builder()
+            .src_offset(staging_offset)
+            .dst_offset(first * self.stride)
+            .size(std::mem::size_of_val(values) as u64);
+        device.cmd_copy_buffer(command_buffer, staging_buffer, self.buffer, &[region]);
+
+        Ok(())
+    }
+
+    unsafe fn destroy(&mut self, device: &Device, allocator: &Allocator) {
+        if self.capacity > 0 {
+            device.destroy_buffer(self.buffer, None);
+            allocator.free(device, self.memory);
+        }
+        self.buffer = vk::Buffer::null();
+        self.capacity = 0;
+        self.free = FreeList::default();
+    }
+}
+
+unsafe fn transfer_barrier(device: &Device, command_buffer: vk::CommandBuffer) {
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[barrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+}
+
+/// Copies a mesh into the geometry arena with the current upload batch.
+pub(crate) unsafe fn upload_mesh(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    vertices: &[Vertex],
+    indices: &[u32],
+) -> Result<GeometryRange> {
+    upload_mesh_into_arena(instance, device, data, vertices, indices, false)
+}
+
+/// Copies a skinned mesh into the skinned vertices of the geometry arena.
+pub(crate) unsafe fn upload_skinned_mesh(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    vertices: &[SkinnedVertex],
+    indices: &[u32],
+) -> Result<GeometryRange> {
+    upload_mesh_into_arena(instance, device, data, vertices, indices, true)
+}
+
+unsafe fn upload_mesh_into_arena<T: Copy>(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    vertices: &[T],
+    indices: &[u32],
+    skinned: bool,
+) -> Result<GeometryRange> {
+    // The arena needs the rest of the app data to grow and stage.
+    let mut geometry = std::mem::take(&mut data.geometry);
+    let range = upload_mesh_into(
+        &mut geometry,
+        instance,
+        device,
+        data,
+        vertices,
+        indices,
+        skinned,
+    );
+    data.geometry = geometry;
+    range
+}
+
+unsafe fn upload_mesh_into<T: Copy>(
+    geometry: &mut GeometryArena,
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    vertices: &[T],
+    indices: &[u32],
+    skinned: bool,
+) -> Result<GeometryRange> {
+    let vertex_offset =
+        geometry
+            .vertex_buffer(skinned)
+            .allocate(vertices.len() as u64, instance, device, data)?;
+    let first_index = match geometry
+        .indices
+        .allocate(indices.len() as u64, instance, device, data)
+    {
+        Ok(first_index) => first_index,
+        Err(e) => {
+            geometry
+                .vertex_buffer(skinned)
+                .free(vertex_offset, vertices.len() as u64);
+            return Err(e);
+        }
+    };
+
+    let range = GeometryRange {
+        first_index: first_index as u32,
+        index_count: indices.len() as u32,
+        vertex_offset: vertex_offset as i32,
+        vertex_count: vertices.len() as u32,
+        skinned,
+    };
+
+    let written = geometry
+        .vertex_buffer(skinned)
+        .write(vertex_offset, vertices, instance, device, data)
+        .and_then(|_| {
+            geometry
+                .indices
+                .write(first_index, indices, instance, device, data)
+        });
+    if let Err(e) = written {
+        // A copy into the ranges may already be recorded.
+        data.deletion_queue.push(Retired::Geometry(range));
+        return Err(e);
+    }
+
+    Ok(range)
+}
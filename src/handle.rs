@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// Identifies a model or texture of an [`App`](crate::App) whatever its load state, stays valid
+/// until the asset is unloaded.
+pub struct Handle<T> {
+    id: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+// Not derived, which would require `T` to implement them too.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.id).finish()
+    }
+}
+
+/// Where an asset is at. A placeholder is drawn until it is `Ready`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    /// Waiting for the streaming thread.
+    Queued,
+    /// Being read and decoded by the streaming thread.
+    Loading,
+    Ready,
+    /// Why it failed, it stays failed until unloaded.
+    Failed(String),
+}
+
+impl LoadState {
+    /// Queued or loading.
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Queued | Self::Loading)
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    name: String,
+    state: LoadState,
+    /// Only `Some` once `Ready`.
+    asset: Option<T>,
+}
+
+/// Assets by handle, whatever their load state, and the handles by name.
+#[derive(Debug)]
+pub(crate) struct AssetStore<T> {
+    slots: HashMap<Handle<T>, Slot<T>>,
+    handles: HashMap<String, Handle<T>>,
+    next_id: u64,
+}
+
+impl<T> Default for AssetStore<T> {
+    fn default() -> Self {
+        Self {
+            slots: HashMap::new(),
+            handles: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> AssetStore<T> {
+    /// A queued handle for the name, `None` when the name is in use.
+    pub(crate) fn reserve(&mut self, name: &str) -> Option<Handle<T>> {
+        if self.handles.contains_key(name) {
+            return None;
+        }
+
+        let handle = Handle {
+            id: self.next_id,
+            _marker: PhantomData,
+        };
+        self.next_id += 1;
+
+        self.handles.insert(name.to_owned(), handle);
+        self.slots.insert(
+            handle,
+            Slot {
+                name: name.to_owned(),
+                state: LoadState::Queued,
+                asset: None,
+            },
+        );
+        Some(handle)
+    }
+
+    /// A ready handle for the asset, `None` when the name is in use.
+    pub(crate) fn insert(&mut self, name: &str, asset: T) -> Option<Handle<T>> {
+        let handle = self.reserve(name)?;
+        let slot = self.slots.get_mut(&handle)?;
+        slot.state = LoadState::Ready;
+        slot.asset = Some(asset);
+        Some(handle)
+    }
+
+    /// Makes a reserved asset ready. The asset is given back when it was unloaded in the
+    /// meantime.
+    pub(crate) fn fill(&mut self, handle: Handle<T>, asset: T) -> Result<(), T> {
+        match self.slots.get_mut(&handle) {
+            Some(slot) if slot.asset.is_none() => {
+                slot.state = LoadState::Ready;
+                slot.asset = Some(asset);
+                Ok(())
+            }
+            _ => Err(asset),
+        }
+    }
+
+    /// Moves a reserved asset along, ignored once it was unloaded.
+    pub(crate) fn set_state(&mut self, handle: Handle<T>, state: LoadState) {
+        if let Some(slot) = self.slots.get_mut(&handle) {
+            if slot.asset.is_none() {
+                slot.state = state;
+            }
+        }
+    }
+
+    pub(crate) fn handle(&self, name: &str) -> Option<Handle<T>> {
+        self.handles.get(name).copied()
+    }
+
+    pub(crate) fn name(&self, handle: Handle<T>) -> Option<&str> {
+        Some(&self.slots.get(&handle)?.name)
+    }
+
+    pub(crate) fn state(&self, handle: Handle<T>) -> Option<&LoadState> {
+        Some(&self.slots.get(&handle)?.state)
+    }
+
+    /// Load state of the name, `None` when nothing has the name.
+    pub(crate) fn state_of(&self, name: &str) -> Option<&LoadState> {
+        self.state(self.handle(name)?)
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.handles.contains_key(name)
+    }
+
+    /// The asset once ready.
+    pub(crate) fn get(&self, name: &str) -> Option<&T> {
+        self.slots.get(self.handles.get(name)?)?.asset.as_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.slots.get_mut(self.handles.get(name)?)?.asset.as_mut()
+    }
+
+    /// Forgets the name, returns the asset if it was ready.
+    pub(crate) fn remove(&mut self, name: &str) -> Option<Option<T>> {
+        let handle = self.handles.remove(name)?;
+        Some(self.slots.remove(&handle)?.asset)
+    }
+
+    /// Queued or loading assets.
+    pub(crate) fn pending(&self) -> usize {
+        self.slots
+            .values()
+            .filter(|slot| slot.state.is_pending())
+            .count()
+    }
+
+    /// Every ready asset.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.handles.clear();
+        self.slots.drain().filter_map(|(_, slot)| slot.asset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_reuses_handles() {
+        let mut store = AssetStore::default();
+        let removed = store.insert("cube", 1).unwrap();
+        assert_eq!(store.remove("cube"), Some(Some(1)));
+
+        let reserved = store.reserve("cube").unwrap();
+
+        assert_ne!(reserved, removed);
+        assert_eq!(store.state(removed), None);
+        assert_eq!(store.handle("cube"), Some(reserved));
+        // A load finishing after the unload is handed back.
+        assert_eq!(store.fill(removed, 2), Err(2));
+        assert_eq!(store.state(reserved), Some(&LoadState::Queued));
+    }
+
+    #[test]
+    fn keeps_names_unique() {
+        let mut store = AssetStore::default();
+        store.reserve("cube").unwrap();
+
+        assert_eq!(store.reserve("cube"), None);
+        assert_eq!(store.insert("cube", 1), None);
+    }
+
+    #[test]
+    fn moves_through_load_states() {
+        let mut store = AssetStore::default();
+        let handle = store.reserve("cube").unwrap();
+        assert_eq!(store.pending(), 1);
+        assert_eq!(store.get("cube"), None);
+
+        store.set_state(handle, LoadState::Loading);
+        assert_eq!(store.state_of("cube"), Some(&LoadState::Loading));
+
+        store.fill(handle, 1).unwrap();
+        assert_eq!(store.state(handle), Some(&LoadState::Ready));
+        assert_eq!(store.get("cube"), Some(&1));
+        assert_eq!(store.pending(), 0);
+
+        // Ready stays ready until unloaded.
+        store.set_state(handle, LoadState::Failed("late".to_owned()));
+        assert_eq!(store.state(handle), Some(&LoadState::Ready));
+        assert_eq!(store.fill(handle, 2), Err(2));
+    }
+}
@@ -0,0 +1,461 @@
+use anyhow::Result;
+
+use cgmath::Matrix4;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    allocator::{Allocation, Allocator},
+    app::AppData,
+    assets::{ActiveModel, Assets},
+    culling::{write_cull_descriptor_set, CullData},
+    descriptor_pool::write_draw_descriptor_set,
+    mesh::Mesh,
+    metrics::DrawCounts,
+    model::Model,
+    vertex::DrawInstance,
+    vertex_buffer::create_buffer,
+};
+
+const MIN_BUFFER_CAPACITY: u64 = 4096;
+
+/// Per draw data the shaders read from the storage buffer of set 2, laid out as std430.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct DrawData {
+    pub(crate) model: Matrix4<f32>,
+    pub(crate) base_color_factor: [f32; 4],
+    pub(crate) opacity: f32,
+    /// Where the instances of the draw start, whether the command says so or not.
+    pub(crate) first_instance: u32,
+    /// Where the joint matrices of a skinned draw start in the joints buffer.
+    pub(crate) first_joint: u32,
+    pub(crate) _padding: u32,
+    /// Bounds of the mesh in model space, the sphere radius in `w`.
+    pub(crate) sphere: [f32; 4],
+    pub(crate) aabb_min: [f32; 4],
+    pub(crate) aabb_max: [f32; 4],
+}
+
+/// Consecutive draw commands sharing a material and a pipeline.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct DrawBatch {
+    pub(crate) material_set: vk::DescriptorSet,
+    /// Drawn with the skinned pipeline and vertices.
+    pub(crate) skinned: bool,
+    pub(crate) first_command: u32,
+    pub(crate) command_count: u32,
+}
+
+/// The draws of a frame, one command per mesh of every spawned model. The culling pass fills in
+/// the instance counts of the commands.
+#[derive(Debug, Default)]
+pub(crate) struct SceneDraws {
+    pub(crate) commands: Vec<vk::DrawIndexedIndirectCommand>,
+    pub(crate) draws: Vec<DrawData>,
+    /// The instances of each draw, the command's `first_instance` points at them.
+    pub(crate) instances: Vec<DrawInstance>,
+    /// The pose of every spawned skinned model, one after the other.
+    pub(crate) joints: Vec<Matrix4<f32>>,
+    pub(crate) batches: Vec<DrawBatch>,
+}
+
+impl SceneDraws {
+    /// Opaque draws are grouped by pipeline and material so batches stay large, translucent ones
+    /// follow in scene order.
+    pub(crate) fn build(&mut self, assets: &Assets) {
+        self.clear();
+
+        let mut draws = Vec::new();
+        for active_model in &assets.active_models {
+            // Spawned while still streaming, or failed.
+            let (model, placeholder) = match assets.models.get(&active_model.name) {
+                Some(model) => (model, false),
+                None => match &assets.placeholder_model {
+                    Some(model) => (model, true),
+                    None => continue,
+                },
+            };
+            if model.instances.len() == 0 {
+                continue;
+            }
+
+            // Not posed yet when the model was inserted this frame.
+            let joints = &active_model.animation.joint_matrices;
+            let posed = model.skeleton.as_ref().is_some_and(|skeleton| {
+                skeleton.joints.len() == joints.len() && !joints.is_empty()
+            });
+            let first_joint = self.joints.len() as u32;
+            if posed {
+                self.joints.extend(joints);
+            }
+
+            for mesh in &model.meshes {
+                if mesh.range.skinned && !posed {
+                    continue;
+                }
+
+                let material = mesh
+                    .material
+                    .as_ref()
+                    .and_then(|name| assets.materials.get(name));
+                let material_set = match material {
+                    Some(material) => material.descriptor_set,
+                    None if placeholder => assets.placeholder_material,
+                    None => assets.default_material,
+                };
+                let base_color_factor = material
+                    .map(|material| material.desc.base_color_factor)
+                    .unwrap_or([1.0; 4]);
+                let translucent = active_model.opacity < 1.0 || base_color_factor[3] < 1.0;
+
+                draws.push(PendingDraw {
+                    translucent,
+                    skinned: mesh.range.skinned,
+                    first_joint,
+                    material_set,
+                    base_color_factor,
+                    active_model,
+                    model,
+                    mesh,
+                });
+            }
+        }
+
+        draws.sort_by_key(|draw| {
+            let (skinned, material) = if draw.translucent {
+                (false, 0)
+            } else {
+                (draw.skinned, draw.material_set.as_raw())
+            };
+            (draw.translucent, skinned, material)
+        });
+
+        for draw in draws {
+            let draw_index = self.draws.len() as u32;
+            let first_instance = self.instances.len() as u32;
+
+            self.instances.extend(
+                draw.model
+                    .instances
+                    .data
+                    .iter()
+                    .map(|instance| DrawInstance {
+                        model_matrix: instance.model_matrix,
+                        draw_index,
+                        _padding: [0; 3],
+                    }),
+            );
+
+            let bounds = if draw.skinned {
+                draw.mesh
+                    .bounds
+                    .posed(&draw.active_model.animation.joint_matrices)
+            } else {
+                draw.mesh.bounds
+            };
+            let center = bounds.sphere.center;
+            self.draws.push(DrawData {
+                model: draw.active_model.transform.matrix(),
+                base_color_factor: draw.base_color_factor,
+                opacity: draw.active_model.opacity,
+                first_instance,
+                first_joint: draw.first_joint,
+                _padding: 0,
+                sphere: [center.x, center.y, center.z, bounds.sphere.radius],
+                aabb_min: bounds.aabb.min.to_homogeneous().into(),
+                aabb_max: bounds.aabb.max.to_homogeneous().into(),
+            });
+
+            // Counted up by the culling pass.
+            self.commands.push(vk::DrawIndexedIndirectCommand {
+                index_count: draw.mesh.range.index_count,
+                instance_count: 0,
+                first_index: draw.mesh.range.first_index,
+                vertex_offset: draw.mesh.range.vertex_offset,
+                first_instance,
+            });
+
+            match self.batches.last_mut() {
+                Some(batch)
+                    if batch.material_set == draw.material_set && batch.skinned == draw.skinned =>
+                {
+                    batch.command_count += 1
+                }
+                _ => self.batches.push(DrawBatch {
+                    material_set: draw.material_set,
+                    skinned: draw.skinned,
+                    first_command: draw_index,
+                    command_count: 1,
+                }),
+            }
+        }
+    }
+
+    /// Nothing is drawn.
+    pub(crate) fn clear(&mut self) {
+        self.commands.clear();
+        self.draws.clear();
+        self.instances.clear();
+        self.joints.clear();
+        self.batches.clear();
+    }
+}
+
+struct PendingDraw<'a> {
+    translucent: bool,
+    skinned: bool,
+    first_joint: u32,
+    material_set: vk::DescriptorSet,
+    base_color_factor: [f32; 4],
+    active_model: &'a ActiveModel,
+    model: &'a Model,
+    mesh: &'a Mesh,
+}
+
+/// The buffers the draws of one frame in flight are copied to, and the culling pass writes.
+#[derive(Debug, Default)]
+pub(crate) struct DrawBuffers {
+    pub(crate) commands: MappedBuffer,
+    pub(crate) draws: MappedBuffer,
+    pub(crate) joints: MappedBuffer,
+    /// Every instance of every draw, read by the culling pass.
+    pub(crate) instances: MappedBuffer,
+    /// The instances left by the culling pass, packed at the start of the range of each draw.
+    pub(crate) visible_instances: DeviceBuffer,
+    /// Instances drawn and culled, counted by the culling pass.
+    pub(crate) counts: MappedBuffer,
+    pub(crate) cull_data: MappedBuffer,
+    /// Generation of the depth pyramid the cull set points at.
+    cull_set_pyramid: Option<u64>,
+}
+
+impl DrawBuffers {
+    /// What the culling pass counted the last time the frame was drawn, the GPU must be done
+    /// with it.
+    pub(crate) unsafe fn counts(&self) -> DrawCounts {
+        self.counts.read().unwrap_or_default()
+    }
+
+    /// Copies the draws of the scene, the GPU must be done with `frame`.
+    pub(crate) unsafe fn update(
+        &mut self,
+        scene: &SceneDraws,
+        cull_data: &CullData,
+        frame: usize,
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+    ) -> Result<()> {
+        let commands_usage =
+            vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER;
+        let mut recreated = if data.feature_draw_indirect_first_instance {
+            self.commands
+                .write(&scene.commands, commands_usage, instance, device, data)?
+        } else {
+            // The instances are bound at the offset of each draw instead.
+            let commands = scene
+                .commands
+                .iter()
+                .map(|command| vk::DrawIndexedIndirectCommand {
+                    first_instance: 0,
+                    ..*command
+                })
+                .collect::<Vec<_>>();
+            self.commands
+                .write(&commands, commands_usage, instance, device, data)?
+        };
+
+        let draws_recreated = self.draws.write(
+            &scene.draws,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            instance,
+            device,
+            data,
+        )?;
+        let joints_recreated = self.joints.write(
+            &scene.joints,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            instance,
+            device,
+            data,
+        )?;
+        if draws_recreated || joints_recreated {
+            write_draw_descriptor_set(
+                device,
+                data.draw_descriptor_sets[frame],
+                self.draws.buffer,
+                self.joints.buffer,
+            );
+        }
+        recreated |= draws_recreated;
+
+        recreated |= self.instances.write(
+            &scene.instances,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            instance,
+            device,
+            data,
+        )?;
+        recreated |= self.visible_instances.reserve(
+            std::mem::size_of_val(scene.instances.as_slice()) as u64,
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            instance,
+            device,
+            data,
+        )?;
+        recreated |= self.counts.write(
+            &[DrawCounts::default()],
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            instance,
+            device,
+            data,
+        )?;
+        recreated |= self.cull_data.write(
+            &[*cull_data],
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            instance,
+            device,
+            data,
+        )?;
+
+        if recreated || self.cull_set_pyramid != Some(data.depth_pyramid.generation) {
+            write_cull_descriptor_set(device, data.cull_descriptor_sets[frame], self, data);
+            self.cull_set_pyramid = Some(data.depth_pyramid.generation);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) unsafe fn destroy(&mut self, device: &Device, allocator: &Allocator) {
+        self.commands.destroy(device, allocator);
+        self.draws.destroy(device, allocator);
+        self.joints.destroy(device, allocator);
+        self.instances.destroy(device, allocator);
+        self.visible_instances.destroy(device, allocator);
+        self.counts.destroy(device, allocator);
+        self.cull_data.destroy(device, allocator);
+        self.cull_set_pyramid = None;
+    }
+}
+
+/// A host visible, persistently mapped buffer that grows to fit what is written to it.
+#[derive(Debug, Default)]
+pub(crate) struct MappedBuffer {
+    pub(crate) buffer: vk::Buffer,
+    memory: Allocation,
+    /// In bytes.
+    capacity: u64,
+}
+
+impl MappedBuffer {
+    /// Returns whether the buffer was recreated. The GPU must be done with it.
+    unsafe fn write<T: Copy>(
+        &mut self,
+        values: &[T],
+        usage: vk::BufferUsageFlags,
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+    ) -> Result<bool> {
+        let size = std::mem::size_of_val(values) as u64;
+        let recreate = self.buffer.is_null() || size > self.capacity;
+
+        if recreate {
+            self.destroy(device, &data.allocator);
+
+            let capacity = size.next_power_of_two().max(MIN_BUFFER_CAPACITY);
+            let (buffer, memory) = create_buffer(
+                instance,
+                device,
+                data,
+                capacity,
+                usage,
+                vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+            )?;
+
+            self.buffer = buffer;
+            self.memory = memory;
+            self.capacity = capacity;
+        }
+
+        if !values.is_empty() {
+            std::ptr::copy_nonoverlapping(values.as_ptr(), self.memory.mapped_ptr(), values.len());
+        }
+
+        Ok(recreate)
+    }
+
+    /// The first value of the buffer, `None` before anything is written to it.
+    unsafe fn read<T: Copy>(&self) -> Option<T> {
+        if self.buffer.is_null() {
+            return None;
+        }
+
+        Some(std::ptr::read(self.memory.mapped_ptr()))
+    }
+
+    unsafe fn destroy(&mut self, device: &Device, allocator: &Allocator) {
+        if self.buffer.is_null() {
+            return;
+        }
+
+        device.destroy_buffer(self.buffer, None);
+        allocator.free(device, self.memory);
+
+        *self = Self::default();
+    }
+}
+
+/// A device local buffer only the GPU writes, grown to fit before the frame that fills it.
+#[derive(Debug, Default)]
+pub(crate) struct DeviceBuffer {
+    pub(crate) buffer: vk::Buffer,
+    memory: Allocation,
+    /// In bytes.
+    capacity: u64,
+}
+
+impl DeviceBuffer {
+    /// Returns whether the buffer was recreated. The GPU must be done with it.
+    unsafe fn reserve(
+        &mut self,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+    ) -> Result<bool> {
+        if !self.buffer.is_null() && size <= self.capacity {
+            return Ok(false);
+        }
+
+        self.destroy(device, &data.allocator);
+
+        let capacity = size.next_power_of_two().max(MIN_BUFFER_CAPACITY);
+        let (buffer, memory) = create_buffer(
+            instance,
+            device,
+            data,
+            capacity,
+            usage,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        self.buffer = buffer;
+        self.memory = memory;
+        self.capacity = capacity;
+
+        Ok(true)
+    }
+
+    unsafe fn destroy(&mut self, device: &Device, allocator: &Allocator) {
+        if self.buffer.is_null() {
+            return;
+        }
+
+        device.destroy_buffer(self.buffer, None);
+        allocator.free(device, self.memory);
+
+        *self = Self::default();
+    }
+}
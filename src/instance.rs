@@ -39,7 +39,7 @@ extern "system" fn debug_callback(
 }
 
 pub(crate) unsafe fn create_instance(
-    window: &Window,
+    window: Option<&Window>,
     entry: &Entry,
     data: &mut AppData,
 ) -> Result<Instance> {
@@ -66,10 +66,14 @@ pub(crate) unsafe fn create_instance(
         Vec::new()
     };
 
-    let mut extensions = vk_window::get_required_instance_extensions(window)
-        .iter()
-        .map(|e| e.as_ptr())
-        .collect::<Vec<_>>();
+    // Headless instances don't present, so they don't need the surface extensions.
+    let mut extensions = match window {
+        Some(window) => vk_window::get_required_instance_extensions(window)
+            .iter()
+            .map(|e| e.as_ptr())
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
 
     let flags = if cfg!(target_os = "macos") && entry.version()? >= PORTABILITY_MACOS_VERSION {
         info!("Enabling extensions for macOS portability.");
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::vertex::InstanceData;
+
+/// Identifies an instance of a model, stays valid until the instance is removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceId(u64);
+
+/// The instances of a model, kept packed so they can be copied to the GPU in one go.
+#[derive(Debug, Default)]
+pub(crate) struct Instances {
+    pub(crate) data: Vec<InstanceData>,
+    ids: Vec<InstanceId>,
+    slots: HashMap<InstanceId, usize>,
+    next_id: u64,
+}
+
+impl Instances {
+    pub(crate) fn add(&mut self, instance: InstanceData) -> InstanceId {
+        let id = InstanceId(self.next_id);
+        self.next_id += 1;
+
+        self.slots.insert(id, self.data.len());
+        self.ids.push(id);
+        self.data.push(instance);
+
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: InstanceId) -> Result<()> {
+        let slot = self
+            .slots
+            .remove(&id)
+            .ok_or_else(|| anyhow!("Instance not found: {:?}", id))?;
+
+        self.data.swap_remove(slot);
+        self.ids.swap_remove(slot);
+        if let Some(moved) = self.ids.get(slot) {
+            self.slots.insert(*moved, slot);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn update(&mut self, id: InstanceId, instance: InstanceData) -> Result<()> {
+        let slot = self
+            .slots
+            .get(&id)
+            .ok_or_else(|| anyhow!("Instance not found: {:?}", id))?;
+
+        self.data[*slot] = instance;
+
+        Ok(())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.data.clear();
+        self.ids.clear();
+        self.slots.clear();
+    }
+
+    pub(crate) fn ids(&self) -> &[InstanceId] {
+        &self.ids
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec3, Matrix4};
+
+    use super::*;
+
+    fn instance(x: f32) -> InstanceData {
+        InstanceData {
+            model_matrix: Matrix4::from_translation(vec3(x, 0.0, 0.0)),
+        }
+    }
+
+    fn positions(instances: &Instances) -> Vec<f32> {
+        instances.data.iter().map(|i| i.model_matrix.w.x).collect()
+    }
+
+    #[test]
+    fn stays_packed_when_removing() {
+        let mut instances = Instances::default();
+        let ids = [0.0, 1.0, 2.0].map(|x| instances.add(instance(x)));
+
+        instances.remove(ids[0]).unwrap();
+
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances.ids(), &[ids[2], ids[1]]);
+        assert_eq!(positions(&instances), vec![2.0, 1.0]);
+
+        // The moved instance is still found by its id.
+        instances.update(ids[2], instance(5.0)).unwrap();
+        assert_eq!(positions(&instances), vec![5.0, 1.0]);
+    }
+
+    #[test]
+    fn forgets_removed_ids() {
+        let mut instances = Instances::default();
+        let removed = instances.add(instance(0.0));
+        instances.remove(removed).unwrap();
+
+        assert!(instances.remove(removed).is_err());
+        assert!(instances.update(removed, instance(1.0)).is_err());
+
+        let added = instances.add(instance(1.0));
+        assert_ne!(added, removed);
+
+        instances.clear();
+        assert_eq!(instances.len(), 0);
+        assert_ne!(instances.add(instance(2.0)), added);
+    }
+}
@@ -1,36 +1,73 @@
+mod allocator;
+mod animation;
 mod app;
+mod asset_cache;
 mod assets;
+mod bounds;
 mod camera;
 mod camera_controller;
 mod command_buffer;
+mod cook;
+mod culling;
+mod deletion_queue;
 mod depth_object;
+mod depth_pyramid;
 mod descriptor_layout;
 mod descriptor_pool;
-mod framebuffer;
 mod generate_mipmaps;
+mod geometry_arena;
+mod handle;
 mod image_view;
+mod indirect;
 mod instance;
-mod instance_buffer;
+mod instances;
+mod loading_screen;
 mod logical_device;
+mod material;
 mod mesh;
 mod metrics;
 mod model;
 mod msaa;
+mod offscreen;
+mod pack;
 mod physical_device;
 mod pipeline;
+mod placeholder;
+mod render_graph;
 mod render_pass;
+mod settings;
 mod shader;
 mod single_time_cmd;
+mod streaming;
 mod swapchain;
 mod sync_object;
+#[cfg(test)]
+mod test_util;
 mod texture;
 mod texture_image;
 mod texture_sampler;
+mod transform;
 mod types;
 mod uniform_buffer;
+mod upload;
+mod upscale;
 mod vertex;
 mod vertex_buffer;
+mod vfs;
 
+pub use allocator::HeapUsage;
 pub use app::App;
 pub use camera::CameraProjectionKind;
 pub use camera_controller::CameraController;
+pub use cook::cook;
+pub use handle::{Handle, LoadState};
+pub use instances::InstanceId;
+pub use material::MaterialDesc;
+pub use metrics::DrawCounts;
+pub use model::Model;
+pub use physical_device::{PhysicalDeviceCandidate, PhysicalDeviceKind, PhysicalDeviceSelector};
+pub use settings::{PresentMode, RendererSettings};
+pub use texture::Texture;
+pub use texture_sampler::{SamplerAddressMode, SamplerFilter, SamplerSettings};
+pub use transform::Transform;
+pub use vfs::Vfs;
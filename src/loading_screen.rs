@@ -0,0 +1,51 @@
+use vulkanalia::prelude::v1_0::*;
+
+/// Size of the progress bar, relative to the frame.
+const BAR_WIDTH: f32 = 0.5;
+const BAR_HEIGHT: f32 = 0.02;
+const FILL_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const TRACK_COLOR: [f32; 4] = [0.15, 0.15, 0.15, 1.0];
+
+/// Clears a progress bar in the middle of the scene pass, drawn instead of the models while
+/// assets stream. Needs no pipeline, the frame around it keeps the clear color of the pass.
+pub(crate) unsafe fn record_loading_screen(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+    progress: f32,
+) {
+    let width = (extent.width as f32 * BAR_WIDTH) as u32;
+    let height = ((extent.height as f32 * BAR_HEIGHT) as u32).max(1);
+    let x = ((extent.width - width) / 2) as i32;
+    let y = (extent.height.saturating_sub(height) / 2) as i32;
+    let filled = (width as f32 * progress.clamp(0.0, 1.0)) as u32;
+
+    // Side by side, overlapping clears have no defined order.
+    for (offset, width, color) in [
+        (0, filled, FILL_COLOR),
+        (filled, width - filled, TRACK_COLOR),
+    ] {
+        if width == 0 {
+            continue;
+        }
+
+        let attachment = vk::ClearAttachment::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .color_attachment(0)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue { float32: color },
+            });
+        let rect = vk::ClearRect::builder()
+            .rect(vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: x + offset as i32,
+                    y,
+                },
+                extent: vk::Extent2D { width, height },
+            })
+            .base_array_layer(0)
+            .layer_count(1);
+
+        device.cmd_clear_attachments(command_buffer, &[attachment], &[rect]);
+    }
+}
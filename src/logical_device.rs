@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 
 use anyhow::Result;
 
@@ -14,17 +14,22 @@ pub(crate) unsafe fn create_logical_device(
 ) -> Result<Device> {
     let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
 
-    let mut unique_indices = HashSet::new();
-    unique_indices.insert(indices.graphics);
-    unique_indices.insert(indices.present);
+    // Queues created per family, the transfer queue may be the second one of a family.
+    let mut queue_counts = HashMap::new();
+    queue_counts.insert(indices.graphics, 1);
+    queue_counts.insert(indices.present, 1);
+    if let Some((family, queue)) = indices.transfer {
+        let count = queue_counts.entry(family).or_insert(0);
+        *count = (*count).max(queue + 1);
+    }
 
-    let queue_priorities = &[1.0];
-    let queue_infos = unique_indices
+    let queue_priorities = &[1.0, 1.0];
+    let queue_infos = queue_counts
         .iter()
-        .map(|i| {
+        .map(|(family, count)| {
             vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(*i)
-                .queue_priorities(queue_priorities)
+                .queue_family_index(*family)
+                .queue_priorities(&queue_priorities[..*count as usize])
         })
         .collect::<Vec<_>>();
 
@@ -34,13 +39,19 @@ pub(crate) unsafe fn create_logical_device(
     }
 
     let features = vk::PhysicalDeviceFeatures::builder()
-        .sampler_anisotropy(data.setting_anisotropy)
-        .sample_rate_shading(data.setting_sample_shading);
+        .sampler_anisotropy(data.feature_sampler_anisotropy)
+        .sample_rate_shading(data.feature_sample_rate_shading)
+        .multi_draw_indirect(data.feature_multi_draw_indirect)
+        .draw_indirect_first_instance(data.feature_draw_indirect_first_instance);
 
-    let extensions = DEVICE_EXTENSIONS
-        .iter()
-        .map(|n| n.as_ptr())
-        .collect::<Vec<_>>();
+    let extensions = if data.headless {
+        Vec::new()
+    } else {
+        DEVICE_EXTENSIONS
+            .iter()
+            .map(|n| n.as_ptr())
+            .collect::<Vec<_>>()
+    };
 
     let info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
@@ -52,6 +63,9 @@ pub(crate) unsafe fn create_logical_device(
 
     data.graphics_queue = device.get_device_queue(indices.graphics, 0);
     data.present_queue = device.get_device_queue(indices.present, 0);
+    if let Some((family, queue)) = indices.transfer {
+        data.transfer_queue = device.get_device_queue(family, queue);
+    }
 
     Ok(device)
 }
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use vulkanalia::prelude::v1_0::*;
+
+use crate::texture_sampler::SamplerSettings;
+
+/// What a mesh looks like, see [`App::create_material`](crate::App::create_material).
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialDesc {
+    /// Name of a loaded texture. White is sampled while it is `None` or names no texture, a
+    /// checkerboard while the texture streams or after it failed to.
+    pub base_color_texture: Option<String>,
+    pub sampler: SamplerSettings,
+    /// Multiplies the texture color, the alpha also multiplies the model opacity.
+    pub base_color_factor: [f32; 4],
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        Self {
+            base_color_texture: None,
+            sampler: SamplerSettings::default(),
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Material {
+    pub(crate) desc: MaterialDesc,
+    pub(crate) descriptor_set: vk::DescriptorSet,
+}
+
+/// A material imported with a model, its texture is an index into the textures of the model.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct SerializedMaterial {
+    pub(crate) base_color_texture: Option<usize>,
+    pub(crate) sampler: SamplerSettings,
+    pub(crate) base_color_factor: [f32; 4],
+}
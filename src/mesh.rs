@@ -1,21 +1,24 @@
-use crate::vertex::Vertex;
+use crate::bounds::Bounds;
+use crate::geometry_arena::GeometryRange;
+use crate::vertex::{Vertex, VertexSkin};
 use serde::{Deserialize, Serialize};
-use vulkanalia::prelude::v1_0::*;
 
 #[derive(Debug)]
 pub(crate) struct Mesh {
-    pub(crate) vertex_buffer: vk::Buffer,
-    pub(crate) vertex_buffer_memory: vk::DeviceMemory,
-    pub(crate) index_count: u32,
-    pub(crate) index_buffer: vk::Buffer,
-    pub(crate) index_buffer_memory: vk::DeviceMemory,
-    pub(crate) instance_count: u32,
-    pub(crate) instance_buffer: vk::Buffer,
-    pub(crate) instance_buffer_memory: vk::DeviceMemory,
+    /// First index, vertex offset and counts in the geometry arena.
+    pub(crate) range: GeometryRange,
+    /// Name of the material drawn with, the default one while `None` or not created.
+    pub(crate) material: Option<String>,
+    pub(crate) bounds: Bounds,
 }
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct SerializedMesh {
     pub(crate) vertices: Vec<Vertex>,
+    /// One per vertex for a skinned mesh, empty otherwise.
+    pub(crate) skin: Vec<VertexSkin>,
     pub(crate) indices: Vec<u32>,
+    /// Index into the materials of the model.
+    pub(crate) material: Option<usize>,
+    pub(crate) bounds: Bounds,
 }
@@ -5,30 +5,57 @@ const CYCLE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
 pub(crate) struct Cycle {
     start: Instant,
     frame_start: Instant,
+    /// Time between the start of the last two frames.
+    frame_delta: Duration,
     slowest_render: Duration,
     fastest_render: Duration,
     total_render: Duration,
     total_frames: u32,
+    /// Shortest time between the start of two frames, from the frame rate limit.
+    min_frame_time: Option<Duration>,
 }
 impl Default for Cycle {
     fn default() -> Self {
         Self {
             start: Instant::now(),
             frame_start: Instant::now(),
+            frame_delta: Duration::ZERO,
             slowest_render: Duration::from_secs(0),
             fastest_render: Duration::from_secs(30),
             total_render: Duration::from_secs(0),
             total_frames: 0,
+            min_frame_time: None,
         }
     }
 }
 impl Cycle {
+    pub(crate) fn set_frame_rate_limit(&mut self, frames_per_second: Option<u32>) {
+        self.min_frame_time = frames_per_second
+            .filter(|fps| *fps > 0)
+            .map(|fps| Duration::from_secs(1) / fps);
+    }
+
     pub(crate) fn start(&mut self) {
         self.start = Instant::now();
     }
 
+    /// Sleeps first when the previous frame started less than the limit's frame time ago.
     pub(crate) fn start_frame(&mut self) {
-        self.frame_start = Instant::now();
+        if let Some(min_frame_time) = self.min_frame_time {
+            let next_frame = self.frame_start + min_frame_time;
+            let now = Instant::now();
+            if next_frame > now {
+                std::thread::sleep(next_frame - now);
+            }
+        }
+        let now = Instant::now();
+        self.frame_delta = now - self.frame_start;
+        self.frame_start = now;
+    }
+
+    /// What animations advance by this frame.
+    pub(crate) fn frame_delta(&self) -> Duration {
+        self.frame_delta
     }
 
     pub(crate) fn end_frame(&mut self) {
@@ -51,16 +78,31 @@ impl Cycle {
                 self.total_render / self.total_frames,
                 self.total_frames
             );
-            *self = Self::default();
+            *self = Self {
+                frame_start: self.frame_start,
+                frame_delta: self.frame_delta,
+                min_frame_time: self.min_frame_time,
+                ..Self::default()
+            };
         }
     }
 }
 
+/// Meshes of the last frame the GPU finished, counted once per instance by the culling pass.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DrawCounts {
+    pub drawn: u32,
+    /// Outside the camera frustum, or occluded according to the depth pyramid of the last frame.
+    pub culled: u32,
+}
+
 #[derive(Debug)]
 pub(crate) struct Metrics {
     pub(crate) engine_start: Instant,
     pub(crate) cycle: Cycle,
     pub(crate) total_frames: u64,
+    pub(crate) draw_counts: DrawCounts,
 }
 impl Default for Metrics {
     fn default() -> Self {
@@ -68,6 +110,7 @@ impl Default for Metrics {
             engine_start: Instant::now(),
             cycle: Cycle::default(),
             total_frames: 0,
+            draw_counts: DrawCounts::default(),
         }
     }
 }
@@ -1,309 +1,889 @@
 use crate::{
+    animation::{
+        AnimationClip, Channel, ChannelValues, Interpolation, Joint, JointTransform, Skeleton,
+    },
     app::AppData,
-    instance_buffer::create_instance_buffer,
+    asset_cache::{read_cache, write_cache, CacheHeader},
+    bounds::Bounds,
+    deletion_queue::{DeletionQueue, Retired},
+    geometry_arena::{upload_mesh, upload_skinned_mesh},
+    instances::Instances,
+    material::SerializedMaterial,
     mesh::{Mesh, SerializedMesh},
-    vertex::{InstanceData, Vertex},
-    vertex_buffer::{create_index_buffer, create_vertex_buffer},
+    texture::{load_suboptimal_png, SerializedTexture},
+    texture_sampler::{SamplerAddressMode, SamplerFilter, SamplerSettings},
+    vertex::{InstanceData, SkinnedVertex, Vertex, VertexSkin},
+    vfs::{Asset, Vfs},
 };
 use anyhow::{anyhow, Result};
-use base64::Engine;
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::Path};
-use vulkanalia::{Device, Instance};
+use vulkanalia::prelude::v1_0::*;
 
+/// A model loaded by an [`App`](crate::App), only ever seen through a [`Handle`](crate::Handle).
 #[derive(Debug)]
-pub(crate) struct Model {
+pub struct Model {
     pub(crate) meshes: Vec<Mesh>,
+    /// Shared by every mesh of the model.
+    pub(crate) instances: Instances,
+    /// Names of the textures and materials imported with the model, unloaded along with it.
+    pub(crate) textures: Vec<String>,
+    pub(crate) materials: Vec<String>,
+    /// Poses the skinned meshes, which are drawn in its rest pose until a clip plays.
+    pub(crate) skeleton: Option<Skeleton>,
+    pub(crate) animations: Vec<AnimationClip>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct SerializedModel {
     pub(crate) meshes: Vec<SerializedMesh>,
+    pub(crate) materials: Vec<SerializedMaterial>,
+    /// Tightly packed RGBA8, sampled by the materials.
+    pub(crate) textures: Vec<SerializedTexture>,
+    pub(crate) skeleton: Option<Skeleton>,
+    pub(crate) animations: Vec<AnimationClip>,
 }
 
-pub(crate) fn load_model(
-    name: &str,
-    instance: &mut Instance,
-    device: &mut Device,
-    data: &mut AppData,
-) -> Result<Model> {
-    let supported_extensions = vec!["bin", "glb", "gltf"];
+impl Model {
+    /// Hands the geometry over to the deletion queue.
+    pub(crate) fn retire(self, deletion_queue: &mut DeletionQueue) {
+        for mesh in &self.meshes {
+            deletion_queue.push(Retired::Geometry(mesh.range));
+        }
+    }
+}
 
-    let extension = supported_extensions
-        .iter()
-        .find_map(|extension| {
-            let path = format!("assets/models/{}.{}", name, extension);
-            if Path::new(&path).exists() {
-                Some(extension)
-            } else {
-                None
-            }
-        })
-        .ok_or(anyhow!("no supported model found"))?;
-
-    if *extension != "bin" {
-        let path = format!("assets/models/{}.{}", name, extension);
-        let serialized = match extension.as_ref() {
-            "gltf" => load_suboptimal_gltf(&path, &extension)?,
-            "glb" => load_suboptimal_gltf(&path, &extension)?,
-            _ => Err(anyhow!("unsupported file extension: {}", extension))?,
-        };
-        save_optimal(&name, serialized)?;
+/// Source files models are imported from.
+pub(crate) const MODEL_EXTENSIONS: &[&str] = &["glb", "gltf", "obj"];
+
+/// Decodes a model file, converting and caching it as `.bin` first if the cache is missing or
+/// stale. Touches no GPU state, the streaming thread calls it.
+pub(crate) fn read_model(vfs: &Vfs, name: &Path) -> Result<SerializedModel> {
+    let (name, path, cache) = match vfs.resolve(name, MODEL_EXTENSIONS)? {
+        Asset::Source { name, path, cache } => (name, path, cache),
+        Asset::Cache { name } => return read_cache(vfs, &name, None),
+    };
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+
+    let header = CacheHeader::for_source(&name, &path, importer_settings(extension))?;
+    match read_cache(vfs, &cache, Some(&header)) {
+        Ok(serialized) => return Ok(serialized),
+        Err(error) if vfs.exists(&cache) => info!("Rebuilding {}: {}", cache, error),
+        Err(_) => {}
+    }
+
+    let serialized = import_model(&path)?;
+    // Without a cache mount to write to, it is converted again the next time.
+    if let Err(error) = write_cache(vfs, &cache, &header, &serialized) {
+        warn!("Not caching {}: {}", cache, error);
     }
 
-    let path = format!("assets/models/{}.bin", name);
-    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
-    let serialized: SerializedModel = bincode::deserialize_from(&mut reader)?;
+    Ok(serialized)
+}
 
-    let mut model = Model { meshes: vec![] };
+/// Converts a source file into the model its cache holds.
+pub(crate) fn import_model(path: &Path) -> Result<SerializedModel> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+
+    let serialized = match extension {
+        "gltf" | "glb" => load_suboptimal_gltf(path)?,
+        "obj" => load_suboptimal_obj(path)?,
+        _ => Err(anyhow!("unsupported file extension: {}", extension))?,
+    };
+    Ok(save_optimal(serialized))
+}
+
+/// Written to the cache header, a cache imported with other settings is rebuilt.
+pub(crate) fn importer_settings(extension: &str) -> String {
+    match extension {
+        "obj" => format!("obj {:?}", obj_load_options()),
+        extension => extension.to_owned(),
+    }
+}
+
+/// Name the texture imported with a model is created under.
+pub(crate) fn imported_texture_name(model: &str, index: usize) -> String {
+    format!("{}/texture{}", model, index)
+}
+
+/// Name the material imported with a model is created under.
+pub(crate) fn imported_material_name(model: &str, index: usize) -> String {
+    format!("{}/material{}", model, index)
+}
+
+/// Uploads the meshes, the imported textures and materials are created by
+/// [`Assets::insert_model`](crate::assets::Assets::insert_model).
+pub(crate) fn create_model(
+    name: &str,
+    serialized: SerializedModel,
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<Model> {
+    let mut model = Model {
+        meshes: vec![],
+        instances: Instances::default(),
+        textures: (0..serialized.textures.len())
+            .map(|index| imported_texture_name(name, index))
+            .collect(),
+        materials: (0..serialized.materials.len())
+            .map(|index| imported_material_name(name, index))
+            .collect(),
+        skeleton: serialized.skeleton,
+        animations: serialized.animations,
+    };
+
+    // A single instance at the model origin, so a spawned model shows up right away.
+    model.instances.add(InstanceData {
+        model_matrix: cgmath::Matrix4::identity(),
+    });
 
     for mesh in serialized.meshes {
-        // FIX starts without instances
-        let instance_data = InstanceData {
-            model_matrix: vec![
-                cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, -1.25, 1.0)),
-                cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 1.25, 1.0)),
-                cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, -1.25, -1.0)),
-                cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 1.25, -1.0)),
-            ],
+        let range = if mesh.skin.is_empty() {
+            unsafe { upload_mesh(instance, device, data, &mesh.vertices, &mesh.indices) }
+        } else {
+            let vertices = mesh
+                .vertices
+                .iter()
+                .zip(&mesh.skin)
+                .map(|(vertex, skin)| SkinnedVertex {
+                    vertex: *vertex,
+                    skin: *skin,
+                })
+                .collect::<Vec<_>>();
+            unsafe { upload_skinned_mesh(instance, device, data, &vertices, &mesh.indices) }
         };
 
-        let (vertex_buffer, vertex_buffer_memory) =
-            unsafe { create_vertex_buffer(&mesh.vertices, instance, device, data)? };
-        let (index_buffer, index_buffer_memory) =
-            unsafe { create_index_buffer(&mesh.indices, instance, device, data)? };
-        let (instance_buffer, instance_buffer_memory) =
-            unsafe { create_instance_buffer(&instance_data, instance, device, data)? };
+        // Frees the meshes uploaded so far, the arena keeps no ranges of a failed model.
+        let range = match range {
+            Ok(range) => range,
+            Err(e) => {
+                model.retire(&mut data.deletion_queue);
+                return Err(e);
+            }
+        };
 
         model.meshes.push(Mesh {
-            vertex_buffer,
-            vertex_buffer_memory,
-            index_count: mesh.indices.len() as u32,
-            index_buffer,
-            index_buffer_memory,
-            instance_count: instance_data.model_matrix.len() as u32,
-            instance_buffer,
-            instance_buffer_memory,
+            range,
+            material: mesh
+                .material
+                .map(|index| imported_material_name(name, index)),
+            bounds: mesh.bounds,
         });
     }
 
     Ok(model)
 }
 
-fn save_optimal(name: &str, serialized: SerializedModel) -> Result<()> {
-    let path = format!("assets/models/{}.bin", name);
-    let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
-
-    let mut new_serialized = SerializedModel { meshes: vec![] };
+/// Deduplicates the vertices and computes the bounds, the model as cached.
+fn save_optimal(serialized: SerializedModel) -> SerializedModel {
+    let mut new_serialized = SerializedModel {
+        meshes: vec![],
+        materials: serialized.materials,
+        textures: serialized.textures,
+        skeleton: serialized.skeleton,
+        animations: serialized.animations,
+    };
 
     for mesh in &serialized.meshes {
-        let mut unique_vertices: HashMap<Vertex, usize> = HashMap::new();
+        let skinned = !mesh.skin.is_empty();
+
+        let mut unique_vertices: HashMap<(Vertex, VertexSkin), usize> = HashMap::new();
         let mut vertices = Vec::new();
+        let mut skin = Vec::new();
         let mut indices = Vec::new();
         for index in &mesh.indices {
             let vertex = mesh.vertices[*index as usize];
-            if let Some(index) = unique_vertices.get(&vertex) {
+            let vertex_skin = if skinned {
+                mesh.skin[*index as usize]
+            } else {
+                VertexSkin::default()
+            };
+            if let Some(index) = unique_vertices.get(&(vertex, vertex_skin)) {
                 indices.push(*index as u32);
             } else {
                 let index = vertices.len();
-                unique_vertices.insert(vertex, index);
+                unique_vertices.insert((vertex, vertex_skin), index);
                 vertices.push(vertex);
+                if skinned {
+                    skin.push(vertex_skin);
+                }
                 indices.push(index as u32);
             }
         }
 
-        new_serialized
-            .meshes
-            .push(SerializedMesh { vertices, indices });
+        let bounds = Bounds::from_vertices(&vertices);
+        new_serialized.meshes.push(SerializedMesh {
+            vertices,
+            skin,
+            indices,
+            material: mesh.material,
+            bounds,
+        });
     }
 
-    bincode::serialize_into(&mut writer, &new_serialized)?;
-
-    Ok(())
+    new_serialized
 }
 
-fn load_suboptimal_gltf(path: &str, extension: &str) -> Result<SerializedModel> {
-    let (gltf, buffers, _) = gltf::import(&path).expect("Failed to import gltf file");
+/// Imports the default scene, each primitive placed with the world transform of its node. The
+/// base color of the materials is imported along with the images it samples, the first skin
+/// along with the clips animating its joints.
+fn load_suboptimal_gltf(path: &Path) -> Result<SerializedModel> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    let mut serialized = SerializedModel {
+        meshes: vec![],
+        materials: vec![],
+        textures: vec![],
+        skeleton: None,
+        animations: vec![],
+    };
+
+    // Only the images a material samples are kept, converted once each.
+    let mut textures = HashMap::new();
+    for material in document.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let texture = pbr.base_color_texture().map(|info| info.texture());
+
+        let base_color_texture = match &texture {
+            Some(texture) => {
+                let image = texture.source().index();
+                match textures.get(&image) {
+                    Some(index) => Some(*index),
+                    None => {
+                        let index = serialized.textures.len();
+                        serialized.textures.push(rgba8_texture(&images[image])?);
+                        textures.insert(image, index);
+                        Some(index)
+                    }
+                }
+            }
+            None => None,
+        };
+
+        serialized.materials.push(SerializedMaterial {
+            base_color_texture,
+            sampler: texture
+                .map(|texture| sampler_settings(&texture.sampler()))
+                .unwrap_or_default(),
+            base_color_factor: pbr.base_color_factor(),
+        });
+    }
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
 
-    let mut buffer_data = Vec::new();
-    for buffer in gltf.buffers() {
-        match buffer.source() {
-            gltf::buffer::Source::Bin => {
-                buffer_data.push(buffers[buffer.index()].clone());
+    let skin = scene
+        .as_ref()
+        .and_then(|scene| read_skin(&document, scene, &buffers));
+    if let Some(skin) = &skin {
+        serialized.animations = read_animations(&document, skin, &buffers);
+        serialized.skeleton = Some(skin.skeleton.clone());
+    }
+
+    match scene {
+        Some(scene) => {
+            for node in scene.nodes() {
+                read_node(
+                    &node,
+                    Matrix4::identity(),
+                    skin.as_ref(),
+                    &buffers,
+                    &mut serialized.meshes,
+                );
             }
-            gltf::buffer::Source::Uri(uri) => {
-                if extension != "glb" {
-                    let uri = uri.trim_start_matches("data:application/octet-stream;base64,");
-                    let data = uri.as_bytes();
-                    let bin = base64::engine::general_purpose::STANDARD
-                        .decode(data)
-                        .expect("Failed to decode buffer data");
-                    buffer_data.push(gltf::buffer::Data(bin));
-                } else {
-                    let bin = std::fs::read(uri).expect("Failed to read buffer data");
-                    buffer_data.push(gltf::buffer::Data(bin));
-                }
+        }
+        // Nothing places the meshes, they all sit at the origin.
+        None => {
+            for mesh in document.meshes() {
+                read_mesh(
+                    &mesh,
+                    &Matrix4::identity(),
+                    None,
+                    &buffers,
+                    &mut serialized.meshes,
+                );
             }
         }
     }
 
-    // let mut materials = Vec::new();
-    // let mut material_meshes_pairs = HashMap::new();
-    // for gltf_material in gltf.materials() {
-    //     let pbr = gltf_material.pbr_metallic_roughness();
-    //     if let Some(gltf_texture) = &pbr.base_color_texture().map(|tex| tex.texture()) {
-    //         // println!(
-    //         //     "Loading PBR for model {} with texture {}",
-    //         //     path.display(),
-    //         //     gltf_texture.index()
-    //         // );
-
-    //         let wrap_s = match gltf_texture.sampler().wrap_s() {
-    //             WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
-    //             WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
-    //             WrappingMode::Repeat => gl::REPEAT,
-    //         };
-    //         let wrap_t = match gltf_texture.sampler().wrap_t() {
-    //             WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
-    //             WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
-    //             WrappingMode::Repeat => gl::REPEAT,
-    //         };
-    //         if let Some(filter_min) = gltf_texture.sampler().min_filter() {
-    //             match filter_min {
-    //                 MinFilter::Nearest => gl::NEAREST,
-    //                 MinFilter::Linear => gl::LINEAR,
-    //                 MinFilter::NearestMipmapNearest => gl::NEAREST_MIPMAP_NEAREST,
-    //                 MinFilter::LinearMipmapNearest => gl::LINEAR_MIPMAP_NEAREST,
-    //                 MinFilter::NearestMipmapLinear => gl::NEAREST_MIPMAP_LINEAR,
-    //                 MinFilter::LinearMipmapLinear => gl::LINEAR_MIPMAP_LINEAR,
-    //             };
-    //         }
-    //         if let Some(filter_mag) = gltf_texture.sampler().mag_filter() {
-    //             match filter_mag {
-    //                 MagFilter::Nearest => gl::NEAREST,
-    //                 MagFilter::Linear => gl::LINEAR,
-    //             };
-    //         }
-
-    //         let texture_source = gltf_texture.source().source();
-
-    //         let albedo_image = match texture_source {
-    //             Source::Uri { uri, .. } => Image::from_uri(uri),
-    //             Source::View { view, .. } => {
-    //                 let data = &buffer_data[view.buffer().index()][view.offset()..];
-    //                 Image::from_data(data)
-    //             }
-    //         };
-    //         let mut albedo = Texture::new(albedo_image);
-    //         albedo.gl_s_wrapping = wrap_s;
-    //         albedo.gl_t_wrapping = wrap_t;
-    //         // TODO add min and mag filter
-    //         // TODO mipmaps? and all the other texture options
-
-    //         materials.push(Material::Pbr { albedo });
-
-    //         // TEMPORARY - ASSIGN EVERY MESH TO THE FIRST MATERIAL
-    //         material_meshes_pairs.insert(0, vec![0]);
-    //     }
-
-    // if let Some(normal_texture) = gltf_material.normal_texture() {
-    //     println!(
-    //         "Loading normal texture for model {} with texture {}",
-    //         path.display(),
-    //         normal_texture.texture().index()
-    //     );
-
-    //     let wrap_s = match normal_texture.texture().sampler().wrap_s() {
-    //         WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
-    //         WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
-    //         WrappingMode::Repeat => gl::REPEAT,
-    //     };
-
-    //     let wrap_t = match normal_texture.texture().sampler().wrap_t() {
-    //         WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
-    //         WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
-    //         WrappingMode::Repeat => gl::REPEAT,
-    //     };
-
-    //     if let Some(filter_min) = normal_texture.texture().sampler().min_filter() {
-    //         match filter_min {
-    //             MinFilter::Nearest => gl::NEAREST,
-    //             MinFilter::Linear => gl::LINEAR,
-    //             MinFilter::NearestMipmapNearest => gl::NEAREST_MIPMAP_NEAREST,
-    //             MinFilter::LinearMipmapNearest => gl::LINEAR_MIPMAP_NEAREST,
-    //             MinFilter::NearestMipmapLinear => gl::NEAREST_MIPMAP_LINEAR,
-    //             MinFilter::LinearMipmapLinear => gl::LINEAR_MIPMAP_LINEAR,
-    //         };
-    //     }
-
-    //     if let Some(filter_mag) = normal_texture.texture().sampler().mag_filter() {
-    //         match filter_mag {
-    //             MagFilter::Nearest => gl::NEAREST,
-    //             MagFilter::Linear => gl::LINEAR,
-    //         };
-    //     }
-
-    //     let texture_source = normal_texture.texture().source().source();
-
-    //     let normal_image = match texture_source {
-    //         Source::Uri { uri, .. } => Image::from_uri(uri),
-    //         Source::View { view, .. } => {
-    //             let data = &buffer_data[view.buffer().index()][view.offset()..];
-    //             Image::from_data(data)
-    //         }
-    //     };
-
-    //     let mut normal = Texture::new(normal_image);
-    //     normal.gl_s_wrapping = wrap_s;
-    //     normal.gl_t_wrapping = wrap_t;
-    //     // TODO add min and mag filter
-    //     // TODO mipmaps? and all the other texture options
-
-    //     materials.push(Material::Normal { normal });
-    // }
-    // }
-
-    let mut serialized = SerializedModel { meshes: vec![] };
-
-    for mesh in gltf.meshes() {
-        for primitive in mesh.primitives() {
-            let mut vertices = Vec::new();
-            let mut indices = Vec::new();
-
-            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-
-            // TODO Vertex color - Needs a new type called VertexGroup or something check on blender
-            if let Some(position_attribute) = reader.read_positions() {
-                position_attribute.for_each(|position| {
-                    vertices.push(Vertex {
-                        pos: position.into(),
-                        color: cgmath::vec3(0.0, 0.0, 0.0),
-                        tex_coord: cgmath::vec2(0.0, 0.0),
-                    })
-                });
+    Ok(serialized)
+}
+
+/// The skin the skinned meshes of a model are imported with.
+struct ImportedSkin {
+    index: usize,
+    /// Where each joint of the skin ended up in the skeleton.
+    joints: Vec<u32>,
+    skeleton: Skeleton,
+}
+
+/// Where a node sits in the scene.
+struct NodePlacement {
+    parent: Option<usize>,
+    /// World transform of the parent, at rest.
+    parent_world: Matrix4<f32>,
+    depth: usize,
+}
+
+fn place_nodes(
+    node: &gltf::Node,
+    parent: Option<usize>,
+    parent_world: Matrix4<f32>,
+    depth: usize,
+    placements: &mut HashMap<usize, NodePlacement>,
+) {
+    let world = parent_world * Matrix4::from(node.transform().matrix());
+    for child in node.children() {
+        place_nodes(&child, Some(node.index()), world, depth + 1, placements);
+    }
+    placements.insert(
+        node.index(),
+        NodePlacement {
+            parent,
+            parent_world,
+            depth,
+        },
+    );
+}
+
+/// Only one skeleton is posed per model, the meshes of the other skins are imported static.
+fn read_skin(
+    document: &gltf::Document,
+    scene: &gltf::Scene,
+    buffers: &[gltf::buffer::Data],
+) -> Option<ImportedSkin> {
+    let mut skins = document.skins();
+    let skin = skins.next()?;
+    if skins.next().is_some() {
+        warn!("Only the first skin is imported, the meshes of the others are static");
+    }
+
+    let mut placements = HashMap::new();
+    for node in scene.nodes() {
+        place_nodes(&node, None, Matrix4::identity(), 0, &mut placements);
+    }
+
+    let nodes = skin.joints().collect::<Vec<_>>();
+    let inverse_binds = skin
+        .reader(|buffer| Some(&buffers[buffer.index()]))
+        .read_inverse_bind_matrices()
+        .map(|matrices| matrices.map(Matrix4::from).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    // Sorted by depth so parents come first.
+    let mut order = (0..nodes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|joint| {
+        placements
+            .get(&nodes[*joint].index())
+            .map_or(0, |placement| placement.depth)
+    });
+    let mut joints = vec![0; nodes.len()];
+    for (new, old) in order.iter().enumerate() {
+        joints[*old] = new as u32;
+    }
+    let node_joints = nodes
+        .iter()
+        .enumerate()
+        .map(|(old, node)| (node.index(), joints[old] as usize))
+        .collect::<HashMap<_, _>>();
+
+    let skeleton = Skeleton {
+        joints: order
+            .iter()
+            .map(|old| {
+                let node = &nodes[*old];
+                let placement = placements.get(&node.index());
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+
+                Joint {
+                    // A joint under a node that is not a joint follows the rest pose of the
+                    // nodes above it.
+                    parent: placement
+                        .and_then(|placement| placement.parent)
+                        .and_then(|parent| node_joints.get(&parent).copied()),
+                    root: placement.map_or(Matrix4::identity(), |placement| placement.parent_world),
+                    inverse_bind: inverse_binds
+                        .get(*old)
+                        .copied()
+                        .unwrap_or(Matrix4::identity()),
+                    rest: JointTransform {
+                        translation: translation.into(),
+                        rotation: Quaternion::new(w, x, y, z),
+                        scale: scale.into(),
+                    },
+                }
+            })
+            .collect(),
+    };
+
+    Some(ImportedSkin {
+        index: skin.index(),
+        joints,
+        skeleton,
+    })
+}
+
+/// The channels of the clips that move the joints of the skin, the others are dropped.
+fn read_animations(
+    document: &gltf::Document,
+    skin: &ImportedSkin,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<AnimationClip> {
+    let node_joints = document
+        .skins()
+        .nth(skin.index)
+        .map(|gltf_skin| {
+            gltf_skin
+                .joints()
+                .zip(&skin.joints)
+                .map(|(node, joint)| (node.index(), *joint as usize))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+
+    let mut clips = Vec::new();
+    for animation in document.animations() {
+        let mut clip = AnimationClip {
+            name: animation
+                .name()
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("animation{}", animation.index())),
+            duration: 0.0,
+            channels: vec![],
+        };
+
+        for channel in animation.channels() {
+            let Some(joint) = node_joints.get(&channel.target().node().index()) else {
+                continue;
+            };
+
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs())
+            else {
+                continue;
+            };
+
+            use gltf::animation::util::ReadOutputs;
+            let times = inputs.collect::<Vec<_>>();
+            let (values, len) = match outputs {
+                ReadOutputs::Translations(values) => {
+                    let values = values.map(Into::into).collect::<Vec<_>>();
+                    let len = values.len();
+                    (ChannelValues::Translation(values), len)
+                }
+                ReadOutputs::Rotations(values) => {
+                    let values = values
+                        .into_f32()
+                        .map(|[x, y, z, w]| Quaternion::new(w, x, y, z))
+                        .collect::<Vec<_>>();
+                    let len = values.len();
+                    (ChannelValues::Rotation(values), len)
+                }
+                ReadOutputs::Scales(values) => {
+                    let values = values.map(Into::into).collect::<Vec<_>>();
+                    let len = values.len();
+                    (ChannelValues::Scale(values), len)
+                }
+                ReadOutputs::MorphTargetWeights(_) => continue,
+            };
+
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+            let keys = match interpolation {
+                Interpolation::CubicSpline => len / 3,
+                _ => len,
+            };
+            if times.is_empty() || keys != times.len() {
+                warn!(
+                    "Skipping a channel of {} on node {}, {} keys for {} times",
+                    clip.name,
+                    channel.target().node().index(),
+                    keys,
+                    times.len()
+                );
+                continue;
             }
-            // if let Some(normal_attribute) = reader.read_normals() {
-            //     let mut normal_index = 0;
-            //     normal_attribute.for_each(|normal| {
-            //         vertices[normal_index].normal = normal.into();
-            //         normal_index += 1;
-            //     });
-            // }
-            if let Some(tex_coord_attribute) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
-                let mut tex_coord_index = 0;
-                tex_coord_attribute.for_each(|tex_coord| {
-                    vertices[tex_coord_index].tex_coord = tex_coord.into();
-                    tex_coord_index += 1;
-                });
+
+            clip.duration = clip.duration.max(times[times.len() - 1]);
+            clip.channels.push(Channel {
+                joint: *joint,
+                interpolation,
+                times,
+                values,
+            });
+        }
+
+        if !clip.channels.is_empty() {
+            clips.push(clip);
+        }
+    }
+
+    clips
+}
+
+fn read_node(
+    node: &gltf::Node,
+    parent: Matrix4<f32>,
+    skin: Option<&ImportedSkin>,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<SerializedMesh>,
+) {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        match (node.skin(), skin) {
+            // The joints place a skinned mesh, the transform of its node is ignored.
+            (Some(node_skin), Some(skin)) if node_skin.index() == skin.index => {
+                read_mesh(
+                    &mesh,
+                    &Matrix4::identity(),
+                    Some(&skin.joints),
+                    buffers,
+                    meshes,
+                );
             }
+            _ => read_mesh(&mesh, &transform, None, buffers, meshes),
+        }
+    }
+    for child in node.children() {
+        read_node(&child, transform, skin, buffers, meshes);
+    }
+}
 
-            if let Some(indices_raw) = reader.read_indices() {
-                indices.append(&mut indices_raw.into_u32().collect::<Vec<u32>>());
+/// The transform is baked into the vertices, a model is placed as a whole. `joints` maps the
+/// joints of the skin of a skinned mesh to the skeleton.
+fn read_mesh(
+    mesh: &gltf::Mesh,
+    transform: &Matrix4<f32>,
+    joints: Option<&[u32]>,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<SerializedMesh>,
+) {
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            warn!(
+                "Skipping primitive of mesh {}, {:?} is not supported",
+                mesh.index(),
+                primitive.mode()
+            );
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let mut vertices = positions
+            .map(|position| Vertex::at(position.into()))
+            .collect::<Vec<_>>();
+
+        if let Some(normals) = reader.read_normals() {
+            vertices
+                .iter_mut()
+                .zip(normals)
+                .for_each(|(vertex, normal)| vertex.normal = normal.into());
+        }
+        if let Some(tangents) = reader.read_tangents() {
+            vertices
+                .iter_mut()
+                .zip(tangents)
+                .for_each(|(vertex, tangent)| vertex.tangent = tangent.into());
+        }
+        if let Some(colors) = reader.read_colors(0) {
+            vertices
+                .iter_mut()
+                .zip(colors.into_rgba_f32())
+                .for_each(|(vertex, color)| vertex.color = color.into());
+        }
+        if let Some(tex_coords) = reader.read_tex_coords(0) {
+            vertices
+                .iter_mut()
+                .zip(tex_coords.into_f32())
+                .for_each(|(vertex, tex_coord)| vertex.tex_coord = tex_coord.into());
+        }
+        if let Some(tex_coords) = reader.read_tex_coords(1) {
+            vertices
+                .iter_mut()
+                .zip(tex_coords.into_f32())
+                .for_each(|(vertex, tex_coord)| vertex.tex_coord_1 = tex_coord.into());
+        }
+
+        let mut skin = Vec::new();
+        if let (Some(joints), Some(gltf_joints), Some(weights)) =
+            (joints, reader.read_joints(0), reader.read_weights(0))
+        {
+            skin.extend(
+                gltf_joints
+                    .into_u16()
+                    .zip(weights.into_f32())
+                    .map(|(vertex_joints, weights)| skin_vertex(joints, vertex_joints, weights)),
+            );
+            if skin.len() != vertices.len() {
+                warn!(
+                    "Skipping the skin of mesh {}, {} joints for {} vertices",
+                    mesh.index(),
+                    skin.len(),
+                    vertices.len()
+                );
+                skin.clear();
             }
+        }
+
+        // The shaders sample the base color with the first set.
+        let material = primitive.material();
+        let base_color_set = material
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .map(|info| info.tex_coord());
+        if base_color_set == Some(1) {
+            vertices.iter_mut().for_each(|vertex| {
+                std::mem::swap(&mut vertex.tex_coord, &mut vertex.tex_coord_1);
+            });
+        }
+
+        let mut indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+
+        let linear = Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        let normal_matrix = linear.invert().unwrap_or(linear).transpose();
+        let mirrored = linear.determinant() < 0.0;
 
-            serialized.meshes.push(SerializedMesh { vertices, indices });
+        for vertex in &mut vertices {
+            vertex.pos = (transform * vertex.pos.extend(1.0)).truncate();
+            vertex.normal = normalized(normal_matrix * vertex.normal);
+
+            let handedness = if mirrored {
+                -vertex.tangent.w
+            } else {
+                vertex.tangent.w
+            };
+            vertex.tangent = normalized(linear * vertex.tangent.truncate()).extend(handedness);
         }
+
+        // A mirroring transform turns the triangles inside out.
+        if mirrored {
+            indices
+                .chunks_exact_mut(3)
+                .for_each(|triangle| triangle.swap(1, 2));
+        }
+
+        meshes.push(SerializedMesh {
+            vertices,
+            skin,
+            indices,
+            material: material.index(),
+            // Computed by `save_optimal`.
+            bounds: Bounds::default(),
+        });
+    }
+}
+
+/// The weights are normalized, the posed bounds of the mesh rely on them summing to one.
+fn skin_vertex(joints: &[u32], vertex_joints: [u16; 4], weights: [f32; 4]) -> VertexSkin {
+    let total = weights.iter().sum::<f32>();
+    VertexSkin {
+        joints: vertex_joints.map(|joint| joints.get(joint as usize).copied().unwrap_or(0)),
+        weights: if total > 0.0 {
+            weights.map(|weight| weight / total)
+        } else {
+            [1.0, 0.0, 0.0, 0.0]
+        },
+    }
+}
+
+/// Zero when there is no direction to keep.
+fn normalized(vector: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+    if vector.magnitude2() > 0.0 {
+        vector.normalize()
+    } else {
+        vector
+    }
+}
+
+fn sampler_settings(sampler: &gltf::texture::Sampler) -> SamplerSettings {
+    let filter = match sampler.mag_filter() {
+        Some(gltf::texture::MagFilter::Nearest) => SamplerFilter::Nearest,
+        _ => SamplerFilter::Linear,
+    };
+    // Both directions share an address mode, the horizontal one is kept.
+    let address_mode = match sampler.wrap_s() {
+        gltf::texture::WrappingMode::Repeat => SamplerAddressMode::Repeat,
+        gltf::texture::WrappingMode::MirroredRepeat => SamplerAddressMode::MirroredRepeat,
+        gltf::texture::WrappingMode::ClampToEdge => SamplerAddressMode::ClampToEdge,
+    };
+
+    SamplerSettings {
+        filter,
+        address_mode,
+    }
+}
+
+/// Converts a decoded image to the pixels textures are created from, 16 bit channels keep their
+/// most significant byte.
+fn rgba8_texture(image: &gltf::image::Data) -> Result<SerializedTexture> {
+    use gltf::image::Format;
+
+    let pixels = match image.format {
+        Format::R8 => image
+            .pixels
+            .iter()
+            .flat_map(|r| [*r, *r, *r, 255])
+            .collect(),
+        Format::R8G8 => image
+            .pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[1], 0, 255])
+            .collect(),
+        Format::R8G8B8 => image
+            .pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        Format::R8G8B8A8 => image.pixels.clone(),
+        Format::R16G16B16 => image
+            .pixels
+            .chunks_exact(6)
+            .flat_map(|p| [p[1], p[3], p[5], 255])
+            .collect(),
+        Format::R16G16B16A16 => image
+            .pixels
+            .chunks_exact(8)
+            .flat_map(|p| [p[1], p[3], p[5], p[7]])
+            .collect(),
+        format => return Err(anyhow!("unsupported image format: {:?}", format)),
+    };
+
+    Ok(SerializedTexture {
+        width: image.width,
+        height: image.height,
+        pixels,
+    })
+}
+
+/// Imports every object of the file with the materials of its `.mtl` libraries. Only `.png`
+/// diffuse maps are imported, the materials of the others keep their diffuse color.
+fn load_suboptimal_obj(path: &Path) -> Result<SerializedModel> {
+    let (objects, materials) = tobj::load_obj(path, &obj_load_options())?;
+    let materials = materials.unwrap_or_else(|error| {
+        warn!("No materials for {}: {}", path.display(), error);
+        Vec::new()
+    });
+
+    let mut serialized = SerializedModel {
+        meshes: vec![],
+        materials: vec![],
+        textures: vec![],
+        skeleton: None,
+        animations: vec![],
+    };
+
+    // Texture paths are relative to the `.obj`, each is decoded once.
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut textures = HashMap::new();
+    for material in &materials {
+        let base_color_texture = match material.diffuse_texture.as_str() {
+            "" => None,
+            file => match textures.get(file) {
+                Some(index) => Some(*index),
+                None => match read_obj_texture(&directory.join(file)) {
+                    Ok(texture) => {
+                        let index = serialized.textures.len();
+                        serialized.textures.push(texture);
+                        textures.insert(file, index);
+                        Some(index)
+                    }
+                    Err(error) => {
+                        warn!("Skipping texture {} of {}: {}", file, material.name, error);
+                        None
+                    }
+                },
+            },
+        };
+
+        let [r, g, b] = material.diffuse;
+        serialized.materials.push(SerializedMaterial {
+            base_color_texture,
+            sampler: SamplerSettings::default(),
+            base_color_factor: [r, g, b, material.dissolve],
+        });
+    }
+
+    for object in objects {
+        let mesh = object.mesh;
+
+        let mut vertices = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| Vertex::at(cgmath::vec3(p[0], p[1], p[2])))
+            .collect::<Vec<_>>();
+        vertices
+            .iter_mut()
+            .zip(mesh.normals.chunks_exact(3))
+            .for_each(|(vertex, n)| vertex.normal = cgmath::vec3(n[0], n[1], n[2]));
+        vertices
+            .iter_mut()
+            .zip(mesh.vertex_color.chunks_exact(3))
+            .for_each(|(vertex, c)| vertex.color = cgmath::vec4(c[0], c[1], c[2], 1.0));
+        // The rows of an `.obj` texture start at the bottom.
+        vertices
+            .iter_mut()
+            .zip(mesh.texcoords.chunks_exact(2))
+            .for_each(|(vertex, t)| vertex.tex_coord = cgmath::vec2(t[0], 1.0 - t[1]));
+
+        serialized.meshes.push(SerializedMesh {
+            vertices,
+            skin: vec![],
+            indices: mesh.indices,
+            // A material of a missing library.
+            material: mesh
+                .material_id
+                .filter(|index| *index < serialized.materials.len()),
+            // Computed by `save_optimal`.
+            bounds: Bounds::default(),
+        });
     }
 
     Ok(serialized)
 }
+
+fn obj_load_options() -> tobj::LoadOptions {
+    tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ..Default::default()
+    }
+}
+
+fn read_obj_texture(path: &Path) -> Result<SerializedTexture> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => {
+            let (pixels, width, height) = load_suboptimal_png(path)?;
+            Ok(SerializedTexture {
+                width,
+                height,
+                pixels,
+            })
+        }
+        _ => Err(anyhow!("unsupported texture format: {}", path.display())),
+    }
+}
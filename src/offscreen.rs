@@ -0,0 +1,153 @@
+use anyhow::Result;
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    app::{AppData, MAX_FRAMES_IN_FLIGHT},
+    image_view::create_swapchain_image_views,
+    single_time_cmd::{begin_single_time_commands, end_single_time_commands},
+    texture_image::create_image,
+    vertex_buffer::create_buffer,
+};
+
+pub(crate) const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Creates the images headless rendering resolves into, standing in for the swapchain images.
+pub(crate) unsafe fn create_offscreen_targets(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    data.swapchain_format = OFFSCREEN_FORMAT;
+    data.swapchain_extent = vk::Extent2D { width, height };
+
+    data.swapchain_images.clear();
+    data.offscreen_images_memory.clear();
+
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (image, image_memory) = create_image(
+            instance,
+            device,
+            data,
+            width,
+            height,
+            1,
+            vk::SampleCountFlags::_1,
+            OFFSCREEN_FORMAT,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        data.swapchain_images.push(image);
+        data.offscreen_images_memory.push(image_memory);
+    }
+
+    create_swapchain_image_views(device, data)?;
+
+    Ok(())
+}
+
+pub(crate) unsafe fn destroy_offscreen_targets(device: &Device, data: &mut AppData) {
+    data.swapchain_images
+        .iter()
+        .for_each(|i| device.destroy_image(*i, None));
+    data.offscreen_images_memory
+        .iter()
+        .for_each(|m| data.allocator.free(device, *m));
+    data.swapchain_images.clear();
+    data.offscreen_images_memory.clear();
+}
+
+/// Copies a rendered offscreen image into host memory as tightly packed RGBA8 rows.
+pub(crate) unsafe fn read_offscreen_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    image_index: usize,
+) -> Result<Vec<u8>> {
+    let extent = data.swapchain_extent;
+    let size = extent.width as u64 * extent.height as u64 * 4;
+
+    let (staging_buffer, staging_buffer_memory) = create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+
+    let command_buffer = begin_single_time_commands(device, data)?;
+
+    // Make the resolve writes of the frame visible to the copy.
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(data.swapchain_images[image_index])
+        .subresource_range(subresource_range)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+
+    // The render pass leaves the resolved image in TRANSFER_SRC_OPTIMAL.
+    device.cmd_copy_image_to_buffer(
+        command_buffer,
+        data.swapchain_images[image_index],
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        staging_buffer,
+        &[region],
+    );
+
+    end_single_time_commands(device, data, command_buffer)?;
+
+    let mut pixels = vec![0u8; size as usize];
+    std::ptr::copy_nonoverlapping(
+        staging_buffer_memory.mapped_ptr(),
+        pixels.as_mut_ptr(),
+        pixels.len(),
+    );
+
+    device.destroy_buffer(staging_buffer, None);
+    data.allocator.free(device, staging_buffer_memory);
+
+    Ok(pixels)
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+const MAGIC: [u8; 4] = *b"YPAK";
+
+/// Bumped whenever the layout of the index changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// Where a file lives in the pack, in bytes from the end of the index.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct PackEntry {
+    offset: u64,
+    len: u64,
+}
+
+/// A read-only archive of files indexed by name, written by `yuumi-cook`. The magic, the format
+/// version and the length of the index come first, then the bincode index and the files it
+/// points at. The files are read straight from the memory mapped pack.
+#[derive(Debug)]
+pub(crate) struct Pack {
+    path: PathBuf,
+    map: Mmap,
+    entries: BTreeMap<String, PackEntry>,
+    /// Where the files start.
+    data_offset: usize,
+}
+
+impl Pack {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // The pack is never written to while mapped, `yuumi-cook` writes a new file.
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() < 16 || map[..4] != MAGIC {
+            return Err(anyhow!("not a pack: {}", path.display()));
+        }
+        let version = u32::from_le_bytes(map[4..8].try_into()?);
+        if version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "pack format {} instead of {}: {}",
+                version,
+                FORMAT_VERSION,
+                path.display()
+            ));
+        }
+
+        let index_len = u64::from_le_bytes(map[8..16].try_into()?) as usize;
+        let data_offset = 16 + index_len;
+        let index = map
+            .get(16..data_offset)
+            .ok_or_else(|| anyhow!("truncated pack: {}", path.display()))?;
+        let entries: BTreeMap<String, PackEntry> = bincode::deserialize(index)?;
+
+        let data_len = (map.len() - data_offset) as u64;
+        if let Some((name, _)) = entries
+            .iter()
+            .find(|(_, entry)| entry.offset + entry.len > data_len)
+        {
+            return Err(anyhow!("{} past the end of {}", name, path.display()));
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            map,
+            entries,
+            data_offset,
+        })
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub(crate) fn read(&self, name: &str) -> Result<&[u8]> {
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| anyhow!("{} not in pack {}", name, self.path.display()))?;
+
+        let start = self.data_offset + entry.offset as usize;
+        Ok(&self.map[start..start + entry.len as usize])
+    }
+}
+
+/// Writes the files in name order, the same files always give the same pack.
+pub(crate) fn write_pack(path: &Path, files: &BTreeMap<String, Vec<u8>>) -> Result<()> {
+    let mut entries = BTreeMap::new();
+    let mut offset = 0;
+    for (name, bytes) in files {
+        let len = bytes.len() as u64;
+        entries.insert(name.clone(), PackEntry { offset, len });
+        offset += len;
+    }
+    let index = bincode::serialize(&entries)?;
+
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&(index.len() as u64).to_le_bytes())?;
+    writer.write_all(&index)?;
+    for bytes in files.values() {
+        writer.write_all(bytes)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_dir;
+    use crate::vfs::Vfs;
+
+    fn files() -> BTreeMap<String, Vec<u8>> {
+        BTreeMap::from([
+            ("models/cube.bin".to_owned(), b"cube".to_vec()),
+            ("shaders/shader.vert.spv".to_owned(), b"vert".to_vec()),
+            ("empty".to_owned(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn round_trips_its_files() {
+        let dir = test_dir("pack-round-trip");
+        let path = dir.join("assets.ypak");
+        write_pack(&path, &files()).unwrap();
+
+        let pack = Pack::open(&path).unwrap();
+
+        for (name, bytes) in files() {
+            assert!(pack.contains(&name));
+            assert_eq!(pack.read(&name).unwrap(), bytes.as_slice());
+        }
+        assert!(!pack.contains("models/sphere.bin"));
+        assert!(pack.read("models/sphere.bin").is_err());
+
+        drop(pack);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn is_mounted_over_directories() {
+        let dir = test_dir("pack-mount");
+        std::fs::create_dir_all(dir.join("assets/models")).unwrap();
+        std::fs::write(dir.join("assets/models/cube.bin"), b"loose").unwrap();
+        std::fs::write(dir.join("assets/models/quad.bin"), b"loose").unwrap();
+        write_pack(&dir.join("assets.ypak"), &files()).unwrap();
+
+        let mut vfs = Vfs::empty();
+        vfs.mount_source("", dir.join("assets"))
+            .mount_pack("", dir.join("assets.ypak"))
+            .unwrap();
+
+        assert_eq!(vfs.read("models/cube.bin").unwrap().as_ref(), b"cube");
+        assert_eq!(vfs.read("models/quad.bin").unwrap().as_ref(), b"loose");
+        assert!(vfs.exists("shaders/shader.vert.spv"));
+
+        drop(vfs);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let dir = test_dir("pack-format");
+        let path = dir.join("assets.ypak");
+        write_pack(&path, &files()).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Pack::open(&path).is_err());
+
+        std::fs::write(&path, b"YUMI").unwrap();
+        assert!(Pack::open(&path).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_packs() {
+        let dir = test_dir("pack-truncated");
+        let path = dir.join("assets.ypak");
+        write_pack(&path, &files()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Pack::open(&path).is_err());
+
+        std::fs::write(&path, &bytes[..20]).unwrap();
+        assert!(Pack::open(&path).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::HashSet;
 
 use log::{info, warn};
 
+use serde::{Deserialize, Serialize};
+
 use thiserror::Error;
 
 use anyhow::{anyhow, Result};
@@ -17,7 +19,7 @@ use crate::swapchain::SwapchainSupport;
 pub(crate) const DEVICE_EXTENSIONS: &[vk::ExtensionName] = &[vk::KHR_SWAPCHAIN_EXTENSION.name];
 
 #[derive(Debug, Error)]
-#[error("Missing {0}.")]
+#[error("{0}")]
 pub(crate) struct SuitabilityError(pub(crate) &'static str);
 
 unsafe fn check_physical_device_extensions(
@@ -38,71 +40,276 @@ unsafe fn check_physical_device_extensions(
     }
 }
 
+/// Environment variable forcing the physical device like [`PhysicalDeviceSelector::parse`],
+/// over the renderer settings.
+pub(crate) const PHYSICAL_DEVICE_ENV: &str = "YUUMI_PHYSICAL_DEVICE";
+
+/// The kind of a physical device, from the fastest to the slowest in general.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhysicalDeviceKind {
+    Discrete,
+    Integrated,
+    Virtual,
+    Cpu,
+    Other,
+}
+
+impl From<vk::PhysicalDeviceType> for PhysicalDeviceKind {
+    fn from(device_type: vk::PhysicalDeviceType) -> Self {
+        match device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => Self::Discrete,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => Self::Integrated,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => Self::Virtual,
+            vk::PhysicalDeviceType::CPU => Self::Cpu,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A physical device considered during selection.
+#[derive(Clone, Debug)]
+pub struct PhysicalDeviceCandidate {
+    /// Position in the Vulkan enumeration order, usable to force the device.
+    pub index: usize,
+    pub name: String,
+    pub kind: PhysicalDeviceKind,
+    /// Higher is better, only meaningful for suitable devices.
+    pub score: u64,
+    /// Why the device can't be used, `None` when it is suitable.
+    pub unsuitable: Option<String>,
+}
+
+/// How the physical device is chosen. Saved in the renderer settings as the text
+/// [`PhysicalDeviceSelector::parse`] reads.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum PhysicalDeviceSelector {
+    /// Pick the suitable device with the highest score.
+    #[default]
+    Best,
+    /// Force the device at this enumeration index.
+    Index(usize),
+    /// Force the suitable device with the highest score among those whose name contains this
+    /// text, ignoring case.
+    Name(String),
+}
+
+impl From<String> for PhysicalDeviceSelector {
+    fn from(text: String) -> Self {
+        Self::parse(&text)
+    }
+}
+
+impl From<PhysicalDeviceSelector> for String {
+    fn from(selector: PhysicalDeviceSelector) -> Self {
+        match selector {
+            PhysicalDeviceSelector::Best => "auto".to_owned(),
+            PhysicalDeviceSelector::Index(index) => index.to_string(),
+            PhysicalDeviceSelector::Name(name) => name,
+        }
+    }
+}
+
+impl PhysicalDeviceSelector {
+    /// Parses an index or a device name, empty text and `auto` mean [`Self::Best`].
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        if text.is_empty() || text.eq_ignore_ascii_case("auto") {
+            Self::Best
+        } else if let Ok(index) = text.parse() {
+            Self::Index(index)
+        } else {
+            Self::Name(text.to_owned())
+        }
+    }
+
+    fn from_env() -> Option<Self> {
+        std::env::var(PHYSICAL_DEVICE_ENV)
+            .ok()
+            .map(|text| Self::parse(&text))
+    }
+
+    fn matches(&self, candidate: &PhysicalDeviceCandidate) -> bool {
+        match self {
+            Self::Best => true,
+            Self::Index(index) => candidate.index == *index,
+            Self::Name(name) => candidate.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
 unsafe fn check_physical_device(
     instance: &Instance,
     data: &AppData,
     physical_device: vk::PhysicalDevice,
 ) -> Result<()> {
-    let properties = instance.get_physical_device_properties(physical_device);
-    if properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU {
-        return Err(anyhow!(SuitabilityError(
-            "Only discrete GPUs are supported."
-        )));
+    QueueFamilyIndices::get(instance, data, physical_device)?;
+
+    if !data.headless {
+        check_physical_device_extensions(instance, physical_device)?;
+
+        let support = SwapchainSupport::get(instance, data, physical_device)?;
+        if support.formats.is_empty() || support.present_modes.is_empty() {
+            return Err(anyhow!(SuitabilityError("Insufficient swapchain support.")));
+        }
     }
 
+    Ok(())
+}
+
+unsafe fn score_physical_device(instance: &Instance, physical_device: vk::PhysicalDevice) -> u64 {
+    let properties = instance.get_physical_device_properties(physical_device);
     let features = instance.get_physical_device_features(physical_device);
-    if features.sampler_anisotropy != vk::TRUE {
-        return Err(anyhow!(SuitabilityError("No sampler anisotropy.")));
-    }
-    if features.geometry_shader != vk::TRUE {
-        return Err(anyhow!(SuitabilityError(
-            "Missing geometry shader support."
-        )));
-    }
+    let memory = instance.get_physical_device_memory_properties(physical_device);
 
-    QueueFamilyIndices::get(instance, data, physical_device)?;
-    check_physical_device_extensions(instance, physical_device)?;
+    let mut score = match PhysicalDeviceKind::from(properties.device_type) {
+        PhysicalDeviceKind::Discrete => 10_000,
+        PhysicalDeviceKind::Integrated => 5_000,
+        PhysicalDeviceKind::Virtual => 2_500,
+        PhysicalDeviceKind::Cpu => 1_000,
+        PhysicalDeviceKind::Other => 0,
+    };
 
-    let support = SwapchainSupport::get(instance, data, physical_device)?;
-    if support.formats.is_empty() || support.present_modes.is_empty() {
-        return Err(anyhow!(SuitabilityError("Insufficient swapchain support.")));
+    // Break ties between devices of the same kind with their capabilities.
+    let device_local_memory = memory.memory_heaps[..memory.memory_heap_count as usize]
+        .iter()
+        .filter(|h| h.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|h| h.size)
+        .sum::<u64>();
+    score += device_local_memory / (256 * 1024 * 1024);
+    score += properties.limits.max_image_dimension_2d as u64 / 1024;
+    if features.sampler_anisotropy == vk::TRUE {
+        score += 100;
     }
 
-    Ok(())
+    score
 }
 
-pub(crate) unsafe fn pick_physical_device(instance: &Instance, data: &mut AppData) -> Result<()> {
-    for physical_device in instance.enumerate_physical_devices()? {
+/// Lists every physical device with its score, or the reason it can't be used.
+pub(crate) unsafe fn get_physical_device_candidates(
+    instance: &Instance,
+    data: &AppData,
+) -> Result<Vec<(vk::PhysicalDevice, PhysicalDeviceCandidate)>> {
+    let mut candidates = Vec::new();
+
+    for (index, physical_device) in instance
+        .enumerate_physical_devices()?
+        .into_iter()
+        .enumerate()
+    {
         let properties = instance.get_physical_device_properties(physical_device);
 
-        if let Err(error) = check_physical_device(instance, data, physical_device) {
+        let unsuitable = check_physical_device(instance, data, physical_device)
+            .err()
+            .map(|error| error.to_string());
+
+        candidates.push((
+            physical_device,
+            PhysicalDeviceCandidate {
+                index,
+                name: properties.device_name.to_string(),
+                kind: properties.device_type.into(),
+                score: score_physical_device(instance, physical_device),
+                unsuitable,
+            },
+        ));
+    }
+
+    Ok(candidates)
+}
+
+/// Selects the physical device and returns every candidate, best first. [`PHYSICAL_DEVICE_ENV`]
+/// overrides `selector` when set.
+pub(crate) unsafe fn pick_physical_device(
+    instance: &Instance,
+    data: &mut AppData,
+    selector: &PhysicalDeviceSelector,
+) -> Result<Vec<PhysicalDeviceCandidate>> {
+    let selector = PhysicalDeviceSelector::from_env().unwrap_or_else(|| selector.clone());
+    let mut candidates = get_physical_device_candidates(instance, data)?;
+
+    // Best first, the enumeration order breaks ties.
+    candidates.sort_by(|(_, a), (_, b)| b.score.cmp(&a.score).then(a.index.cmp(&b.index)));
+
+    let mut selected: Option<(vk::PhysicalDevice, &PhysicalDeviceCandidate)> = None;
+    for (physical_device, candidate) in &candidates {
+        if let Some(reason) = &candidate.unsuitable {
             warn!(
-                "Skipping physical device (`{}`): {}",
-                properties.device_name, error
+                "Skipping physical device {} (`{}`, {:?}): {}",
+                candidate.index, candidate.name, candidate.kind, reason
+            );
+        } else if !selector.matches(candidate) {
+            info!(
+                "Skipping physical device {} (`{}`, {:?}): not matching {:?}.",
+                candidate.index, candidate.name, candidate.kind, selector
+            );
+        } else if let Some((_, selected)) = selected {
+            info!(
+                "Skipping physical device {} (`{}`, {:?}): ranked below `{}` (score {} vs {}).",
+                candidate.index,
+                candidate.name,
+                candidate.kind,
+                selected.name,
+                candidate.score,
+                selected.score
             );
         } else {
-            info!("Selected physical device (`{}`).", properties.device_name);
-            data.physical_device = physical_device;
-            data.msaa_samples = get_max_msaa_samples(instance, data);
-
-            data.limit_max_sampler_anisotropy = properties.limits.max_sampler_anisotropy;
-            data.limit_max_push_constants_size = properties.limits.max_push_constants_size;
-
-            // TODO settings file
-            data.setting_anisotropy = true;
-            data.setting_max_sampler_anisotropy = data.limit_max_sampler_anisotropy;
-            data.setting_sample_shading = true;
-            return Ok(());
+            selected = Some((*physical_device, candidate));
         }
     }
 
-    Err(anyhow!("Failed to find suitable physical device."))
+    let (physical_device, candidate) = selected.ok_or_else(|| match selector {
+        PhysicalDeviceSelector::Best => anyhow!("Failed to find suitable physical device."),
+        _ => anyhow!(
+            "Failed to find suitable physical device matching {:?}.",
+            selector
+        ),
+    })?;
+
+    match selector {
+        PhysicalDeviceSelector::Best => info!(
+            "Selected physical device {} (`{}`, {:?}) with the highest score {}.",
+            candidate.index, candidate.name, candidate.kind, candidate.score
+        ),
+        _ => info!(
+            "Selected physical device {} (`{}`, {:?}) forced by {:?}.",
+            candidate.index, candidate.name, candidate.kind, selector
+        ),
+    }
+
+    let properties = instance.get_physical_device_properties(physical_device);
+    let features = instance.get_physical_device_features(physical_device);
+
+    data.physical_device = physical_device;
+    data.allocator
+        .set_memory_properties(instance.get_physical_device_memory_properties(physical_device));
+    data.limit_max_msaa_samples = get_max_msaa_samples(instance, data);
+
+    data.limit_max_sampler_anisotropy = properties.limits.max_sampler_anisotropy;
+    data.limit_max_push_constants_size = properties.limits.max_push_constants_size;
+
+    // Enabled whenever supported, so the settings can toggle them without a new device.
+    data.feature_sampler_anisotropy = features.sampler_anisotropy == vk::TRUE;
+    data.feature_sample_rate_shading = features.sample_rate_shading == vk::TRUE;
+    data.feature_multi_draw_indirect = features.multi_draw_indirect == vk::TRUE;
+    data.feature_draw_indirect_first_instance = features.draw_indirect_first_instance == vk::TRUE;
+
+    data.limit_max_draw_indirect_count = if data.feature_multi_draw_indirect {
+        properties.limits.max_draw_indirect_count
+    } else {
+        1
+    };
+
+    Ok(candidates.into_iter().map(|(_, c)| c).collect())
 }
 
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct QueueFamilyIndices {
     pub(crate) graphics: u32,
     pub(crate) present: u32,
+    /// Family and index of the queue streaming uploads run on, `None` when every queue is
+    /// already used by graphics and presentation.
+    pub(crate) transfer: Option<(u32, u32)>,
 }
 
 impl QueueFamilyIndices {
@@ -113,6 +320,29 @@ impl QueueFamilyIndices {
     ) -> Result<Self> {
         let properties = instance.get_physical_device_queue_family_properties(physical_device);
 
+        // The culling pass is recorded along with the draws.
+        let graphics = properties
+            .iter()
+            .position(|p| {
+                p.queue_flags
+                    .contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .map(|i| i as u32);
+
+        // Without a surface there is nothing to present to, the graphics queue does it all.
+        if data.headless {
+            return match graphics {
+                Some(graphics) => Ok(Self {
+                    graphics,
+                    present: graphics,
+                    transfer: find_transfer_queue(&properties, graphics, graphics),
+                }),
+                None => Err(anyhow!(SuitabilityError(
+                    "Missing required queue families."
+                ))),
+            };
+        }
+
         let mut present = None;
         for (index, _properties) in properties.iter().enumerate() {
             if instance.get_physical_device_surface_support_khr(
@@ -125,13 +355,12 @@ impl QueueFamilyIndices {
             }
         }
 
-        let graphics = properties
-            .iter()
-            .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
-            .map(|i| i as u32);
-
         if let (Some(graphics), Some(present)) = (graphics, present) {
-            Ok(Self { graphics, present })
+            Ok(Self {
+                graphics,
+                present,
+                transfer: find_transfer_queue(&properties, graphics, present),
+            })
         } else {
             Err(anyhow!(SuitabilityError(
                 "Missing required queue families."
@@ -139,3 +368,92 @@ impl QueueFamilyIndices {
         }
     }
 }
+
+/// Prefers a transfer only family, usually backed by the copy engines of discrete GPUs, then
+/// another family, then a spare queue of the graphics or present family.
+fn find_transfer_queue(
+    properties: &[vk::QueueFamilyProperties],
+    graphics: u32,
+    present: u32,
+) -> Option<(u32, u32)> {
+    properties
+        .iter()
+        .enumerate()
+        .filter_map(|(index, p)| {
+            let index = index as u32;
+            // Graphics and compute queues support transfers without advertising it.
+            let flags = p.queue_flags;
+            let transfer = flags.intersects(
+                vk::QueueFlags::TRANSFER | vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+            );
+            let taken = u32::from(index == graphics || index == present);
+            if !transfer || p.queue_count <= taken {
+                return None;
+            }
+
+            let rank = if taken == 1 {
+                2
+            } else if flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE) {
+                1
+            } else {
+                0
+            };
+            Some((rank, index, taken))
+        })
+        .min()
+        .map(|(_, index, queue)| (index, queue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_indices_and_names() {
+        assert_eq!(
+            PhysicalDeviceSelector::parse(""),
+            PhysicalDeviceSelector::Best
+        );
+        assert_eq!(
+            PhysicalDeviceSelector::parse(" Auto "),
+            PhysicalDeviceSelector::Best
+        );
+        assert_eq!(
+            PhysicalDeviceSelector::parse("1"),
+            PhysicalDeviceSelector::Index(1)
+        );
+        assert_eq!(
+            PhysicalDeviceSelector::parse(" GeForce RTX "),
+            PhysicalDeviceSelector::Name("GeForce RTX".to_owned())
+        );
+    }
+
+    #[test]
+    fn matches_names_ignoring_case() {
+        let candidate = PhysicalDeviceCandidate {
+            index: 1,
+            name: "NVIDIA GeForce RTX 3080".to_owned(),
+            kind: PhysicalDeviceKind::Discrete,
+            score: 0,
+            unsuitable: None,
+        };
+
+        assert!(PhysicalDeviceSelector::Best.matches(&candidate));
+        assert!(PhysicalDeviceSelector::Index(1).matches(&candidate));
+        assert!(!PhysicalDeviceSelector::Index(0).matches(&candidate));
+        assert!(PhysicalDeviceSelector::parse("geforce").matches(&candidate));
+        assert!(!PhysicalDeviceSelector::parse("radeon").matches(&candidate));
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        for selector in [
+            PhysicalDeviceSelector::Best,
+            PhysicalDeviceSelector::Index(2),
+            PhysicalDeviceSelector::Name("llvmpipe".to_owned()),
+        ] {
+            let text = String::from(selector.clone());
+            assert_eq!(PhysicalDeviceSelector::from(text), selector);
+        }
+    }
+}
@@ -4,29 +4,53 @@ use vulkanalia::prelude::v1_0::*;
 use vulkanalia::Device;
 
 use crate::app::AppData;
+use crate::render_pass::SCENE_PASS;
 use crate::shader::create_shader_module;
-use crate::vertex::{InstanceData, Vertex};
+use crate::vertex::{DrawInstance, SkinnedVertex, Vertex};
 
 pub(crate) unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
-    let vert_push_constant_range = vk::PushConstantRange::builder()
-        .stage_flags(vk::ShaderStageFlags::VERTEX)
-        .offset(0)
-        .size(64 /* 16 × 4 byte floats */);
-
-    let frag_push_constant_range = vk::PushConstantRange::builder()
-        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
-        .offset(64)
-        .size(4);
-
-    let set_layouts = &[data.descriptor_set_layout];
-    let push_constant_ranges = &[vert_push_constant_range, frag_push_constant_range];
-    let layout_info = vk::PipelineLayoutCreateInfo::builder()
-        .set_layouts(set_layouts)
-        .push_constant_ranges(push_constant_ranges);
+    let set_layouts = &[
+        data.descriptor_set_layout,
+        data.material_set_layout,
+        data.draw_set_layout,
+    ];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
     data.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
 
-    let vert_shader_module = create_shader_module(device, "vert".to_owned())?;
-    let frag_shader_module = create_shader_module(device, "frag".to_owned())?;
+    let mut attribute_descriptions = Vertex::attribute_descriptions();
+    attribute_descriptions.extend(&DrawInstance::attribute_descriptions());
+    data.pipeline = create_scene_pipeline(
+        device,
+        data,
+        "shader.vert",
+        Vertex::binding_description(),
+        &attribute_descriptions,
+    )?;
+
+    // Shares the layout, the draw set holds the joint matrices.
+    let mut attribute_descriptions = SkinnedVertex::attribute_descriptions();
+    attribute_descriptions.extend(&DrawInstance::attribute_descriptions());
+    data.skinned_pipeline = create_scene_pipeline(
+        device,
+        data,
+        "skinned.vert",
+        SkinnedVertex::binding_description(),
+        &attribute_descriptions,
+    )?;
+
+    Ok(())
+}
+
+/// A pipeline of the scene pass, `vertex_binding` describes the vertices of binding 0.
+unsafe fn create_scene_pipeline(
+    device: &Device,
+    data: &AppData,
+    vert_shader: &str,
+    vertex_binding: vk::VertexInputBindingDescription,
+    attribute_descriptions: &[vk::VertexInputAttributeDescription],
+) -> Result<vk::Pipeline> {
+    let vert_shader_module = create_shader_module(device, &data.vfs, vert_shader.to_owned())?;
+    let frag_shader_module = create_shader_module(device, &data.vfs, "shader.frag".to_owned())?;
 
     let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::VERTEX)
@@ -38,38 +62,23 @@ pub(crate) unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Res
         .module(frag_shader_module)
         .name(b"main\0");
 
-    let binding_descriptions = &[
-        Vertex::binding_description(),
-        InstanceData::binding_description(),
-    ];
-    let mut attribute_descriptions: Vec<vk::VertexInputAttributeDescription> = Vec::new();
-    attribute_descriptions.extend(&Vertex::attribute_descriptions());
-    attribute_descriptions.extend(&InstanceData::attribute_descriptions());
+    let binding_descriptions = &[vertex_binding, DrawInstance::binding_description()];
     let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
         .vertex_binding_descriptions(binding_descriptions)
-        .vertex_attribute_descriptions(&attribute_descriptions);
+        .vertex_attribute_descriptions(attribute_descriptions);
 
     let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
         .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
         .primitive_restart_enable(false);
 
-    let viewport = vk::Viewport::builder()
-        .x(0.0)
-        .y(0.0)
-        .width(data.swapchain_extent.width as f32)
-        .height(data.swapchain_extent.height as f32)
-        .min_depth(0.0)
-        .max_depth(1.0);
-
-    let scissor = vk::Rect2D::builder()
-        .offset(vk::Offset2D { x: 0, y: 0 })
-        .extent(data.swapchain_extent);
-
-    let viewports = &[viewport];
-    let scissors = &[scissor];
+    // Set when recording, so resizing doesn't rebuild the pipeline.
     let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(viewports)
-        .scissors(scissors);
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
 
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
         .depth_clamp_enable(false)
@@ -118,16 +127,17 @@ pub(crate) unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Res
         .multisample_state(&multisample_state)
         .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
         .layout(data.pipeline_layout)
-        .render_pass(data.render_pass)
+        .render_pass(data.render_graph.render_pass(SCENE_PASS)?)
         .subpass(0);
 
-    data.pipeline = device
+    let pipeline = device
         .create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)?
         .0;
 
     device.destroy_shader_module(vert_shader_module, None);
     device.destroy_shader_module(frag_shader_module, None);
 
-    Ok(())
+    Ok(pipeline)
 }
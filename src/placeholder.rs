@@ -0,0 +1,95 @@
+use cgmath::{vec2, vec3, vec4, Vector3};
+
+use crate::bounds::Bounds;
+use crate::mesh::SerializedMesh;
+use crate::model::SerializedModel;
+use crate::vertex::Vertex;
+
+/// Texels per side of the checkerboard.
+const CHECKERBOARD_SIZE: u32 = 64;
+/// Texels per side of a square of the checkerboard.
+const CHECKER_SIZE: u32 = 8;
+
+/// Grey checkerboard sampled in place of the textures that are not loaded, as RGBA8.
+pub(crate) fn checkerboard() -> (Vec<u8>, u32, u32) {
+    let pixels = (0..CHECKERBOARD_SIZE * CHECKERBOARD_SIZE)
+        .flat_map(|texel| {
+            let (x, y) = (texel % CHECKERBOARD_SIZE, texel / CHECKERBOARD_SIZE);
+            let shade = if (x / CHECKER_SIZE + y / CHECKER_SIZE).is_multiple_of(2) {
+                96
+            } else {
+                160
+            };
+            [shade, shade, shade, 255]
+        })
+        .collect();
+
+    (pixels, CHECKERBOARD_SIZE, CHECKERBOARD_SIZE)
+}
+
+/// Unit cube around the origin drawn in place of the models that are not loaded, without
+/// materials.
+pub(crate) fn cube() -> SerializedModel {
+    // The normal of each face, then two axes along it whose cross product is the normal, so the
+    // corners wind counter clockwise seen from outside.
+    let faces: [[Vector3<f32>; 3]; 6] = [
+        [
+            vec3(1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+        ],
+        [
+            vec3(-1.0, 0.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 1.0, 0.0),
+        ],
+        [
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+            vec3(1.0, 0.0, 0.0),
+        ],
+        [
+            vec3(0.0, -1.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+        ],
+        [
+            vec3(0.0, 0.0, 1.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+        ],
+        [
+            vec3(0.0, 0.0, -1.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+        ],
+    ];
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for [normal, u, v] in faces {
+        let first = vertices.len() as u32;
+        vertices.extend(corners.iter().map(|(x, y)| Vertex {
+            tex_coord: vec2((x + 1.0) / 2.0, (1.0 - y) / 2.0),
+            normal,
+            tangent: vec4(u.x, u.y, u.z, 1.0),
+            ..Vertex::at((normal + u * *x + v * *y) * 0.5)
+        }));
+        indices.extend([0, 1, 2, 0, 2, 3].map(|index| first + index));
+    }
+
+    SerializedModel {
+        meshes: vec![SerializedMesh {
+            bounds: Bounds::from_vertices(&vertices),
+            vertices,
+            skin: Vec::new(),
+            indices,
+            material: None,
+        }],
+        materials: Vec::new(),
+        textures: Vec::new(),
+        skeleton: None,
+        animations: Vec::new(),
+    }
+}
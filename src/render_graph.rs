@@ -0,0 +1,707 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    allocator::{Allocation, Allocator},
+    app::AppData,
+    image_view::create_image_view,
+    texture_image::create_image,
+};
+
+/// The swapchain images, or the offscreen images of a headless app. Imported, not created by
+/// the graph.
+pub(crate) const BACKBUFFER: &str = "backbuffer";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AttachmentKind {
+    Color,
+    Depth,
+}
+
+/// An image created and owned by the graph.
+#[derive(Clone, Debug)]
+pub(crate) struct AttachmentDesc {
+    pub(crate) name: &'static str,
+    pub(crate) kind: AttachmentKind,
+    pub(crate) format: vk::Format,
+    pub(crate) samples: vk::SampleCountFlags,
+    /// Size relative to the backbuffer.
+    pub(crate) scale: f32,
+}
+
+/// A render pass declared by the attachments it writes and the ones it samples.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PassDesc {
+    pub(crate) name: &'static str,
+    pub(crate) color: Vec<&'static str>,
+    pub(crate) depth: Option<&'static str>,
+    /// Receives the multisampled color attachment of the same index.
+    pub(crate) resolve: Vec<&'static str>,
+    /// Attachments written by earlier passes, sampled by the fragment shaders of this one.
+    pub(crate) reads: Vec<&'static str>,
+    pub(crate) clear_color: [f32; 4],
+}
+
+/// Passes run in declaration order.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RenderGraphDesc {
+    pub(crate) attachments: Vec<AttachmentDesc>,
+    pub(crate) passes: Vec<PassDesc>,
+    /// Attachments sampled by compute shaders recorded after the pass writing them last.
+    pub(crate) exports: Vec<&'static str>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Usage {
+    Color,
+    Depth,
+    Resolve,
+    Read,
+}
+
+impl Usage {
+    fn layout(self) -> vk::ImageLayout {
+        match self {
+            Usage::Color | Usage::Resolve => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Usage::Depth => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            Usage::Read => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    fn image_usage(self) -> vk::ImageUsageFlags {
+        match self {
+            Usage::Color | Usage::Resolve => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            Usage::Depth => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            Usage::Read => vk::ImageUsageFlags::SAMPLED,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct GraphImage {
+    image: vk::Image,
+    memory: Allocation,
+    view: vk::ImageView,
+}
+
+#[derive(Clone, Debug, Default)]
+struct CompiledPass {
+    name: &'static str,
+    render_pass: vk::RenderPass,
+    /// Attachments of the render pass, in attachment order.
+    attachments: Vec<&'static str>,
+    /// Shared by all the attachments of the pass.
+    scale: f32,
+    /// One per backbuffer image when the pass writes the backbuffer, a single one otherwise.
+    framebuffers: Vec<vk::Framebuffer>,
+    clear_values: Vec<vk::ClearValue>,
+}
+
+/// Render passes derived from a [`RenderGraphDesc`], along with the extent dependent
+/// framebuffers and images, which [`RenderGraph::create_targets`] can rebuild on their own.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RenderGraph {
+    passes: Vec<CompiledPass>,
+    attachments: Vec<(AttachmentDesc, vk::ImageUsageFlags)>,
+    images: HashMap<&'static str, GraphImage>,
+}
+
+impl RenderGraph {
+    /// Derives the load/store ops, layouts and dependencies of every pass from the order in
+    /// which the attachments are used. The targets are created separately.
+    pub(crate) unsafe fn compile(
+        desc: &RenderGraphDesc,
+        device: &Device,
+        data: &AppData,
+    ) -> Result<Self> {
+        let attachments = desc
+            .attachments
+            .iter()
+            .map(|a| (a.name, a))
+            .collect::<HashMap<_, _>>();
+        let uses = attachment_uses(desc)?;
+
+        let mut graph = Self::default();
+
+        for attachment in &desc.attachments {
+            let Some(attachment_uses) = uses.get(attachment.name) else {
+                continue;
+            };
+
+            graph
+                .attachments
+                .push((attachment.clone(), image_usage(attachment_uses)));
+        }
+
+        // Offscreen targets are read back after the frame instead of being presented.
+        let backbuffer_final_layout = if data.headless {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        };
+
+        for (index, pass) in desc.passes.iter().enumerate() {
+            let mut descriptions = Vec::new();
+            let mut clear_values = Vec::new();
+            let mut names = Vec::new();
+            let mut color_refs = Vec::new();
+            let mut depth_ref = None;
+            let mut resolve_refs = Vec::new();
+            let mut scale = None;
+            let mut writes_earlier_output = false;
+            let mut output_read_later = false;
+
+            let pass_attachments = pass
+                .color
+                .iter()
+                .map(|n| (*n, Usage::Color))
+                .chain(pass.depth.iter().map(|n| (*n, Usage::Depth)))
+                .chain(pass.resolve.iter().map(|n| (*n, Usage::Resolve)));
+
+            for (name, usage) in pass_attachments {
+                let attachment_uses = &uses[name];
+                let position = attachment_uses
+                    .iter()
+                    .position(|(p, u)| *p == index && *u == usage)
+                    .expect("Attachment use recorded");
+                let previous = position.checked_sub(1).map(|i| attachment_uses[i]);
+                let next = attachment_uses.get(position + 1);
+                let imported = name == BACKBUFFER;
+
+                let attachment_scale = if imported {
+                    1.0
+                } else {
+                    attachments[name].scale
+                };
+                if *scale.get_or_insert(attachment_scale) != attachment_scale {
+                    return Err(anyhow!(
+                        "Pass {} mixes attachments of different sizes",
+                        pass.name
+                    ));
+                }
+
+                let initial_layout = previous
+                    .map(|(_, u)| u.layout())
+                    .unwrap_or(vk::ImageLayout::UNDEFINED);
+                let final_layout = match next {
+                    Some((_, u)) => u.layout(),
+                    None if imported => backbuffer_final_layout,
+                    None => usage.layout(),
+                };
+                let load_op = match (previous, usage) {
+                    (Some(_), _) => vk::AttachmentLoadOp::LOAD,
+                    (None, Usage::Resolve) => vk::AttachmentLoadOp::DONT_CARE,
+                    (None, _) => vk::AttachmentLoadOp::CLEAR,
+                };
+                let store_op = if next.is_some() || imported {
+                    vk::AttachmentStoreOp::STORE
+                } else {
+                    vk::AttachmentStoreOp::DONT_CARE
+                };
+
+                writes_earlier_output |= previous.is_some();
+                output_read_later |= next.is_some();
+
+                let (format, samples) = if imported {
+                    (data.swapchain_format, vk::SampleCountFlags::_1)
+                } else {
+                    (attachments[name].format, attachments[name].samples)
+                };
+
+                descriptions.push(
+                    vk::AttachmentDescription::builder()
+                        .format(format)
+                        .samples(samples)
+                        .load_op(load_op)
+                        .store_op(store_op)
+                        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                        .initial_layout(initial_layout)
+                        .final_layout(final_layout)
+                        .build(),
+                );
+
+                clear_values.push(match usage {
+                    Usage::Depth => vk::ClearValue {
+                        depth_stencil: vk::ClearDepthStencilValue {
+                            depth: 1.0,
+                            stencil: 0,
+                        },
+                    },
+                    _ => vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: pass.clear_color,
+                        },
+                    },
+                });
+
+                let reference = vk::AttachmentReference::builder()
+                    .attachment(names.len() as u32)
+                    .layout(usage.layout())
+                    .build();
+                match usage {
+                    Usage::Color => color_refs.push(reference),
+                    Usage::Depth => depth_ref = Some(reference),
+                    Usage::Resolve => resolve_refs.push(reference),
+                    Usage::Read => unreachable!("Sampled attachments are not pass attachments"),
+                }
+
+                names.push(name);
+            }
+
+            let mut subpass = vk::SubpassDescription::builder()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&color_refs)
+                .resolve_attachments(&resolve_refs);
+            if let Some(depth_ref) = &depth_ref {
+                subpass = subpass.depth_stencil_attachment(depth_ref);
+            }
+
+            let attachment_stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+            let attachment_writes = vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+
+            // Waits for the earlier passes writing what this one reads or keeps writing.
+            let (mut dst_stages, mut dst_access) = (attachment_stages, attachment_writes);
+            if !pass.reads.is_empty() {
+                dst_stages |= vk::PipelineStageFlags::FRAGMENT_SHADER;
+                dst_access |= vk::AccessFlags::SHADER_READ;
+            }
+            let src_access = if writes_earlier_output || !pass.reads.is_empty() {
+                attachment_writes
+            } else {
+                vk::AccessFlags::empty()
+            };
+            let incoming = vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(attachment_stages)
+                .src_access_mask(src_access)
+                .dst_stage_mask(dst_stages)
+                .dst_access_mask(dst_access)
+                .build();
+
+            // Makes the writes of this pass visible to the later passes and the compute shaders
+            // reading the exports.
+            let outgoing = vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(attachment_stages)
+                .src_access_mask(attachment_writes)
+                .dst_stage_mask(
+                    attachment_stages
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags::COMPUTE_SHADER,
+                )
+                .dst_access_mask(
+                    attachment_writes
+                        | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::SHADER_READ,
+                )
+                .build();
+
+            let mut dependencies = vec![incoming];
+            if output_read_later {
+                dependencies.push(outgoing);
+            }
+
+            let subpasses = &[subpass];
+            let info = vk::RenderPassCreateInfo::builder()
+                .attachments(&descriptions)
+                .subpasses(subpasses)
+                .dependencies(&dependencies);
+
+            let render_pass = device.create_render_pass(&info, None)?;
+
+            graph.passes.push(CompiledPass {
+                name: pass.name,
+                render_pass,
+                attachments: names,
+                scale: scale.unwrap_or(1.0),
+                framebuffers: Vec::new(),
+                clear_values,
+            });
+        }
+
+        Ok(graph)
+    }
+
+    /// Creates the images owned by the graph and the framebuffers of every pass, sized like the
+    /// backbuffer.
+    pub(crate) unsafe fn create_targets(
+        &mut self,
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+    ) -> Result<()> {
+        for (attachment, usage) in &self.attachments {
+            let extent = scaled_extent(data.swapchain_extent, attachment.scale);
+            let (image, memory) = create_image(
+                instance,
+                device,
+                data,
+                extent.width,
+                extent.height,
+                1,
+                attachment.samples,
+                attachment.format,
+                vk::ImageTiling::OPTIMAL,
+                *usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )?;
+
+            let aspects = match attachment.kind {
+                AttachmentKind::Color => vk::ImageAspectFlags::COLOR,
+                AttachmentKind::Depth => vk::ImageAspectFlags::DEPTH,
+            };
+            let view = create_image_view(device, &image, &attachment.format, &aspects, &1)?;
+
+            self.images.insert(
+                attachment.name,
+                GraphImage {
+                    image,
+                    memory,
+                    view,
+                },
+            );
+        }
+
+        for pass in &mut self.passes {
+            let framebuffer_count = if pass.attachments.contains(&BACKBUFFER) {
+                data.swapchain_image_views.len()
+            } else {
+                1
+            };
+            let extent = scaled_extent(data.swapchain_extent, pass.scale);
+            pass.framebuffers = (0..framebuffer_count)
+                .map(|i| {
+                    let views = pass
+                        .attachments
+                        .iter()
+                        .map(|name| match *name {
+                            BACKBUFFER => data.swapchain_image_views[i],
+                            name => self.images[name].view,
+                        })
+                        .collect::<Vec<_>>();
+
+                    let info = vk::FramebufferCreateInfo::builder()
+                        .render_pass(pass.render_pass)
+                        .attachments(&views)
+                        .width(extent.width)
+                        .height(extent.height)
+                        .layers(1);
+
+                    device.create_framebuffer(&info, None)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn pass_count(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub(crate) fn pass_name(&self, pass: usize) -> &'static str {
+        self.passes[pass].name
+    }
+
+    pub(crate) fn has_pass(&self, name: &str) -> bool {
+        self.passes.iter().any(|p| p.name == name)
+    }
+
+    /// Size of the attachments of a pass, given the size of the backbuffer.
+    pub(crate) fn pass_extent(&self, pass: usize, backbuffer: vk::Extent2D) -> vk::Extent2D {
+        scaled_extent(backbuffer, self.passes[pass].scale)
+    }
+
+    /// Size of an image owned by the graph, given the size of the backbuffer.
+    pub(crate) fn attachment_extent(
+        &self,
+        name: &str,
+        backbuffer: vk::Extent2D,
+    ) -> Result<vk::Extent2D> {
+        self.attachments
+            .iter()
+            .find(|(a, _)| a.name == name)
+            .map(|(a, _)| scaled_extent(backbuffer, a.scale))
+            .ok_or_else(|| anyhow!("Graph image not found: {}", name))
+    }
+
+    /// View of an image owned by the graph, once the targets are created.
+    pub(crate) fn image_view(&self, name: &str) -> Result<vk::ImageView> {
+        self.images
+            .get(name)
+            .map(|i| i.view)
+            .ok_or_else(|| anyhow!("Graph image not found: {}", name))
+    }
+
+    pub(crate) fn render_pass(&self, name: &str) -> Result<vk::RenderPass> {
+        self.passes
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.render_pass)
+            .ok_or_else(|| anyhow!("Render pass not found: {}", name))
+    }
+
+    pub(crate) fn framebuffer(&self, pass: usize, image_index: usize) -> vk::Framebuffer {
+        let framebuffers = &self.passes[pass].framebuffers;
+        framebuffers[image_index.min(framebuffers.len() - 1)]
+    }
+
+    pub(crate) unsafe fn begin_pass(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pass: usize,
+        image_index: usize,
+        backbuffer: vk::Extent2D,
+        contents: vk::SubpassContents,
+    ) {
+        let render_area = vk::Rect2D::builder()
+            .offset(vk::Offset2D::default())
+            .extent(self.pass_extent(pass, backbuffer));
+
+        let info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.passes[pass].render_pass)
+            .framebuffer(self.framebuffer(pass, image_index))
+            .render_area(render_area)
+            .clear_values(&self.passes[pass].clear_values);
+
+        device.cmd_begin_render_pass(command_buffer, &info, contents);
+    }
+
+    pub(crate) unsafe fn destroy_targets(&mut self, device: &Device, allocator: &Allocator) {
+        for pass in &mut self.passes {
+            pass.framebuffers
+                .drain(..)
+                .for_each(|f| device.destroy_framebuffer(f, None));
+        }
+        for (_, image) in self.images.drain() {
+            device.destroy_image_view(image.view, None);
+            device.destroy_image(image.image, None);
+            allocator.free(device, image.memory);
+        }
+    }
+
+    pub(crate) unsafe fn destroy(&mut self, device: &Device, allocator: &Allocator) {
+        self.destroy_targets(device, allocator);
+        for pass in self.passes.drain(..) {
+            device.destroy_render_pass(pass.render_pass, None);
+        }
+        self.attachments.clear();
+    }
+}
+
+/// Every use of every attachment, in pass order, the exports read after the last pass.
+fn attachment_uses(desc: &RenderGraphDesc) -> Result<HashMap<&'static str, Vec<(usize, Usage)>>> {
+    let attachments = desc
+        .attachments
+        .iter()
+        .map(|a| a.name)
+        .collect::<HashSet<_>>();
+
+    let mut uses: HashMap<&'static str, Vec<(usize, Usage)>> = HashMap::new();
+    for (index, pass) in desc.passes.iter().enumerate() {
+        let pass_uses = pass
+            .color
+            .iter()
+            .map(|n| (*n, Usage::Color))
+            .chain(pass.depth.iter().map(|n| (*n, Usage::Depth)))
+            .chain(pass.resolve.iter().map(|n| (*n, Usage::Resolve)))
+            .chain(pass.reads.iter().map(|n| (*n, Usage::Read)));
+
+        for (name, usage) in pass_uses {
+            if name != BACKBUFFER && !attachments.contains(name) {
+                return Err(anyhow!(
+                    "Pass {} uses undeclared attachment {}",
+                    pass.name,
+                    name
+                ));
+            }
+            if usage == Usage::Read {
+                let Some(earlier_uses) = uses.get(name) else {
+                    return Err(anyhow!(
+                        "Pass {} reads {} before any pass writes it",
+                        pass.name,
+                        name
+                    ));
+                };
+                // The writes of a pass come before its reads.
+                if earlier_uses
+                    .iter()
+                    .any(|(p, u)| *p == index && *u != Usage::Read)
+                {
+                    return Err(anyhow!(
+                        "Pass {} reads {} while writing it",
+                        pass.name,
+                        name
+                    ));
+                }
+            }
+            uses.entry(name).or_default().push((index, usage));
+        }
+
+        if !pass.resolve.is_empty() && pass.resolve.len() != pass.color.len() {
+            return Err(anyhow!(
+                "Pass {} must resolve every color attachment",
+                pass.name
+            ));
+        }
+    }
+
+    // Read as if by a pass after the last one.
+    for name in &desc.exports {
+        let Some(attachment_uses) = uses.get_mut(name) else {
+            return Err(anyhow!("Exported attachment {} is never written", name));
+        };
+        if *name == BACKBUFFER {
+            return Err(anyhow!("The backbuffer cannot be exported"));
+        }
+        attachment_uses.push((desc.passes.len(), Usage::Read));
+    }
+
+    Ok(uses)
+}
+
+/// Usage of an image owned by the graph, from its uses in pass order.
+fn image_usage(attachment_uses: &[(usize, Usage)]) -> vk::ImageUsageFlags {
+    let usage = attachment_uses
+        .iter()
+        .fold(vk::ImageUsageFlags::empty(), |u, (_, usage)| {
+            u | usage.image_usage()
+        });
+
+    // Never leaving the tile memory of a single pass, `create_image` backs it with lazily allocated
+    // memory. Transient images can only be attachments.
+    let first_pass = attachment_uses[0].0;
+    if attachment_uses.iter().all(|(pass, _)| *pass == first_pass)
+        && !usage.contains(vk::ImageUsageFlags::SAMPLED)
+    {
+        usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+    } else {
+        usage
+    }
+}
+
+fn scaled_extent(extent: vk::Extent2D, scale: f32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: ((extent.width as f32 * scale) as u32).max(1),
+        height: ((extent.height as f32 * scale) as u32).max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(name: &'static str, kind: AttachmentKind) -> AttachmentDesc {
+        AttachmentDesc {
+            name,
+            kind,
+            format: vk::Format::R8G8B8A8_UNORM,
+            samples: vk::SampleCountFlags::_1,
+            scale: 1.0,
+        }
+    }
+
+    /// A multisampled scene resolved to a target upscaled to the backbuffer, the depth read by
+    /// the culling pass.
+    fn scene_desc() -> RenderGraphDesc {
+        RenderGraphDesc {
+            attachments: vec![
+                attachment("color", AttachmentKind::Color),
+                attachment("target", AttachmentKind::Color),
+                attachment("depth", AttachmentKind::Depth),
+            ],
+            passes: vec![
+                PassDesc {
+                    name: "scene",
+                    color: vec!["color"],
+                    depth: Some("depth"),
+                    resolve: vec!["target"],
+                    ..Default::default()
+                },
+                PassDesc {
+                    name: "upscale",
+                    color: vec![BACKBUFFER],
+                    reads: vec!["target"],
+                    ..Default::default()
+                },
+            ],
+            exports: vec!["depth"],
+        }
+    }
+
+    #[test]
+    fn orders_uses_by_pass() {
+        let uses = attachment_uses(&scene_desc()).unwrap();
+
+        assert_eq!(uses["color"], vec![(0, Usage::Color)]);
+        assert_eq!(uses["target"], vec![(0, Usage::Resolve), (1, Usage::Read)]);
+        assert_eq!(uses[BACKBUFFER], vec![(1, Usage::Color)]);
+        // Exports are read after the last pass.
+        assert_eq!(uses["depth"], vec![(0, Usage::Depth), (2, Usage::Read)]);
+    }
+
+    #[test]
+    fn keeps_single_pass_attachments_transient() {
+        let uses = attachment_uses(&scene_desc()).unwrap();
+
+        assert_eq!(
+            image_usage(&uses["color"]),
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+        );
+        assert_eq!(
+            image_usage(&uses["target"]),
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+        );
+        assert_eq!(
+            image_usage(&uses["depth"]),
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+        );
+    }
+
+    #[test]
+    fn never_makes_sampled_images_transient() {
+        let usage = image_usage(&[(0, Usage::Color), (0, Usage::Read)]);
+
+        assert!(usage.contains(vk::ImageUsageFlags::SAMPLED));
+        assert!(!usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT));
+    }
+
+    #[test]
+    fn rejects_invalid_graphs() {
+        let mut read_before_write = scene_desc();
+        read_before_write.passes.swap(0, 1);
+
+        let mut read_while_writing = scene_desc();
+        read_while_writing.passes[0].reads.push("color");
+
+        let mut undeclared = scene_desc();
+        undeclared.passes[1].reads.push("normals");
+
+        let mut unresolved = scene_desc();
+        unresolved.passes[0].color.push(BACKBUFFER);
+
+        let mut exported_backbuffer = scene_desc();
+        exported_backbuffer.exports.push(BACKBUFFER);
+
+        for desc in [
+            read_before_write,
+            read_while_writing,
+            undeclared,
+            unresolved,
+            exported_backbuffer,
+        ] {
+            assert!(attachment_uses(&desc).is_err());
+        }
+    }
+}
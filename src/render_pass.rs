@@ -2,93 +2,109 @@ use anyhow::Result;
 
 use vulkanalia::prelude::v1_0::*;
 
-use crate::{app::AppData, depth_object::get_depth_format};
+use crate::{
+    app::AppData,
+    depth_object::get_depth_format,
+    depth_pyramid::{create_depth_pyramid, supports_depth_pyramid},
+    render_graph::{
+        AttachmentDesc, AttachmentKind, PassDesc, RenderGraph, RenderGraphDesc, BACKBUFFER,
+    },
+    upscale::update_upscale_descriptor_set,
+};
 
-pub(crate) unsafe fn create_render_pass(
+/// Draws the models, multisampled and resolved into the backbuffer or the scene target.
+pub(crate) const SCENE_PASS: &str = "scene";
+/// Stretches the scene target over the backbuffer, when the resolution is scaled.
+pub(crate) const UPSCALE_PASS: &str = "upscale";
+/// The scene at the resolution scale.
+pub(crate) const SCENE_TARGET: &str = "scene";
+/// Depth of the scene pass, kept for the depth pyramid when occlusion culling is supported.
+pub(crate) const DEPTH_TARGET: &str = "depth";
+
+/// Declares the passes of a frame and compiles them into `data.render_graph`.
+pub(crate) unsafe fn create_render_graph(
     instance: &Instance,
     device: &Device,
     data: &mut AppData,
 ) -> Result<()> {
-    let depth_stencil_attachment = vk::AttachmentDescription::builder()
-        .format(get_depth_format(instance, data)?)
-        .samples(data.msaa_samples)
-        .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::DONT_CARE)
-        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-        .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
-
-    let depth_stencil_attachment_ref = vk::AttachmentReference::builder()
-        .attachment(1)
-        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let scale = data.setting_resolution_scale;
+    let scaled = scale != 1.0;
+    let multisampled = data.msaa_samples != vk::SampleCountFlags::_1;
 
-    let color_attachment = vk::AttachmentDescription::builder()
-        .format(data.swapchain_format)
-        .samples(data.msaa_samples)
-        .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
-        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-        .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let mut desc = RenderGraphDesc::default();
 
-    let color_attachment_ref = vk::AttachmentReference::builder()
-        .attachment(0)
-        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let target = if scaled {
+        desc.attachments.push(AttachmentDesc {
+            name: SCENE_TARGET,
+            kind: AttachmentKind::Color,
+            format: data.swapchain_format,
+            samples: vk::SampleCountFlags::_1,
+            scale,
+        });
+        SCENE_TARGET
+    } else {
+        BACKBUFFER
+    };
 
-    let color_resolve_attachment = vk::AttachmentDescription::builder()
-        .format(data.swapchain_format)
-        .samples(vk::SampleCountFlags::_1)
-        .load_op(vk::AttachmentLoadOp::DONT_CARE)
-        .store_op(vk::AttachmentStoreOp::STORE)
-        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-        .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+    let mut scene = PassDesc {
+        name: SCENE_PASS,
+        depth: Some(DEPTH_TARGET),
+        clear_color: [0.0, 0.0, 0.0, 1.0],
+        ..Default::default()
+    };
+    if multisampled {
+        desc.attachments.push(AttachmentDesc {
+            name: "color",
+            kind: AttachmentKind::Color,
+            format: data.swapchain_format,
+            samples: data.msaa_samples,
+            scale,
+        });
+        scene.color = vec!["color"];
+        scene.resolve = vec![target];
+    } else {
+        scene.color = vec![target];
+    }
+    let depth_format = get_depth_format(instance, data)?;
+    desc.attachments.push(AttachmentDesc {
+        name: DEPTH_TARGET,
+        kind: AttachmentKind::Depth,
+        format: depth_format,
+        samples: data.msaa_samples,
+        scale,
+    });
+    desc.passes.push(scene);
 
-    let color_resolve_attachment_ref = vk::AttachmentReference::builder()
-        .attachment(2)
-        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    data.occlusion_culling = supports_depth_pyramid(instance, data, depth_format);
+    if data.occlusion_culling {
+        desc.exports.push(DEPTH_TARGET);
+    }
 
-    let color_attachments = &[color_attachment_ref];
-    let resolve_attachments = &[color_resolve_attachment_ref];
-    let subpass = vk::SubpassDescription::builder()
-        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .color_attachments(color_attachments)
-        .depth_stencil_attachment(&depth_stencil_attachment_ref)
-        .resolve_attachments(resolve_attachments);
+    if scaled {
+        desc.passes.push(PassDesc {
+            name: UPSCALE_PASS,
+            color: vec![BACKBUFFER],
+            reads: vec![SCENE_TARGET],
+            ..Default::default()
+        });
+    }
 
-    let dependency = vk::SubpassDependency::builder()
-        .src_subpass(vk::SUBPASS_EXTERNAL)
-        .dst_subpass(0)
-        .src_stage_mask(
-            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-        )
-        .src_access_mask(vk::AccessFlags::empty())
-        .dst_stage_mask(
-            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-        )
-        .dst_access_mask(
-            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-        );
-
-    let attachments = &[
-        color_attachment,
-        depth_stencil_attachment,
-        color_resolve_attachment,
-    ];
-    let subpasses = &[subpass];
-    let dependencies = &[dependency];
-    let info = vk::RenderPassCreateInfo::builder()
-        .attachments(attachments)
-        .subpasses(subpasses)
-        .dependencies(dependencies);
+    data.render_graph = RenderGraph::compile(&desc, device, data)?;
+    create_render_targets(instance, device, data)
+}
 
-    data.render_pass = device.create_render_pass(&info, None)?;
+/// Rebuilds the extent dependent part of the graph and the depth pyramid, the render passes of
+/// the graph are kept.
+pub(crate) unsafe fn create_render_targets(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let mut render_graph = std::mem::take(&mut data.render_graph);
+    let result = render_graph.create_targets(instance, device, data);
+    data.render_graph = render_graph;
+    result?;
 
-    Ok(())
+    update_upscale_descriptor_set(device, data)?;
+    create_depth_pyramid(instance, device, data)
 }
@@ -0,0 +1,212 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::app::{AppData, MAX_FRAMES_IN_FLIGHT};
+use crate::physical_device::PhysicalDeviceSelector;
+
+/// How the swapchain hands frames to the display. Unsupported modes fall back to the closest
+/// supported one, ending with [`PresentMode::Vsync`] which is always available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentMode {
+    /// Waits for the vertical blank, never tears.
+    Vsync,
+    /// Like vsync, but presents late frames right away, tearing instead of stuttering.
+    Adaptive,
+    /// Replaces the queued frame with the newest one, low latency without tearing.
+    #[default]
+    Mailbox,
+    /// Presents right away, tears. Useful to measure latency.
+    Immediate,
+}
+
+/// Renderer configuration, clamped to what the device supports when applied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RendererSettings {
+    /// Samples per pixel, `None` for the device maximum. 1 disables multisampling.
+    pub msaa_samples: Option<u32>,
+    /// Maximum anisotropic filtering level, 1.0 disables it.
+    pub anisotropy: f32,
+    pub sample_shading: bool,
+    pub present_mode: PresentMode,
+    /// Frames recorded ahead of the GPU, from 1 to 3.
+    pub frames_in_flight: usize,
+    /// Size of the rendered images relative to the window, upscaled when presented.
+    pub resolution_scale: f32,
+    /// Frames per second the CPU waits for before starting a frame, `None` for no limit.
+    pub frame_rate_limit: Option<u32>,
+    /// Device to render with, only read when the app is created.
+    pub physical_device: PhysicalDeviceSelector,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            msaa_samples: None,
+            anisotropy: 16.0,
+            sample_shading: true,
+            present_mode: PresentMode::default(),
+            frames_in_flight: 2,
+            resolution_scale: 1.0,
+            frame_rate_limit: None,
+            physical_device: PhysicalDeviceSelector::default(),
+        }
+    }
+}
+
+impl RendererSettings {
+    /// Reads settings from a TOML file, missing fields keep their default.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Like [`RendererSettings::load`], with the defaults when the file doesn't exist yet.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self> {
+        if path.as_ref().exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Resolves the settings against the device limits into the values the renderer uses.
+pub(crate) fn apply_settings(data: &mut AppData, settings: &RendererSettings) {
+    let max_samples = data.limit_max_msaa_samples.bits();
+    let samples = settings
+        .msaa_samples
+        .unwrap_or(max_samples)
+        .clamp(1, max_samples);
+    // Sample counts are powers of two, round down to the nearest one.
+    data.msaa_samples = vk::SampleCountFlags::from_bits_truncate(1 << samples.ilog2());
+
+    data.setting_anisotropy = data.feature_sampler_anisotropy && settings.anisotropy > 1.0;
+    data.setting_max_sampler_anisotropy = if data.setting_anisotropy {
+        settings
+            .anisotropy
+            .clamp(1.0, data.limit_max_sampler_anisotropy)
+    } else {
+        1.0
+    };
+
+    data.setting_sample_shading = data.feature_sample_rate_shading
+        && settings.sample_shading
+        && data.msaa_samples != vk::SampleCountFlags::_1;
+    data.setting_present_mode = settings.present_mode;
+    data.setting_frames_in_flight = settings.frames_in_flight.clamp(1, MAX_FRAMES_IN_FLIGHT);
+    data.setting_resolution_scale = if settings.resolution_scale.is_finite() {
+        settings.resolution_scale.clamp(0.25, 2.0)
+    } else {
+        1.0
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device_data() -> AppData {
+        AppData {
+            limit_max_msaa_samples: vk::SampleCountFlags::_8,
+            limit_max_sampler_anisotropy: 16.0,
+            feature_sampler_anisotropy: true,
+            feature_sample_rate_shading: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn clamps_to_the_device_limits() {
+        let mut data = device_data();
+        let settings = RendererSettings {
+            msaa_samples: Some(6),
+            anisotropy: 64.0,
+            frames_in_flight: 10,
+            resolution_scale: 8.0,
+            ..Default::default()
+        };
+
+        apply_settings(&mut data, &settings);
+
+        assert_eq!(data.msaa_samples, vk::SampleCountFlags::_4);
+        assert!(data.setting_anisotropy);
+        assert_eq!(data.setting_max_sampler_anisotropy, 16.0);
+        assert!(data.setting_sample_shading);
+        assert_eq!(data.setting_frames_in_flight, MAX_FRAMES_IN_FLIGHT);
+        assert_eq!(data.setting_resolution_scale, 2.0);
+    }
+
+    #[test]
+    fn disables_what_the_device_lacks() {
+        let mut data = AppData {
+            feature_sampler_anisotropy: false,
+            feature_sample_rate_shading: false,
+            ..device_data()
+        };
+        let settings = RendererSettings {
+            frames_in_flight: 0,
+            resolution_scale: f32::NAN,
+            ..Default::default()
+        };
+
+        apply_settings(&mut data, &settings);
+
+        assert_eq!(data.msaa_samples, vk::SampleCountFlags::_8);
+        assert!(!data.setting_anisotropy);
+        assert_eq!(data.setting_max_sampler_anisotropy, 1.0);
+        assert!(!data.setting_sample_shading);
+        assert_eq!(data.setting_frames_in_flight, 1);
+        assert_eq!(data.setting_resolution_scale, 1.0);
+    }
+
+    #[test]
+    fn shades_samples_only_when_multisampling() {
+        let mut data = device_data();
+        let settings = RendererSettings {
+            msaa_samples: Some(1),
+            ..Default::default()
+        };
+
+        apply_settings(&mut data, &settings);
+
+        assert_eq!(data.msaa_samples, vk::SampleCountFlags::_1);
+        assert!(!data.setting_sample_shading);
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let settings = RendererSettings {
+            msaa_samples: Some(4),
+            present_mode: PresentMode::Immediate,
+            frame_rate_limit: Some(144),
+            physical_device: PhysicalDeviceSelector::Name("Radeon".to_owned()),
+            ..Default::default()
+        };
+
+        let text = toml::to_string_pretty(&settings).unwrap();
+
+        assert_eq!(toml::from_str::<RendererSettings>(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn keeps_the_default_of_missing_fields() {
+        let settings: RendererSettings = toml::from_str("anisotropy = 4.0").unwrap();
+
+        assert_eq!(
+            settings,
+            RendererSettings {
+                anisotropy: 4.0,
+                ..Default::default()
+            }
+        );
+    }
+}
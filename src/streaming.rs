@@ -0,0 +1,189 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+use anyhow::{anyhow, Result};
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    app::AppData,
+    deletion_queue::DeletionQueue,
+    handle::Handle,
+    model::{self, Model, SerializedModel},
+    physical_device::QueueFamilyIndices,
+    texture::Texture,
+    upload::{
+        create_streaming_upload_context, destroy_upload_context, finish_uploads, take_releases,
+        Release,
+    },
+};
+
+/// An asset requested from the streaming thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AssetHandle {
+    Model(Handle<Model>),
+    Texture(Handle<Texture>),
+}
+
+#[derive(Debug)]
+struct StreamRequest {
+    asset: AssetHandle,
+    path: PathBuf,
+}
+
+/// What the streaming thread did with an asset.
+pub(crate) enum Streamed {
+    /// Taken off the queue, being read.
+    Started(AssetHandle),
+    /// Uploaded on the transfer queue, the graphics queue must acquire the releases before use.
+    Texture(Handle<Texture>, Texture, Vec<Release>),
+    /// Only decoded, the graphics queue uploads it. Always the case for models, their geometry
+    /// lives in the arena the graphics queue owns.
+    DecodedModel(Handle<Model>, SerializedModel),
+    DecodedTexture(Handle<Texture>, Vec<u8>, u32, u32),
+    Failed(AssetHandle, anyhow::Error),
+}
+
+/// Decodes and uploads assets on a background thread, so the render loop never waits for files
+/// or transfers.
+#[derive(Debug, Default)]
+pub(crate) struct Streamer {
+    requests: Option<Sender<StreamRequest>>,
+    results: Option<Receiver<Streamed>>,
+    thread: Option<JoinHandle<()>>,
+    /// Family of the transfer queue, `None` when assets are only decoded in the background.
+    pub(crate) transfer_family: Option<u32>,
+}
+
+impl Streamer {
+    pub(crate) unsafe fn start(
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+    ) -> Result<Self> {
+        let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+
+        // Just what creating resources and looking up queue families reads.
+        let mut worker = AppData {
+            headless: data.headless,
+            surface: data.surface,
+            physical_device: data.physical_device,
+            allocator: data.allocator.clone(),
+            transfer_queue: data.transfer_queue,
+            vfs: data.vfs.clone(),
+            ..Default::default()
+        };
+        if indices.transfer.is_some() {
+            create_streaming_upload_context(instance, device, &mut worker)?;
+        }
+
+        let (request_sender, request_receiver) = mpsc::channel();
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        let instance = instance.clone();
+        let device = device.clone();
+        let thread = std::thread::Builder::new()
+            .name("yuumi-streaming".to_owned())
+            .spawn(move || run(instance, device, worker, request_receiver, result_sender))?;
+
+        Ok(Self {
+            requests: Some(request_sender),
+            results: Some(result_receiver),
+            thread: Some(thread),
+            transfer_family: indices.transfer.map(|(family, _)| family),
+        })
+    }
+
+    pub(crate) fn request(&self, asset: AssetHandle, path: &Path) -> Result<()> {
+        let request = StreamRequest {
+            asset,
+            path: path.to_owned(),
+        };
+        self.requests
+            .as_ref()
+            .ok_or_else(|| anyhow!("Streaming thread stopped"))?
+            .send(request)
+            .map_err(|_| anyhow!("Streaming thread stopped"))
+    }
+
+    /// The next finished asset, if any.
+    pub(crate) fn try_recv(&self) -> Option<Streamed> {
+        self.results.as_ref()?.try_recv().ok()
+    }
+
+    /// Waits for the requests in progress and stops the thread. Finished assets not received
+    /// yet are retired.
+    pub(crate) fn stop(&mut self, deletion_queue: &mut DeletionQueue) {
+        self.requests = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        while let Some(streamed) = self.try_recv() {
+            if let Streamed::Texture(_, texture, _) = streamed {
+                texture.retire(deletion_queue);
+            }
+        }
+        self.results = None;
+    }
+}
+
+fn run(
+    instance: Instance,
+    device: Device,
+    mut data: AppData,
+    requests: Receiver<StreamRequest>,
+    results: Sender<Streamed>,
+) {
+    // Ends once the app drops the sender.
+    for request in requests {
+        if results.send(Streamed::Started(request.asset)).is_err() {
+            break;
+        }
+
+        let streamed =
+            unsafe { stream(&request, &instance, &device, &mut data) }.unwrap_or_else(|e| {
+                // Whatever the failed asset released is never acquired.
+                take_releases(&mut data);
+                Streamed::Failed(request.asset, e)
+            });
+
+        if results.send(streamed).is_err() {
+            break;
+        }
+    }
+
+    if data.upload.streaming {
+        unsafe {
+            let _ = finish_uploads(&device, &mut data);
+            destroy_upload_context(&device, &mut data);
+        }
+    }
+}
+
+unsafe fn stream(
+    request: &StreamRequest,
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<Streamed> {
+    match request.asset {
+        AssetHandle::Model(handle) => Ok(Streamed::DecodedModel(
+            handle,
+            model::read_model(&data.vfs, &request.path)?,
+        )),
+        AssetHandle::Texture(handle) => {
+            let (pixels, width, height) = Texture::read(&data.vfs, &request.path)?;
+            if !data.upload.streaming {
+                return Ok(Streamed::DecodedTexture(handle, pixels, width, height));
+            }
+
+            let texture = Texture::from_pixels(&pixels, width, height, instance, device, data)?;
+            finish_uploads(device, data)?;
+            Ok(Streamed::Texture(handle, texture, take_releases(data)))
+        }
+    }
+}
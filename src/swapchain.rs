@@ -3,10 +3,11 @@ use anyhow::Result;
 use winit::window::Window;
 
 use vulkanalia::prelude::v1_0::*;
-use vulkanalia::vk::{KhrSurfaceExtension, KhrSwapchainExtension};
+use vulkanalia::vk::{Handle, KhrSurfaceExtension, KhrSwapchainExtension};
 
 use crate::app::AppData;
 use crate::physical_device::QueueFamilyIndices;
+use crate::settings::PresentMode;
 
 #[derive(Clone, Debug)]
 pub(crate) struct SwapchainSupport {
@@ -43,12 +44,27 @@ fn get_swapchain_surface_format(formats: &[vk::SurfaceFormatKHR]) -> vk::Surface
         .unwrap_or_else(|| formats[0])
 }
 
-fn get_swapchain_present_mode(present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
-    present_modes
+fn get_swapchain_present_mode(
+    present_modes: &[vk::PresentModeKHR],
+    setting: PresentMode,
+) -> vk::PresentModeKHR {
+    // Closest first, FIFO is always supported.
+    let preferred: &[vk::PresentModeKHR] = match setting {
+        PresentMode::Vsync => &[],
+        PresentMode::Adaptive => &[vk::PresentModeKHR::FIFO_RELAXED],
+        PresentMode::Mailbox => &[vk::PresentModeKHR::MAILBOX],
+        PresentMode::Immediate => &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX],
+    };
+
+    let present_mode = preferred
         .iter()
         .cloned()
-        .find(|m| *m == vk::PresentModeKHR::MAILBOX)
-        .unwrap_or(vk::PresentModeKHR::FIFO)
+        .find(|m| present_modes.contains(m))
+        .unwrap_or(vk::PresentModeKHR::FIFO);
+
+    log::info!("Present mode {:?} for {:?}.", present_mode, setting);
+
+    present_mode
 }
 
 fn get_swapchain_extent(window: &Window, capabilities: vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
@@ -82,7 +98,8 @@ pub(crate) unsafe fn create_swapchain(
     let support = SwapchainSupport::get(instance, data, data.physical_device)?;
 
     let surface_format = get_swapchain_surface_format(&support.formats);
-    let present_mode = get_swapchain_present_mode(&support.present_modes);
+    let present_mode =
+        get_swapchain_present_mode(&support.present_modes, data.setting_present_mode);
     let extent = get_swapchain_extent(window, support.capabilities);
 
     let mut image_count = support.capabilities.min_image_count + 1;
@@ -115,12 +132,19 @@ pub(crate) unsafe fn create_swapchain(
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(present_mode)
         .clipped(true)
-        .old_swapchain(vk::SwapchainKHR::null());
+        .old_swapchain(data.swapchain);
+
+    let old_swapchain = data.swapchain;
 
     data.swapchain_format = surface_format.format;
     data.swapchain_extent = extent;
     data.swapchain = device.create_swapchain_khr(&info, None)?;
     data.swapchain_images = device.get_swapchain_images_khr(data.swapchain)?;
 
+    // Retired by the creation above, its presented images stay valid until then.
+    if !old_swapchain.is_null() {
+        device.destroy_swapchain_khr(old_swapchain, None);
+    }
+
     Ok(())
 }
@@ -0,0 +1,9 @@
+use std::path::PathBuf;
+
+/// An empty directory of its own for a test, under the system temporary directory.
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yuumi-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Failed to create test directory");
+    dir
+}
@@ -1,17 +1,23 @@
+use crate::allocator::Allocation;
 use crate::app::AppData;
+use crate::asset_cache::{read_cache, write_cache, CacheHeader};
+use crate::deletion_queue::{DeletionQueue, Retired};
 use crate::image_view::create_image_view;
 use crate::texture_image::create_texture_image;
+use crate::vfs::{Asset, Vfs};
 use anyhow::{anyhow, Result};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use vulkanalia::prelude::v1_0::*;
 use vulkanalia::{Device, Instance};
 
+/// A texture loaded by an [`App`](crate::App), only ever seen through a [`Handle`](crate::Handle).
 #[derive(Debug)]
-pub(crate) struct Texture {
+pub struct Texture {
     pub(crate) image: vk::Image,
     pub(crate) image_view: vk::ImageView,
-    pub(crate) image_memory: vk::DeviceMemory,
+    pub(crate) image_memory: Allocation,
     pub(crate) _width: u32,
     pub(crate) _height: u32,
     pub(crate) _mip_levels: u32,
@@ -28,40 +34,54 @@ pub(crate) struct SerializedTexture {
 
 impl Texture {
     pub(crate) fn load(
-        name: &str,
+        path: &Path,
         instance: &mut Instance,
         device: &mut Device,
         data: &mut AppData,
     ) -> Result<Texture> {
-        let supported_extensions = vec!["bin", "png"];
-
-        let extension = supported_extensions
-            .iter()
-            .find_map(|extension| {
-                let path = format!("assets/textures/{}.{}", name, extension);
-                if Path::new(&path).exists() {
-                    Some(extension)
-                } else {
-                    None
-                }
-            })
-            .ok_or(anyhow!("no supported texture found"))?;
-
-        if *extension != "bin" {
-            let path = format!("assets/textures/{}.{}", name, extension);
-            let (pixels, width, height) = match extension.as_ref() {
-                "png" => load_suboptimal_png(&path)?,
-                _ => Err(anyhow!("unsupported file extension: {}", extension))?,
-            };
-            save_optimal(&name, pixels, width, height)?;
+        let (pixels, width, height) = Self::read(&data.vfs, path)?;
+        Self::from_pixels(&pixels, width, height, instance, device, data)
+    }
+
+    /// Decodes a texture file into RGBA8 pixels, converting and caching it as `.bin` first if
+    /// the cache is missing or stale. Touches no GPU state, the streaming thread calls it.
+    pub(crate) fn read(vfs: &Vfs, name: &Path) -> Result<(Vec<u8>, u32, u32)> {
+        let (name, path, cache) = match vfs.resolve(name, TEXTURE_EXTENSIONS)? {
+            Asset::Source { name, path, cache } => (name, path, cache),
+            Asset::Cache { name } => return load_optimal(vfs, &name, None),
+        };
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+
+        let header = CacheHeader::for_source(&name, &path, importer_settings(extension))?;
+        match load_optimal(vfs, &cache, Some(&header)) {
+            Ok(texture) => return Ok(texture),
+            Err(error) if vfs.exists(&cache) => info!("Rebuilding {}: {}", cache, error),
+            Err(_) => {}
         }
 
-        let path = format!("assets/textures/{}.bin", name);
-        let (pixels, width, height) = load_optimal(&path)?;
-        let size = pixels.len() as u64;
+        let serialized = import_texture(&path)?;
+        // Without a cache mount to write to, it is converted again the next time.
+        if let Err(error) = write_cache(vfs, &cache, &header, &serialized) {
+            warn!("Not caching {}: {}", cache, error);
+        }
+
+        Ok((serialized.pixels, serialized.width, serialized.height))
+    }
 
+    /// Uploads tightly packed RGBA8 pixels.
+    pub(crate) fn from_pixels(
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        instance: &Instance,
+        device: &Device,
+        data: &mut AppData,
+    ) -> Result<Texture> {
         let (image, image_memory, mip_levels) =
-            unsafe { create_texture_image(instance, device, data, &pixels, size, width, height)? };
+            unsafe { create_texture_image(instance, device, data, pixels, width, height)? };
 
         // OPTIMIZE reuse image views
         let format = vk::Format::R8G8B8A8_SRGB;
@@ -79,38 +99,74 @@ impl Texture {
             _format: vk::Format::R8G8B8A8_SRGB,
         })
     }
+
+    /// Hands the image and its view over to the deletion queue.
+    pub(crate) fn retire(self, deletion_queue: &mut DeletionQueue) {
+        deletion_queue.push(Retired::ImageView(self.image_view));
+        deletion_queue.push(Retired::Image(self.image, self.image_memory));
+    }
 }
 
-fn load_suboptimal_png(path: &str) -> Result<(Vec<u8>, u32, u32)> {
-    let image = std::fs::File::open(path)?;
+/// Source files textures are imported from.
+pub(crate) const TEXTURE_EXTENSIONS: &[&str] = &["png"];
+
+/// Converts a source file into the texture its cache holds.
+pub(crate) fn import_texture(path: &Path) -> Result<SerializedTexture> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+
+    let (pixels, width, height) = match extension {
+        "png" => load_suboptimal_png(path)?,
+        _ => Err(anyhow!("unsupported file extension: {}", extension))?,
+    };
+    Ok(SerializedTexture {
+        width,
+        height,
+        pixels,
+    })
+}
 
-    let decoder = png::Decoder::new(image);
-    let mut reader = decoder.read_info()?;
+/// Written to the cache header, a cache imported with other settings is rebuilt.
+pub(crate) fn importer_settings(extension: &str) -> String {
+    format!("{} rgba8", extension)
+}
 
-    let mut pixels = vec![0; reader.info().raw_bytes()];
-    reader.next_frame(&mut pixels)?;
+/// Decodes a `.png` of any color type into RGBA8 pixels.
+pub(crate) fn load_suboptimal_png(path: &Path) -> Result<(Vec<u8>, u32, u32)> {
+    let image = std::fs::File::open(path)?;
 
-    let (width, height) = reader.info().size();
+    let mut decoder = png::Decoder::new(image);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
 
-    Ok((pixels, width, height))
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+    pixels.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => pixels,
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+        png::ColorType::Indexed => Err(anyhow!("palette not expanded: {}", path.display()))?,
+    };
+
+    Ok((pixels, info.width, info.height))
 }
 
-fn load_optimal(path: &str) -> Result<(Vec<u8>, u32, u32)> {
-    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
-    let serialized: SerializedTexture = bincode::deserialize_from(&mut reader)?;
+fn load_optimal(
+    vfs: &Vfs,
+    name: &str,
+    expected: Option<&CacheHeader>,
+) -> Result<(Vec<u8>, u32, u32)> {
+    let serialized: SerializedTexture = read_cache(vfs, name, expected)?;
     Ok((serialized.pixels, serialized.width, serialized.height))
 }
-
-fn save_optimal(name: &str, pixels: Vec<u8>, width: u32, height: u32) -> Result<()> {
-    let path = format!("assets/textures/{}.bin", name);
-    let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
-    bincode::serialize_into(
-        &mut writer,
-        &SerializedTexture {
-            width,
-            height,
-            pixels,
-        },
-    )?;
-    Ok(())
-}
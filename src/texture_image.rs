@@ -1,15 +1,12 @@
 use anyhow::{anyhow, Result};
 
-use vulkanalia::{
-    prelude::v1_0::*,
-    vk::{DeviceMemory, Image},
-};
+use vulkanalia::{prelude::v1_0::*, vk::Image};
 
 use crate::{
+    allocator::Allocation,
     app::AppData,
     generate_mipmaps::generate_mipmaps,
-    single_time_cmd::{begin_single_time_commands, end_single_time_commands},
-    vertex_buffer::create_buffer,
+    upload::{release_to_graphics, stage, upload_command_buffer, Release},
     vertex_buffer::get_memory_type_index,
 };
 
@@ -18,27 +15,13 @@ pub(crate) unsafe fn create_texture_image(
     instance: &Instance,
     device: &Device,
     data: &mut AppData,
-    pixels: &Vec<u8>,
-    size: u64,
+    pixels: &[u8],
     width: u32,
     height: u32,
-) -> Result<(Image, DeviceMemory, MipLevels)> {
+) -> Result<(Image, Allocation, MipLevels)> {
     let mip_levels = (width.max(height) as f32).log2().floor() as u32 + 1;
 
-    let (staging_buffer, staging_buffer_memory) = create_buffer(
-        instance,
-        device,
-        data,
-        size,
-        vk::BufferUsageFlags::TRANSFER_SRC,
-        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-    )?;
-
-    let memory = device.map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
-
-    std::ptr::copy_nonoverlapping(pixels.as_ptr(), memory.cast(), pixels.len());
-
-    device.unmap_memory(staging_buffer_memory);
+    let (staging_buffer, staging_offset) = stage(instance, device, data, pixels)?;
 
     let (image, image_memory) = create_image(
         instance,
@@ -56,9 +39,12 @@ pub(crate) unsafe fn create_texture_image(
         vk::MemoryPropertyFlags::DEVICE_LOCAL,
     )?;
 
+    // The whole upload is recorded into the current batch, nothing waits for the GPU.
+    let command_buffer = upload_command_buffer(device, data)?;
+
     transition_image_layout(
         device,
-        data,
+        command_buffer,
         image,
         vk::Format::R8G8B8A8_SRGB,
         vk::ImageLayout::UNDEFINED,
@@ -66,21 +52,30 @@ pub(crate) unsafe fn create_texture_image(
         mip_levels,
     )?;
 
-    copy_buffer_to_image(device, data, staging_buffer, image, width, height)?;
-
-    generate_mipmaps(
-        instance,
+    copy_buffer_to_image(
         device,
-        data,
+        command_buffer,
+        staging_buffer,
+        staging_offset,
         image,
-        vk::Format::R8G8B8A8_SRGB,
+        width,
+        height,
+    );
+
+    let release = Release {
+        image,
+        format: vk::Format::R8G8B8A8_SRGB,
         width,
         height,
         mip_levels,
-    )?;
+    };
 
-    device.destroy_buffer(staging_buffer, None);
-    device.free_memory(staging_buffer_memory, None);
+    // Blits need a graphics queue, streamed textures get their mipmaps once acquired by it.
+    if data.upload.streaming {
+        release_to_graphics(device, data, release)?;
+    } else {
+        generate_mipmaps(instance, device, data, command_buffer, release)?;
+    }
 
     Ok((image, image_memory, mip_levels))
 }
@@ -97,7 +92,7 @@ pub(crate) unsafe fn create_image(
     tiling: vk::ImageTiling,
     usage: vk::ImageUsageFlags,
     properties: vk::MemoryPropertyFlags,
-) -> Result<(vk::Image, vk::DeviceMemory)> {
+) -> Result<(vk::Image, Allocation)> {
     let info = vk::ImageCreateInfo::builder()
         .image_type(vk::ImageType::_2D)
         .extent(vk::Extent3D {
@@ -118,25 +113,28 @@ pub(crate) unsafe fn create_image(
 
     let requirements = device.get_image_memory_requirements(image);
 
-    let info = vk::MemoryAllocateInfo::builder()
-        .allocation_size(requirements.size)
-        .memory_type_index(get_memory_type_index(
-            instance,
-            data,
-            properties,
-            requirements,
-        )?);
-
-    let image_memory = device.allocate_memory(&info, None)?;
+    // Transient attachments are backed by lazily allocated memory where the device has some, only
+    // committed if the attachment ever leaves the tile memory.
+    let memory_type = if usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+        let lazy = properties | vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
+        get_memory_type_index(instance, data, lazy, requirements)
+            .or_else(|_| get_memory_type_index(instance, data, properties, requirements))?
+    } else {
+        get_memory_type_index(instance, data, properties, requirements)?
+    };
+    let linear = tiling == vk::ImageTiling::LINEAR;
+    let image_memory = data
+        .allocator
+        .allocate(device, requirements, memory_type, linear)?;
 
-    device.bind_image_memory(image, image_memory, 0)?;
+    device.bind_image_memory(image, image_memory.memory, image_memory.offset)?;
 
     Ok((image, image_memory))
 }
 
 pub(crate) unsafe fn transition_image_layout(
     device: &Device,
-    data: &AppData,
+    command_buffer: vk::CommandBuffer,
     image: vk::Image,
     format: vk::Format,
     old_layout: vk::ImageLayout,
@@ -178,8 +176,6 @@ pub(crate) unsafe fn transition_image_layout(
             _ => return Err(anyhow!("Unsupported image layout transition!")),
         };
 
-    let command_buffer = begin_single_time_commands(device, data)?;
-
     let subresource = vk::ImageSubresourceRange::builder()
         .aspect_mask(aspect_mask)
         .base_mip_level(0)
@@ -207,21 +203,18 @@ pub(crate) unsafe fn transition_image_layout(
         &[barrier],
     );
 
-    end_single_time_commands(device, data, command_buffer)?;
-
     Ok(())
 }
 
 unsafe fn copy_buffer_to_image(
     device: &Device,
-    data: &AppData,
+    command_buffer: vk::CommandBuffer,
     buffer: vk::Buffer,
+    buffer_offset: vk::DeviceSize,
     image: vk::Image,
     width: u32,
     height: u32,
-) -> Result<()> {
-    let command_buffer = begin_single_time_commands(device, data)?;
-
+) {
     let subresource = vk::ImageSubresourceLayers::builder()
         .aspect_mask(vk::ImageAspectFlags::COLOR)
         .mip_level(0)
@@ -229,7 +222,7 @@ unsafe fn copy_buffer_to_image(
         .layer_count(1);
 
     let region = vk::BufferImageCopy::builder()
-        .buffer_offset(0)
+        .buffer_offset(buffer_offset)
         .buffer_row_length(0)
         .buffer_image_height(0)
         .image_subresource(subresource)
@@ -247,8 +240,4 @@ unsafe fn copy_buffer_to_image(
         vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         &[region],
     );
-
-    end_single_time_commands(device, data, command_buffer)?;
-
-    Ok(())
 }
@@ -1,29 +1,67 @@
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 use vulkanalia::{prelude::v1_0::*, vk::Sampler};
 
 use crate::app::AppData;
 
+/// How a texture is filtered when magnified or minified.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SamplerFilter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+/// How texture coordinates outside of `[0, 1]` are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SamplerAddressMode {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SamplerSettings {
+    pub filter: SamplerFilter,
+    pub address_mode: SamplerAddressMode,
+}
+
 pub(crate) unsafe fn create_texture_sampler(
     device: &Device,
     data: &mut AppData,
-    mip_levels: &u32,
+    settings: &SamplerSettings,
 ) -> Result<Sampler> {
+    let filter = match settings.filter {
+        SamplerFilter::Nearest => vk::Filter::NEAREST,
+        SamplerFilter::Linear => vk::Filter::LINEAR,
+    };
+    let mipmap_mode = match settings.filter {
+        SamplerFilter::Nearest => vk::SamplerMipmapMode::NEAREST,
+        SamplerFilter::Linear => vk::SamplerMipmapMode::LINEAR,
+    };
+    let address_mode = match settings.address_mode {
+        SamplerAddressMode::Repeat => vk::SamplerAddressMode::REPEAT,
+        SamplerAddressMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        SamplerAddressMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+    };
+
     let info = vk::SamplerCreateInfo::builder()
-        .mag_filter(vk::Filter::LINEAR)
-        .min_filter(vk::Filter::LINEAR)
-        .address_mode_u(vk::SamplerAddressMode::REPEAT)
-        .address_mode_v(vk::SamplerAddressMode::REPEAT)
-        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .mag_filter(filter)
+        .min_filter(filter)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
         .anisotropy_enable(data.setting_anisotropy)
         .max_anisotropy(data.setting_max_sampler_anisotropy)
         .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
         .unnormalized_coordinates(false)
         .compare_enable(false)
         .compare_op(vk::CompareOp::ALWAYS)
-        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .mipmap_mode(mipmap_mode)
         .min_lod(0.0) // Optional
-        .max_lod(*mip_levels as f32)
+        .max_lod(vk::LOD_CLAMP_NONE) // Every mip level of whatever texture is sampled
         .mip_lod_bias(0.0); // Optional
 
     Ok(device.create_sampler(&info, None)?)
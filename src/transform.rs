@@ -0,0 +1,36 @@
+use cgmath::{point3, vec3, EuclideanSpace, Matrix4, One, Quaternion, Vector3};
+
+use crate::types::PositionXYZ;
+
+/// Placement of an object in the world, applied as scale, then rotation, then translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub position: PositionXYZ,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn from_position(position: PositionXYZ) -> Self {
+        Self {
+            position,
+            ..Self::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position.to_vec())
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            position: point3(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Result};
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    allocator::Allocation, app::AppData, generate_mipmaps::generate_mipmaps,
+    physical_device::QueueFamilyIndices, vertex_buffer::create_buffer,
+};
+
+/// Initial size of the staging ring, grown when a single upload doesn't fit.
+const STAGING_SIZE: vk::DeviceSize = 16 * 1024 * 1024;
+/// Satisfies the texel alignment of every format copied to images.
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+/// Records uploads into one command buffer, submitted as a single batch before the next frame
+/// or when the staging ring is full. Staged data stays in the ring until the batch reading it
+/// signals the fence.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UploadContext {
+    queue: vk::Queue,
+    queue_family: u32,
+    graphics_family: u32,
+    /// Runs on the transfer queue of the streaming thread. Resources are released to the
+    /// graphics queue, which generates the mipmaps.
+    pub(crate) streaming: bool,
+    /// Resources released since the last call to `take_releases`.
+    releases: Vec<Release>,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    /// Commands were recorded since the last submission.
+    recording: bool,
+    /// A submitted batch hasn't been waited for.
+    pending: bool,
+    staging_buffer: vk::Buffer,
+    staging_memory: Allocation,
+    staging_size: vk::DeviceSize,
+    /// Positions in the ring only ever grow, the offset in the buffer is modulo its size.
+    head: vk::DeviceSize,
+    /// Oldest byte still read by the GPU or by the batch being recorded.
+    tail: vk::DeviceSize,
+    /// First byte of the batch being recorded.
+    batch_start: vk::DeviceSize,
+}
+
+/// An image written on the transfer queue, handed over to the graphics queue once the
+/// transfer completed. Only the base level is written, every level waits in
+/// `TRANSFER_DST_OPTIMAL` for [`generate_mipmaps`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Release {
+    pub(crate) image: vk::Image,
+    pub(crate) format: vk::Format,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) mip_levels: u32,
+}
+
+/// The upload context of the graphics queue, used by the main thread.
+pub(crate) unsafe fn create_upload_context(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    let queue = data.graphics_queue;
+    init_upload_context(instance, device, data, indices.graphics, queue, false)
+}
+
+/// The upload context of the transfer queue, used by the streaming thread. The device must have
+/// a transfer queue.
+pub(crate) unsafe fn create_streaming_upload_context(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    let (family, _) = indices
+        .transfer
+        .ok_or_else(|| anyhow!("Missing transfer queue."))?;
+    let queue = data.transfer_queue;
+    init_upload_context(instance, device, data, family, queue, true)
+}
+
+unsafe fn init_upload_context(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    queue_family: u32,
+    queue: vk::Queue,
+    streaming: bool,
+) -> Result<()> {
+    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    data.upload.queue = queue;
+    data.upload.queue_family = queue_family;
+    data.upload.graphics_family = indices.graphics;
+    data.upload.streaming = streaming;
+
+    let info = vk::CommandPoolCreateInfo::builder()
+        .flags(
+            vk::CommandPoolCreateFlags::TRANSIENT
+                | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+        )
+        .queue_family_index(queue_family);
+    data.upload.command_pool = device.create_command_pool(&info, None)?;
+
+    let info = vk::CommandBufferAllocateInfo::builder()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_pool(data.upload.command_pool)
+        .command_buffer_count(1);
+    data.upload.command_buffer = device.allocate_command_buffers(&info)?[0];
+
+    data.upload.fence = device.create_fence(&vk::FenceCreateInfo::builder(), None)?;
+
+    create_staging_buffer(instance, device, data, STAGING_SIZE)
+}
+
+unsafe fn create_staging_buffer(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    size: vk::DeviceSize,
+) -> Result<()> {
+    let (buffer, memory) = create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+
+    let upload = &mut data.upload;
+    upload.staging_buffer = buffer;
+    upload.staging_memory = memory;
+    upload.staging_size = size;
+    upload.head = 0;
+    upload.tail = 0;
+    upload.batch_start = 0;
+
+    Ok(())
+}
+
+impl UploadContext {
+    /// Reserves `size` bytes of the ring, returns their offset in the staging buffer.
+    fn reserve(&mut self, size: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        if self.head == self.tail {
+            // Empty, start over at the beginning of the buffer.
+            self.head = self.head.next_multiple_of(self.staging_size);
+            self.tail = self.head;
+            self.batch_start = self.head;
+        }
+
+        let mut start = self.head.next_multiple_of(STAGING_ALIGNMENT);
+        // An upload never wraps around the end of the buffer.
+        if start % self.staging_size + size > self.staging_size {
+            start = start.next_multiple_of(self.staging_size);
+        }
+
+        if start + size - self.tail > self.staging_size {
+            return None;
+        }
+
+        self.head = start + size;
+        Some(start % self.staging_size)
+    }
+}
+
+/// Copies `values` into the staging ring, returns the staging buffer and the offset of the
+/// copy. Must be called before recording the commands reading it, it may submit the batch.
+pub(crate) unsafe fn stage<T: Copy>(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    values: &[T],
+) -> Result<(vk::Buffer, vk::DeviceSize)> {
+    let size = std::mem::size_of_val(values) as vk::DeviceSize;
+
+    let mut offset = data.upload.reserve(size);
+    if offset.is_none() && data.upload.pending {
+        wait_uploads(device, data)?;
+        offset = data.upload.reserve(size);
+    }
+    if offset.is_none() && data.upload.recording {
+        finish_uploads(device, data)?;
+        offset = data.upload.reserve(size);
+    }
+    let offset = match offset {
+        Some(offset) => offset,
+        None => {
+            // Nothing uses the ring anymore, replace it with one large enough.
+            device.destroy_buffer(data.upload.staging_buffer, None);
+            data.allocator.free(device, data.upload.staging_memory);
+            let staging_size = size.next_power_of_two().max(STAGING_SIZE);
+            create_staging_buffer(instance, device, data, staging_size)?;
+            data.upload
+                .reserve(size)
+                .expect("Staging ring fits the upload")
+        }
+    };
+
+    std::ptr::copy_nonoverlapping(
+        values.as_ptr() as *const u8,
+        data.upload
+            .staging_memory
+            .mapped_ptr::<u8>()
+            .add(offset as usize),
+        size as usize,
+    );
+
+    Ok((data.upload.staging_buffer, offset))
+}
+
+/// The command buffer of the current batch, recording.
+pub(crate) unsafe fn upload_command_buffer(
+    device: &Device,
+    data: &mut AppData,
+) -> Result<vk::CommandBuffer> {
+    if !data.upload.recording {
+        // The command buffer and the fence are reused, the previous batch must be done.
+        if data.upload.pending {
+            wait_uploads(device, data)?;
+        }
+
+        let info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device.begin_command_buffer(data.upload.command_buffer, &info)?;
+
+        data.upload.recording = true;
+    }
+
+    Ok(data.upload.command_buffer)
+}
+
+/// Submits the recorded uploads without waiting. On the graphics queue, commands submitted after
+/// them see their results.
+pub(crate) unsafe fn flush_uploads(device: &Device, data: &mut AppData) -> Result<()> {
+    if !data.upload.recording {
+        return Ok(());
+    }
+
+    let upload = &mut data.upload;
+
+    // Images are transitioned by their own barriers, buffers only need their writes visible.
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                | vk::AccessFlags::INDEX_READ
+                | vk::AccessFlags::SHADER_READ,
+        );
+    device.cmd_pipeline_barrier(
+        upload.command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::VERTEX_INPUT
+            | vk::PipelineStageFlags::VERTEX_SHADER
+            | vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::DependencyFlags::empty(),
+        &[barrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+
+    device.end_command_buffer(upload.command_buffer)?;
+
+    let command_buffers = &[upload.command_buffer];
+    let info = vk::SubmitInfo::builder().command_buffers(command_buffers);
+    device.queue_submit(upload.queue, &[info], upload.fence)?;
+
+    upload.recording = false;
+    upload.pending = true;
+    upload.batch_start = upload.head;
+
+    Ok(())
+}
+
+/// Waits for the submitted batch, its staging space is reused afterwards.
+pub(crate) unsafe fn wait_uploads(device: &Device, data: &mut AppData) -> Result<()> {
+    let upload = &mut data.upload;
+    if !upload.pending {
+        return Ok(());
+    }
+
+    device.wait_for_fences(&[upload.fence], true, u64::MAX)?;
+    device.reset_fences(&[upload.fence])?;
+    device.reset_command_buffer(upload.command_buffer, vk::CommandBufferResetFlags::empty())?;
+
+    upload.pending = false;
+    upload.tail = upload.batch_start;
+
+    Ok(())
+}
+
+/// Releases an image written by the current batch to the graphics queue, see
+/// [`acquire_released`].
+pub(crate) unsafe fn release_to_graphics(
+    device: &Device,
+    data: &mut AppData,
+    release: Release,
+) -> Result<()> {
+    let command_buffer = upload_command_buffer(device, data)?;
+    let upload = &mut data.upload;
+    upload.releases.push(release);
+
+    // Within a family, the fence wait of the streaming thread already orders the queues.
+    if upload.queue_family == upload.graphics_family {
+        return Ok(());
+    }
+
+    let barrier = ownership_barrier(
+        upload.queue_family,
+        upload.graphics_family,
+        release,
+        vk::AccessFlags::TRANSFER_WRITE,
+        vk::AccessFlags::empty(),
+    );
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+
+    Ok(())
+}
+
+/// The images released since the last call, once their batch completed.
+pub(crate) fn take_releases(data: &mut AppData) -> Vec<Release> {
+    std::mem::take(&mut data.upload.releases)
+}
+
+/// Acquires images released from `transfer_family` on the graphics queue, and generates their
+/// mipmaps.
+pub(crate) unsafe fn acquire_released(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    transfer_family: u32,
+    releases: &[Release],
+) -> Result<()> {
+    let command_buffer = upload_command_buffer(device, data)?;
+    let graphics_family = data.upload.graphics_family;
+
+    for release in releases {
+        let barrier = ownership_barrier(
+            transfer_family,
+            graphics_family,
+            *release,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE,
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier],
+        );
+
+        generate_mipmaps(instance, device, data, command_buffer, *release)?;
+    }
+
+    Ok(())
+}
+
+/// Either half of a queue family ownership transfer from the transfer to the graphics family,
+/// a plain barrier within a family.
+fn ownership_barrier(
+    transfer_family: u32,
+    graphics_family: u32,
+    release: Release,
+    src_access_mask: vk::AccessFlags,
+    dst_access_mask: vk::AccessFlags,
+) -> vk::ImageMemoryBarrier {
+    let (src_queue_family_index, dst_queue_family_index) = if transfer_family == graphics_family {
+        (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+    } else {
+        (transfer_family, graphics_family)
+    };
+
+    let subresource = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(release.mip_levels)
+        .base_array_layer(0)
+        .layer_count(1);
+    vk::ImageMemoryBarrier::builder()
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .src_queue_family_index(src_queue_family_index)
+        .dst_queue_family_index(dst_queue_family_index)
+        .image(release.image)
+        .subresource_range(subresource)
+        .build()
+}
+
+/// Submits the recorded uploads and waits for them, before destroying what they write.
+pub(crate) unsafe fn finish_uploads(device: &Device, data: &mut AppData) -> Result<()> {
+    flush_uploads(device, data)?;
+    wait_uploads(device, data)
+}
+
+pub(crate) unsafe fn destroy_upload_context(device: &Device, data: &mut AppData) {
+    device.destroy_buffer(data.upload.staging_buffer, None);
+    data.allocator.free(device, data.upload.staging_memory);
+    device.destroy_fence(data.upload.fence, None);
+    device.destroy_command_pool(data.upload.command_pool, None);
+}
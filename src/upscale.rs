@@ -0,0 +1,157 @@
+use anyhow::Result;
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::{
+    app::AppData,
+    descriptor_pool::{allocate_material_descriptor_set, write_material_descriptor_set},
+    render_pass::{SCENE_TARGET, UPSCALE_PASS},
+    shader::create_shader_module,
+};
+
+/// Stretches the scene rendered at the resolution scale over the backbuffer. Only built while
+/// the render graph has the upscale pass.
+pub(crate) unsafe fn create_upscale_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    if !data.render_graph.has_pass(UPSCALE_PASS) {
+        return Ok(());
+    }
+
+    // Same bindings as a material: a texture and its sampler.
+    let set_layouts = &[data.material_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.upscale_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let vert_shader_module = create_shader_module(device, &data.vfs, "upscale.vert".to_owned())?;
+    let frag_shader_module = create_shader_module(device, &data.vfs, "upscale.frag".to_owned())?;
+
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::_1);
+
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(attachments);
+
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state)
+        .layout(data.upscale_pipeline_layout)
+        .render_pass(data.render_graph.render_pass(UPSCALE_PASS)?)
+        .subpass(0);
+
+    data.upscale_pipeline = device
+        .create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)?
+        .0;
+
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+
+    Ok(())
+}
+
+/// Creates the sampler and descriptor set reading the scene, kept for the app's lifetime.
+pub(crate) unsafe fn create_upscale_descriptor_set(
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+
+    data.upscale_sampler = device.create_sampler(&info, None)?;
+    data.upscale_descriptor_set = allocate_material_descriptor_set(device, data)?;
+
+    Ok(())
+}
+
+/// Points the descriptor set at the scene target, whenever the graph targets are recreated.
+pub(crate) unsafe fn update_upscale_descriptor_set(device: &Device, data: &AppData) -> Result<()> {
+    if !data.render_graph.has_pass(UPSCALE_PASS) {
+        return Ok(());
+    }
+
+    write_material_descriptor_set(
+        device,
+        data.upscale_descriptor_set,
+        data.render_graph.image_view(SCENE_TARGET)?,
+        data.upscale_sampler,
+    );
+
+    Ok(())
+}
+
+pub(crate) unsafe fn record_upscale_pass(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    data: &AppData,
+) {
+    device.cmd_bind_pipeline(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        data.upscale_pipeline,
+    );
+
+    let viewport = vk::Viewport::builder()
+        .width(data.swapchain_extent.width as f32)
+        .height(data.swapchain_extent.height as f32)
+        .max_depth(1.0);
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+
+    let scissor = vk::Rect2D::builder().extent(data.swapchain_extent);
+    device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+
+    device.cmd_bind_descriptor_sets(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        data.upscale_pipeline_layout,
+        0,
+        &[data.upscale_descriptor_set],
+        &[],
+    );
+
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+}
@@ -9,13 +9,96 @@ use vulkanalia::prelude::v1_0::*;
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct Vertex {
     pub(crate) pos: cgmath::Vector3<f32>,
-    pub(crate) color: cgmath::Vector3<f32>,
+    /// Multiplies the base color, white when the file has none.
+    pub(crate) color: cgmath::Vector4<f32>,
     pub(crate) tex_coord: cgmath::Vector2<f32>,
+    pub(crate) normal: cgmath::Vector3<f32>,
+    /// The handedness of the bitangent in `w`.
+    pub(crate) tangent: cgmath::Vector4<f32>,
+    pub(crate) tex_coord_1: cgmath::Vector2<f32>,
+}
+
+impl Vertex {
+    /// A vertex with nothing but a position, the other attributes are filled in when present.
+    pub(crate) fn at(pos: cgmath::Vector3<f32>) -> Self {
+        Self {
+            pos,
+            color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+            tex_coord: cgmath::vec2(0.0, 0.0),
+            normal: cgmath::vec3(0.0, 0.0, 0.0),
+            tangent: cgmath::vec4(0.0, 0.0, 0.0, 1.0),
+            tex_coord_1: cgmath::vec2(0.0, 0.0),
+        }
+    }
+
+    fn components(&self) -> [f32; 18] {
+        let (pos, color, tex_coord) = (self.pos, self.color, self.tex_coord);
+        let (normal, tangent, tex_coord_1) = (self.normal, self.tangent, self.tex_coord_1);
+        [
+            pos.x,
+            pos.y,
+            pos.z,
+            color.x,
+            color.y,
+            color.z,
+            color.w,
+            tex_coord.x,
+            tex_coord.y,
+            normal.x,
+            normal.y,
+            normal.z,
+            tangent.x,
+            tangent.y,
+            tangent.z,
+            tangent.w,
+            tex_coord_1.x,
+            tex_coord_1.y,
+        ]
+    }
+
+    pub(crate) fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(std::mem::size_of::<Vertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    pub(crate) fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        let vec2_size = std::mem::size_of::<cgmath::Vector2<f32>>() as u32;
+        let vec3_size = std::mem::size_of::<cgmath::Vector3<f32>>() as u32;
+        let vec4_size = std::mem::size_of::<cgmath::Vector4<f32>>() as u32;
+
+        let attributes = [
+            (vk::Format::R32G32B32_SFLOAT, vec3_size),
+            (vk::Format::R32G32B32A32_SFLOAT, vec4_size),
+            (vk::Format::R32G32_SFLOAT, vec2_size),
+            (vk::Format::R32G32B32_SFLOAT, vec3_size),
+            (vk::Format::R32G32B32A32_SFLOAT, vec4_size),
+            (vk::Format::R32G32_SFLOAT, vec2_size),
+        ];
+
+        let mut offset = 0;
+        attributes
+            .iter()
+            .enumerate()
+            .map(|(location, (format, size))| {
+                let attribute = vk::VertexInputAttributeDescription::builder()
+                    .binding(0)
+                    .location(location as u32)
+                    .format(*format)
+                    .offset(offset)
+                    .build();
+                offset += size;
+                attribute
+            })
+            .collect()
+    }
 }
 
 impl PartialEq for Vertex {
     fn eq(&self, other: &Self) -> bool {
-        self.pos == other.pos && self.color == other.color && self.tex_coord == other.tex_coord
+        self.components() == other.components()
     }
 }
 
@@ -23,65 +106,100 @@ impl Eq for Vertex {}
 
 impl Hash for Vertex {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.pos[0].to_bits().hash(state);
-        self.pos[1].to_bits().hash(state);
-        self.pos[2].to_bits().hash(state);
-        self.color[0].to_bits().hash(state);
-        self.color[1].to_bits().hash(state);
-        self.color[2].to_bits().hash(state);
-        self.tex_coord[0].to_bits().hash(state);
-        self.tex_coord[1].to_bits().hash(state);
+        self.components()
+            .iter()
+            .for_each(|component| component.to_bits().hash(state));
     }
 }
 
-impl Vertex {
+/// The joints moving a vertex of a skinned mesh and how much each of them does.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct VertexSkin {
+    /// Indices into the joints of the model's skeleton.
+    pub(crate) joints: [u32; 4],
+    pub(crate) weights: [f32; 4],
+}
+
+impl PartialEq for VertexSkin {
+    fn eq(&self, other: &Self) -> bool {
+        self.joints == other.joints
+            && self.weights.map(f32::to_bits) == other.weights.map(f32::to_bits)
+    }
+}
+
+impl Eq for VertexSkin {}
+
+impl Hash for VertexSkin {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.joints.hash(state);
+        self.weights.map(f32::to_bits).hash(state);
+    }
+}
+
+/// A vertex of a skinned mesh, drawn with the skinned pipeline. The attributes of [`Vertex`]
+/// keep their locations.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SkinnedVertex {
+    pub(crate) vertex: Vertex,
+    pub(crate) skin: VertexSkin,
+}
+
+impl SkinnedVertex {
     pub(crate) fn binding_description() -> vk::VertexInputBindingDescription {
         vk::VertexInputBindingDescription::builder()
             .binding(0)
-            .stride(std::mem::size_of::<Vertex>() as u32)
+            .stride(std::mem::size_of::<SkinnedVertex>() as u32)
             .input_rate(vk::VertexInputRate::VERTEX)
             .build()
     }
 
     pub(crate) fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
-        let pos_size = std::mem::size_of::<cgmath::Vector3<f32>>();
-        let pos = vk::VertexInputAttributeDescription::builder()
-            .binding(0)
-            .location(0)
-            .format(vk::Format::R32G32B32_SFLOAT)
-            .offset(0)
-            .build();
+        let vertex_size = std::mem::size_of::<Vertex>() as u32;
 
-        let color_size = std::mem::size_of::<cgmath::Vector3<f32>>();
-        let color = vk::VertexInputAttributeDescription::builder()
+        let joints = vk::VertexInputAttributeDescription::builder()
             .binding(0)
-            .location(1)
-            .format(vk::Format::R32G32B32_SFLOAT)
-            .offset(pos_size as u32)
+            .location(11)
+            .format(vk::Format::R32G32B32A32_UINT)
+            .offset(vertex_size)
             .build();
 
-        let _tex_coord_size = std::mem::size_of::<cgmath::Vector2<f32>>();
-        let tex_coord = vk::VertexInputAttributeDescription::builder()
+        let weights = vk::VertexInputAttributeDescription::builder()
             .binding(0)
-            .location(2)
-            .format(vk::Format::R32G32_SFLOAT)
-            .offset((pos_size + color_size) as u32)
+            .location(12)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset(vertex_size + 16)
             .build();
 
-        vec![pos, color, tex_coord]
+        let mut attributes = Vertex::attribute_descriptions();
+        attributes.extend([joints, weights]);
+        attributes
     }
 }
 
 #[repr(C)]
-pub struct InstanceData {
-    pub model_matrix: Vec<cgmath::Matrix4<f32>>,
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct InstanceData {
+    pub(crate) model_matrix: cgmath::Matrix4<f32>,
+}
+
+/// An instance as drawn, copied once per draw of its model. Laid out as std430 since the
+/// culling pass reads and writes it too.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct DrawInstance {
+    pub(crate) model_matrix: cgmath::Matrix4<f32>,
+    /// Index of the draw data in the storage buffer.
+    pub(crate) draw_index: u32,
+    pub(crate) _padding: [u32; 3],
 }
 
-impl InstanceData {
+impl DrawInstance {
     pub(crate) fn binding_description() -> vk::VertexInputBindingDescription {
         vk::VertexInputBindingDescription::builder()
             .binding(1)
-            .stride(std::mem::size_of::<cgmath::Matrix4<f32>>() as u32)
+            .stride(std::mem::size_of::<DrawInstance>() as u32)
             .input_rate(vk::VertexInputRate::INSTANCE)
             .build()
     }
@@ -89,37 +207,45 @@ impl InstanceData {
     pub(crate) fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
         let instance_transform0 = vk::VertexInputAttributeDescription::builder()
             .binding(1)
-            .location(3)
+            .location(6)
             .format(vk::Format::R32G32B32A32_SFLOAT)
             .offset(0)
             .build();
 
         let instance_transform1 = vk::VertexInputAttributeDescription::builder()
             .binding(1)
-            .location(4)
+            .location(7)
             .format(vk::Format::R32G32B32A32_SFLOAT)
             .offset(16)
             .build();
 
         let instance_transform2 = vk::VertexInputAttributeDescription::builder()
             .binding(1)
-            .location(5)
+            .location(8)
             .format(vk::Format::R32G32B32A32_SFLOAT)
             .offset(32)
             .build();
 
         let instance_transform3 = vk::VertexInputAttributeDescription::builder()
             .binding(1)
-            .location(6)
+            .location(9)
             .format(vk::Format::R32G32B32A32_SFLOAT)
             .offset(48)
             .build();
 
+        let draw_index = vk::VertexInputAttributeDescription::builder()
+            .binding(1)
+            .location(10)
+            .format(vk::Format::R32_UINT)
+            .offset(64)
+            .build();
+
         vec![
             instance_transform0,
             instance_transform1,
             instance_transform2,
             instance_transform3,
+            draw_index,
         ]
     }
 }
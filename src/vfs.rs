@@ -0,0 +1,356 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::pack::Pack;
+
+/// Where the assets, their caches and the shaders are found, by names like `models/cube.glb`.
+///
+/// Each mount serves the names under its mount point, `""` serves every name. Mounts are
+/// searched from the last one added to the first, so a later mount overrides an earlier one.
+#[derive(Clone, Debug)]
+pub struct Vfs {
+    mounts: Vec<Mount>,
+}
+
+#[derive(Clone, Debug)]
+struct Mount {
+    point: String,
+    backing: Backing,
+}
+
+impl Mount {
+    /// `name` relative to the mount point, `None` when the mount doesn't serve it.
+    fn relative<'a>(&self, name: &'a str) -> Option<&'a str> {
+        match self.point.as_str() {
+            "" => Some(name),
+            point => name.strip_prefix(point)?.strip_prefix('/'),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Backing {
+    /// Source files, only read.
+    Source(PathBuf),
+    /// Where the converted `.bin` caches are written.
+    Cache(PathBuf),
+    Pack(Arc<Pack>),
+}
+
+impl Default for Vfs {
+    /// The layout of the repository, run from its root: the sources and their caches in
+    /// `assets`, the compiled shaders in `lib`, or those of the build script with the
+    /// `compile-shaders` feature.
+    fn default() -> Self {
+        let mut vfs = Self::empty();
+        vfs.mount_source("", "assets")
+            .mount_cache("", "assets")
+            .mount_source("shaders", "lib");
+        #[cfg(feature = "compile-shaders")]
+        vfs.mount_source("shaders", env!("OUT_DIR"));
+        vfs
+    }
+}
+
+impl Vfs {
+    /// Without any mount, nothing can be loaded.
+    pub fn empty() -> Self {
+        Self { mounts: Vec::new() }
+    }
+
+    /// Serves the source files of a directory, e.g. `.glb` and `.png`. They are never written to.
+    pub fn mount_source(&mut self, point: &str, dir: impl Into<PathBuf>) -> &mut Self {
+        self.mount(point, Backing::Source(dir.into()))
+    }
+
+    /// Serves the `.bin` caches of a directory and writes the ones converted from the sources
+    /// to it. The directory is created when the first cache is written.
+    pub fn mount_cache(&mut self, point: &str, dir: impl Into<PathBuf>) -> &mut Self {
+        self.mount(point, Backing::Cache(dir.into()))
+    }
+
+    /// Serves the files of a pack written by `yuumi-cook`, read-only. The pack stays mapped in
+    /// memory while mounted.
+    pub fn mount_pack(&mut self, point: &str, path: impl AsRef<Path>) -> Result<&mut Self> {
+        let pack = Pack::open(path.as_ref())?;
+        Ok(self.mount(point, Backing::Pack(Arc::new(pack))))
+    }
+
+    fn mount(&mut self, point: &str, backing: Backing) -> &mut Self {
+        self.mounts.push(Mount {
+            point: point.trim_matches('/').to_owned(),
+            backing,
+        });
+        self
+    }
+
+    /// The mounts serving `name`, searched first, with the name relative to their mount point.
+    fn serving<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a Backing, &'a str)> {
+        self.mounts
+            .iter()
+            .rev()
+            .filter_map(move |mount| Some((&mount.backing, mount.relative(name)?)))
+    }
+
+    pub(crate) fn exists(&self, name: &str) -> bool {
+        self.serving(name).any(|(backing, relative)| match backing {
+            Backing::Source(dir) | Backing::Cache(dir) => dir.join(relative).is_file(),
+            Backing::Pack(pack) => pack.contains(relative),
+        })
+    }
+
+    /// Reads a file from the first mount that has it, borrowed from a pack.
+    pub(crate) fn read(&self, name: &str) -> Result<Cow<'_, [u8]>> {
+        // Not `serving`, a pack's files outlive the name.
+        for mount in self.mounts.iter().rev() {
+            let Some(relative) = mount.relative(name) else {
+                continue;
+            };
+            match &mount.backing {
+                Backing::Source(dir) | Backing::Cache(dir) => {
+                    let path = dir.join(relative);
+                    if path.is_file() {
+                        return Ok(Cow::Owned(std::fs::read(path)?));
+                    }
+                }
+                Backing::Pack(pack) => {
+                    if pack.contains(relative) {
+                        return Ok(Cow::Borrowed(pack.read(relative)?));
+                    }
+                }
+            }
+        }
+
+        Err(anyhow!("{} not found in any mount", name))
+    }
+
+    /// Writes a file to the first cache mount serving it.
+    pub(crate) fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let path = self
+            .serving(name)
+            .find_map(|(backing, relative)| match backing {
+                Backing::Cache(dir) => Some(dir.join(relative)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("no cache mount for {}", name))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Where the source file of `name` is on disk, for the importers reading the files next to
+    /// it.
+    pub(crate) fn source_path(&self, name: &str) -> Option<PathBuf> {
+        self.serving(name)
+            .find_map(|(backing, relative)| match backing {
+                Backing::Source(dir) => Some(dir.join(relative)).filter(|path| path.is_file()),
+                _ => None,
+            })
+    }
+
+    /// Finds the asset to load for a name.
+    ///
+    /// A cache name, e.g. `models/cube.glb.bin`, is looked up as the source it caches. A name
+    /// without extension is tried with every supported source extension in order. The cache
+    /// itself is only resolved to when none of the sources exist, a source decides whether its
+    /// cache is up to date.
+    pub(crate) fn resolve(&self, name: &Path, source_extensions: &[&str]) -> Result<Asset> {
+        let name = match name.extension().and_then(|e| e.to_str()) {
+            Some("bin") => name.with_extension(""),
+            _ => name.to_owned(),
+        };
+        let candidates = match name.extension().and_then(|e| e.to_str()) {
+            Some(extension) if source_extensions.contains(&extension) => vec![normalize(&name)],
+            None => source_extensions
+                .iter()
+                .map(|extension| normalize(&name.with_extension(extension)))
+                .collect(),
+            Some(extension) => {
+                return Err(anyhow!(
+                    "unsupported file extension: {} ({})",
+                    extension,
+                    name.display()
+                ))
+            }
+        };
+
+        for candidate in &candidates {
+            if let Some(path) = self.source_path(candidate) {
+                return Ok(Asset::Source {
+                    cache: cache_name(candidate),
+                    name: candidate.clone(),
+                    path,
+                });
+            }
+        }
+
+        if let Some(cache) = candidates
+            .iter()
+            .map(|candidate| cache_name(candidate))
+            .find(|cache| self.exists(cache))
+        {
+            return Ok(Asset::Cache { name: cache });
+        }
+
+        Err(anyhow!("no supported asset found at {}", name.display()))
+    }
+}
+
+/// An asset found by [`Vfs::resolve`].
+pub(crate) enum Asset {
+    /// A source file, converted unless its cache is up to date.
+    Source {
+        name: String,
+        path: PathBuf,
+        cache: String,
+    },
+    /// A cache without its source, nothing tells whether it is up to date.
+    Cache { name: String },
+}
+
+/// Names use `/` whatever the platform.
+pub(crate) fn normalize(name: &Path) -> String {
+    name.to_string_lossy()
+        .replace('\\', "/")
+        .trim_start_matches("./")
+        .to_owned()
+}
+
+/// `models/cube.glb` is cached as `models/cube.glb.bin`, the extension keeps the caches of
+/// `models/cube.gltf` and `models/cube.obj` apart.
+pub(crate) fn cache_name(name: &str) -> String {
+    format!("{}.bin", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_dir;
+
+    fn write(path: PathBuf, bytes: &[u8]) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn mount_points_serve_the_names_under_them() {
+        let mount = |point: &str| Mount {
+            point: point.to_owned(),
+            backing: Backing::Source(PathBuf::new()),
+        };
+
+        assert_eq!(
+            mount("").relative("models/cube.glb"),
+            Some("models/cube.glb")
+        );
+        assert_eq!(
+            mount("models").relative("models/cube.glb"),
+            Some("cube.glb")
+        );
+        assert_eq!(mount("models").relative("models2/cube.glb"), None);
+        assert_eq!(mount("models").relative("textures/cube.png"), None);
+    }
+
+    #[test]
+    fn later_mounts_override_earlier_ones() {
+        let dir = test_dir("vfs-override");
+        write(dir.join("base/models/a.txt"), b"base a");
+        write(dir.join("base/models/b.txt"), b"base b");
+        write(dir.join("patch/a.txt"), b"patch a");
+
+        let mut vfs = Vfs::empty();
+        vfs.mount_source("", dir.join("base"))
+            .mount_source("models", dir.join("patch"));
+
+        assert_eq!(vfs.read("models/a.txt").unwrap().as_ref(), b"patch a");
+        assert_eq!(vfs.read("models/b.txt").unwrap().as_ref(), b"base b");
+        assert!(vfs.exists("models/a.txt"));
+        assert!(!vfs.exists("models/c.txt"));
+        assert!(vfs.read("models/c.txt").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_to_the_cache_mount() {
+        let dir = test_dir("vfs-write");
+        let mut vfs = Vfs::empty();
+        vfs.mount_source("", dir.join("sources"));
+        assert!(vfs.write("models/cube.bin", b"cache").is_err());
+
+        vfs.mount_cache("", dir.join("caches"));
+        vfs.write("models/cube.bin", b"cache").unwrap();
+
+        assert_eq!(
+            std::fs::read(dir.join("caches/models/cube.bin")).unwrap(),
+            b"cache"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_sources_before_caches() {
+        let dir = test_dir("vfs-resolve");
+        write(dir.join("models/cube.glb"), b"source");
+        write(dir.join("models/cube.glb.bin"), b"cache");
+        write(dir.join("models/cube.gltf.bin"), b"cache");
+        let mut vfs = Vfs::empty();
+        vfs.mount_source("", &dir).mount_cache("", &dir);
+
+        for name in [
+            "models/cube",
+            "models/cube.bin",
+            "models/cube.glb",
+            "models/cube.glb.bin",
+        ] {
+            match vfs.resolve(Path::new(name), &["gltf", "glb"]).unwrap() {
+                Asset::Source { name, path, cache } => {
+                    assert_eq!(name, "models/cube.glb");
+                    assert_eq!(path, dir.join("models/cube.glb"));
+                    assert_eq!(cache, "models/cube.glb.bin");
+                }
+                Asset::Cache { .. } => panic!("resolved {} to its cache", name),
+            }
+        }
+
+        std::fs::remove_file(dir.join("models/cube.glb")).unwrap();
+        match vfs
+            .resolve(Path::new("models/cube"), &["gltf", "glb"])
+            .unwrap()
+        {
+            Asset::Cache { name } => assert_eq!(name, "models/cube.gltf.bin"),
+            Asset::Source { .. } => panic!("resolved a removed source"),
+        }
+        match vfs
+            .resolve(Path::new("models/cube.glb.bin"), &["gltf", "glb"])
+            .unwrap()
+        {
+            Asset::Cache { name } => assert_eq!(name, "models/cube.glb.bin"),
+            Asset::Source { .. } => panic!("resolved a removed source"),
+        }
+        assert!(vfs.resolve(Path::new("models/cube.png"), &["glb"]).is_err());
+        assert!(vfs
+            .resolve(Path::new("models/cube.png.bin"), &["glb"])
+            .is_err());
+        assert!(vfs.resolve(Path::new("models/sphere"), &["glb"]).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn names_caches_after_their_source() {
+        assert_eq!(
+            normalize(Path::new("./models\\cube.glb")),
+            "models/cube.glb"
+        );
+        assert_eq!(cache_name("models/cube.glb"), "models/cube.glb.bin");
+        assert_eq!(cache_name("models/cube.obj"), "models/cube.obj.bin");
+    }
+}